@@ -0,0 +1,100 @@
+use chrono::{DateTime, Utc};
+use erfurt::candle::Candles;
+use thiserror::Error;
+
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum CandleError {
+    #[error("{field} has {len} values, expected {expected}")]
+    Length {
+        field: &'static str,
+        len: usize,
+        expected: usize,
+    },
+    #[error("time at {index} isn't after the previous one")]
+    Unordered { index: usize },
+    #[error("{field} at {index} is {value}")]
+    InvalidValue {
+        field: &'static str,
+        index: usize,
+        value: f64,
+    },
+}
+
+pub trait ValidateExt {
+    /// Checks the candles are well formed: every series as long as `time`, times strictly
+    /// increasing and no NaN, infinite or negative prices or volumes.
+    fn validate(&self) -> Result<(), CandleError>;
+}
+
+impl ValidateExt for Candles {
+    fn validate(&self) -> Result<(), CandleError> {
+        let expected = self.time.len();
+        let mut series = vec![
+            ("open", self.open.as_slice()),
+            ("high", self.high.as_slice()),
+            ("low", self.low.as_slice()),
+            ("close", self.close.as_slice()),
+        ];
+        if let Some(volume) = self.volume.as_deref() {
+            series.push(("volume", volume));
+        }
+        for (field, xs) in series {
+            if xs.len() != expected {
+                return Err(CandleError::Length {
+                    field,
+                    len: xs.len(),
+                    expected,
+                });
+            }
+            non_negative(field, xs)?;
+        }
+        increasing(&self.time)
+    }
+}
+
+fn increasing(time: &[DateTime<Utc>]) -> Result<(), CandleError> {
+    match time.windows(2).position(|w| w[1] <= w[0]) {
+        Some(i) => Err(CandleError::Unordered { index: i + 1 }),
+        None => Ok(()),
+    }
+}
+
+fn non_negative(field: &'static str, xs: &[f64]) -> Result<(), CandleError> {
+    match xs.iter().position(|x| !x.is_finite() || *x < 0.0) {
+        Some(index) => Err(CandleError::InvalidValue {
+            field,
+            index,
+            value: xs[index],
+        }),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn unordered_time() {
+        let time = [1, 2, 2]
+            .map(|d| Utc.with_ymd_and_hms(2024, 1, d, 0, 0, 0).unwrap())
+            .to_vec();
+        assert_eq!(increasing(&time[..2]), Ok(()));
+        assert_eq!(increasing(&time), Err(CandleError::Unordered { index: 2 }));
+    }
+
+    #[test]
+    fn invalid_value() {
+        assert_eq!(non_negative("close", &[1.0, 0.0, 2.5]), Ok(()));
+        assert!(matches!(
+            non_negative("close", &[1.0, f64::NAN]),
+            Err(CandleError::InvalidValue { index: 1, .. })
+        ));
+        assert!(matches!(
+            non_negative("low", &[-1.0]),
+            Err(CandleError::InvalidValue { index: 0, .. })
+        ));
+    }
+}
@@ -1,23 +1,32 @@
-use std::net::{Ipv4Addr, SocketAddrV4};
+use std::{
+    net::{Ipv4Addr, SocketAddrV4},
+    path::PathBuf,
+};
 
 use anyhow::Result;
 use async_trait::async_trait;
 use chrono::NaiveDate;
 use clap::{ArgGroup, Parser, Subcommand};
-use degiro_rs::util::ProductCategory;
+use degiro_rs::util::{Currency, Period, ProductCategory};
 use master_of_puppets::{master_of_puppets::MasterOfPuppets, puppet::PuppetBuilder};
 use tokio::signal;
 use tracing::{error, info, warn};
 
 use crate::{
-    portfolio::RiskMode,
+    indicators::annualized_return::AnnualizedReturnMode,
+    period::PeriodExt,
+    portfolio::{ReturnKind, RiskMode},
     puppet::{
-        db::{Db, ProductQuery},
-        degiro::Degiro,
-        portfolio::Calculator,
+        db::{Db, ProductQuery, DEFAULT_INTERVAL, DEFAULT_MAP_SIZE},
+        degiro::{
+            Degiro, Heartbeat, ProductType, TransactionSide, DEFAULT_REQUESTS_PER_SECOND,
+            HEARTBEAT_INTERVAL,
+        },
+        portfolio::{Calculator, DrawdownMetric, StopLossMethod},
         settings::Settings,
     },
     server::{self, ClientBuilder, Response},
+    table::{Precision, TableStyle},
     App,
 };
 
@@ -25,6 +34,18 @@ use crate::{
 pub struct Cli {
     #[clap(short, long, default_value = "9123")]
     port: u16,
+    /// Directory holding the config and the database, the working directory by default.
+    #[clap(long, global = true, env = "VOGELSANG_DATA_DIR")]
+    data_dir: Option<PathBuf>,
+    /// How tables are rendered: utf8, ascii, markdown or none for tab separated values.
+    #[clap(long, global = true, default_value = "utf8")]
+    table_style: TableStyle,
+    /// Decimal places of money and prices in tables.
+    #[clap(long, global = true, default_value = "2")]
+    precision: usize,
+    /// Decimal places of allocations and ratios in tables, `--precision` when not given.
+    #[clap(long, global = true)]
+    ratio_precision: Option<usize>,
     #[clap(subcommand)]
     command: Option<Commands>,
 }
@@ -35,7 +56,10 @@ pub enum Commands {
     FetchData {
         id: Option<String>,
     },
-    #[clap(group(ArgGroup::new("product_query").required(true).args(&["id", "symbol", "name"])))]
+    FetchStatus,
+    /// Session, configured assets, database entries and last fetch of the running server.
+    Status,
+    #[clap(group(ArgGroup::new("product_query").required(true).args(&["id", "symbol", "name", "isin"])))]
     GetProduct {
         #[clap(long, group = "product_query")]
         id: Option<String>,
@@ -43,8 +67,15 @@ pub enum Commands {
         symbol: Option<String>,
         #[clap(long, group = "product_query")]
         name: Option<String>,
+        #[clap(long, group = "product_query")]
+        isin: Option<String>,
+    },
+    SearchProducts {
+        query: String,
+        #[clap(long, default_value = "10")]
+        limit: usize,
     },
-    #[clap(group(ArgGroup::new("product_query").required(true).args(&["id", "symbol", "name"])))]
+    #[clap(group(ArgGroup::new("product_query").required(true).args(&["id", "symbol", "name", "isin"])))]
     GetCandles {
         #[clap(long, group = "product_query")]
         id: Option<String>,
@@ -52,8 +83,10 @@ pub enum Commands {
         symbol: Option<String>,
         #[clap(long, group = "product_query")]
         name: Option<String>,
+        #[clap(long, group = "product_query")]
+        isin: Option<String>,
     },
-    #[clap(group(ArgGroup::new("product_query").required(true).args(&["id", "symbol", "name"])))]
+    #[clap(group(ArgGroup::new("product_query").required(true).args(&["id", "symbol", "name", "isin"])))]
     GetFinancials {
         #[clap(long, group = "product_query")]
         id: Option<String>,
@@ -61,9 +94,16 @@ pub enum Commands {
         symbol: Option<String>,
         #[clap(long, group = "product_query")]
         name: Option<String>,
+        #[clap(long, group = "product_query")]
+        isin: Option<String>,
     },
-    GetPortfolio,
-    #[clap(group(ArgGroup::new("product_query").required(true).args(&["id", "symbol", "name"])))]
+    GetPortfolio {
+        /// Date the annual reports are picked as of, e.g. 2024-03-31, today by default.
+        #[clap(long)]
+        as_of: Option<NaiveDate>,
+    },
+    Correlations,
+    #[clap(group(ArgGroup::new("product_query").required(true).args(&["id", "symbol", "name", "isin"])))]
     GetSingleAllocation {
         #[clap(long, group = "product_query")]
         id: Option<String>,
@@ -71,24 +111,51 @@ pub enum Commands {
         symbol: Option<String>,
         #[clap(long, group = "product_query")]
         name: Option<String>,
+        #[clap(long, group = "product_query")]
+        isin: Option<String>,
         #[clap(long, default_value = "STD")]
         mode: RiskMode,
+        #[clap(long, default_value = "simple")]
+        ret_kind: ReturnKind,
         #[clap(long)]
         risk: f64,
         #[clap(long, default_value = "0.0")]
         risk_free: f64,
+        /// Lookback the ratios are computed over.
+        #[clap(long, default_value = "P1Y")]
+        period: Period,
+        /// Candle interval, candles in it have to be fetched already.
+        #[clap(long, default_value = "P1M")]
+        interval: Period,
     },
     CalculatePortfolio {
         #[clap(long)]
         mode: RiskMode,
+        #[clap(long, default_value = "simple")]
+        ret_kind: ReturnKind,
         #[clap(long)]
         risk: f64,
         #[clap(long, default_value = "0.0")]
         risk_free: f64,
+        /// Lookback the metrics and allocations are computed over.
+        #[clap(long, default_value = "P1Y")]
+        period: Period,
+        /// Candle interval, candles in it have to be fetched already.
+        #[clap(long, default_value = "P1M")]
+        interval: Period,
+        /// Lookback in candles, overrides the one of `--period` and `--interval`.
         #[clap(long)]
-        freq: usize,
+        freq: Option<usize>,
         #[clap(long)]
         money: f64,
+        /// Currency of `--money`, prices are converted into it with the configured exchange
+        /// rates. Without it prices are compared as they are.
+        #[clap(long)]
+        currency: Option<Currency>,
+        /// Date the analysis is done as of, e.g. 2024-03-31: later candles are ignored and the
+        /// annual reports are picked as of it. Today by default.
+        #[clap(long)]
+        as_of: Option<NaiveDate>,
         #[clap(long)]
         max_stocks: usize,
         #[clap(long)]
@@ -99,6 +166,8 @@ pub enum Commands {
         min_dd: Option<f64>,
         #[clap(long)]
         max_dd: Option<f64>,
+        #[clap(long, default_value = "avg_dd")]
+        dd_metric: DrawdownMetric,
         #[clap(long)]
         min_class: Option<ProductCategory>,
         #[clap(long)]
@@ -109,19 +178,121 @@ pub enum Commands {
         min_roic: Option<f64>,
         #[clap(long)]
         roic_wacc_delta: Option<f64>,
+        #[clap(long)]
+        shrinkage: Option<f64>,
+        #[clap(long, value_delimiter = ',')]
+        types: Option<Vec<ProductType>>,
+        /// Minimum acceptable return for the Sortino ratio, defaults to the configured one.
+        #[clap(long)]
+        mar: Option<f64>,
+        /// Lowest Bollinger %B of the last close, 0 is the lower band.
+        #[clap(long)]
+        min_percent_b: Option<f64>,
+        /// Highest Bollinger %B of the last close, 1 is the upper band.
+        #[clap(long)]
+        max_percent_b: Option<f64>,
+        /// Only keep assets whose RSI last crossed up through this oversold level.
+        #[clap(long)]
+        rsi_cross: Option<f64>,
+        /// Most candles since the RSI cross for it to count as fresh.
+        #[clap(long, default_value = "3")]
+        rsi_cross_within: usize,
+        /// Drop the lower Sharpe asset of any held pair correlated above this.
+        #[clap(long)]
+        max_correlation: Option<f64>,
+        /// How the annualized return column compounds: geometric or simple.
+        #[clap(long, default_value = "geometric")]
+        return_mode: AnnualizedReturnMode,
+        /// Product id of a benchmark with stored candles, adds each asset's beta, alpha and
+        /// information ratio against it.
+        #[clap(long)]
+        benchmark: Option<String>,
     },
     RecalculateSl {
-        #[clap(short, default_value = "2")]
-        n: usize,
+        /// Distance of the stop below the last price, in units of `--method`.
+        #[clap(short = 'n', long, default_value = "2")]
+        nstd: f64,
+        #[clap(long, default_value = "drawdown")]
+        method: StopLossMethod,
+        #[clap(long)]
+        max_percent: Option<f64>,
+        /// Averages only this many of the largest drawdowns for the drawdown method.
+        #[clap(long)]
+        largest_drawdowns: Option<usize>,
+        /// Minimum acceptable return for the Sortino ratio, defaults to the configured one.
+        #[clap(long)]
+        mar: Option<f64>,
+        /// Lookback of the stop loss method and the Sortino ratio, in the default monthly
+        /// candles.
+        #[clap(long, default_value = "P1Y")]
+        period: Period,
+        /// Lookback in candles, overrides `--period`.
+        #[clap(long)]
+        freq: Option<usize>,
+        /// Annual risk free rate of the Sortino ratio, configured rates take precedence.
+        #[clap(long, default_value = "0.0")]
+        risk_free: f64,
+    },
+    /// Lists the stop losses `RecalculateSl` would set on positions without a pending sell
+    /// order, `--force` places them.
+    PlaceStopLosses {
+        /// Distance of the stop below the last price, in units of `--method`.
+        #[clap(short = 'n', long, default_value = "2")]
+        nstd: f64,
+        #[clap(long, default_value = "drawdown")]
+        method: StopLossMethod,
+        /// Lookback of the stop loss method, in the default monthly candles.
+        #[clap(long, default_value = "P1Y")]
+        period: Period,
+        /// Lookback in candles, overrides `--period`.
+        #[clap(long)]
+        freq: Option<usize>,
+        #[clap(long)]
+        max_percent: Option<f64>,
+        /// Averages only this many of the largest drawdowns for the drawdown method.
+        #[clap(long)]
+        largest_drawdowns: Option<usize>,
+        #[clap(long)]
+        force: bool,
     },
+    /// Lists transactions newest first.
     GetTransactions {
         #[clap(short, long)]
         from_date: NaiveDate,
         #[clap(short, long)]
         to_date: NaiveDate,
+        /// Only transactions of this product.
+        #[clap(long)]
+        product_id: Option<String>,
+        /// Only `buy` or `sell` transactions.
+        #[clap(long)]
+        side: Option<TransactionSide>,
     },
     GetOrders,
-    CleanUp,
+    /// Portfolio value and profit recorded each day the portfolio was fetched.
+    GetSnapshotHistory {
+        #[clap(short, long)]
+        from_date: NaiveDate,
+        #[clap(short, long)]
+        to_date: NaiveDate,
+    },
+    /// Lists the products not among the configured assets, `--force` deletes them.
+    CleanUp {
+        #[clap(long)]
+        force: bool,
+    },
+    /// Disables an asset in the config, `--purge` also deletes its data.
+    RemoveAsset {
+        id: String,
+        #[clap(long)]
+        purge: bool,
+    },
+    ExportDb {
+        path: PathBuf,
+    },
+    ImportDb {
+        path: PathBuf,
+    },
 }
 
 #[async_trait]
@@ -134,11 +305,20 @@ impl CliExt for App {
     async fn run(self) -> Result<()> {
         let cli = Cli::parse();
         let port = cli.port;
+        let data_dir = cli.data_dir.unwrap_or_default();
         match cli.command {
             Some(cmd) => {
                 let addr = Ipv4Addr::new(127, 0, 0, 1);
                 let socket = SocketAddrV4::new(addr, port);
-                let mut client = ClientBuilder::new(socket).build().await.unwrap();
+                let mut client = ClientBuilder::new(socket)
+                    .table_style(cli.table_style)
+                    .precision(Precision {
+                        money: cli.precision,
+                        ratio: cli.ratio_precision.unwrap_or(cli.precision),
+                    })
+                    .build()
+                    .await
+                    .unwrap();
                 match cmd {
                     Commands::Authorize {} => {
                         info!("Authorizing...");
@@ -155,13 +335,86 @@ impl CliExt for App {
                             None
                         });
                     }
-                    Commands::GetProduct { id, symbol, name } => {
+                    Commands::FetchStatus => {
+                        let msg = server::Request::GetFetchStatus;
+                        match client.write(msg).await {
+                            Some(Response::SendFetchStatus {
+                                status: Some(status),
+                            }) => {
+                                if status.is_running() {
+                                    println!(
+                                        "Fetching {}/{}, current: {}",
+                                        status.completed,
+                                        status.total,
+                                        status.current_id.as_deref().unwrap_or("-")
+                                    );
+                                } else {
+                                    println!("Fetched {}/{}", status.completed, status.total);
+                                }
+                            }
+                            Some(Response::SendFetchStatus { status: None }) => {
+                                println!("No fetch status");
+                            }
+                            Some(_) => error!("Unexpected response"),
+                            None => warn!("No response"),
+                        }
+                    }
+                    Commands::Status => {
+                        let msg = server::Request::GetStatus;
+                        match client.write(msg).await {
+                            Some(Response::SendStatus { status }) => {
+                                let unknown = || "unknown".to_owned();
+                                let session =
+                                    status.session.map_or_else(unknown, |session| {
+                                        match (session.valid, session.age) {
+                                            (_, None) => "never authorized".to_owned(),
+                                            (valid, Some(age)) => format!(
+                                                "{}, last alive {}s ago",
+                                                if valid { "valid" } else { "expired" },
+                                                age.as_secs()
+                                            ),
+                                        }
+                                    });
+                                println!("Session: {session}");
+                                println!(
+                                    "Assets: {}",
+                                    status.assets.map_or_else(unknown, |n| n.to_string())
+                                );
+                                let entries = status.entries.map_or_else(unknown, |entries| {
+                                    format!(
+                                        "{} products, {} candles, {} financial reports, {} company ratios, {} snapshots",
+                                        entries.products,
+                                        entries.candles,
+                                        entries.financial_reports,
+                                        entries.company_ratios,
+                                        entries.portfolio_snapshots
+                                    )
+                                });
+                                println!("Database: {entries}");
+                                let last_fetch = status
+                                    .fetch
+                                    .and_then(|fetch| fetch.last_success)
+                                    .map_or_else(|| "never".to_owned(), |at| at.to_rfc3339());
+                                println!("Last successful fetch: {last_fetch}");
+                            }
+                            Some(_) => error!("Unexpected response"),
+                            None => warn!("No response"),
+                        }
+                    }
+                    Commands::GetProduct {
+                        id,
+                        symbol,
+                        name,
+                        isin,
+                    } => {
                         let query = if let Some(id) = id {
                             ProductQuery::Id(id.clone())
                         } else if let Some(symbol) = symbol {
                             ProductQuery::Symbol(symbol.clone())
                         } else if let Some(name) = name {
                             ProductQuery::Name(name.clone())
+                        } else if let Some(isin) = isin {
+                            ProductQuery::Isin(isin.clone())
                         } else {
                             panic!("No valid argument provided for GetProduct");
                         };
@@ -178,13 +431,38 @@ impl CliExt for App {
                             None => warn!("No response"),
                         };
                     }
-                    Commands::GetFinancials { id, symbol, name } => {
+                    Commands::SearchProducts { query, limit } => {
+                        let msg = server::Request::SearchProducts { query, limit };
+                        match client.write(msg).await {
+                            Some(Response::SendSearchProducts { products }) => {
+                                if products.is_empty() {
+                                    println!("No product found");
+                                }
+                                for product in products {
+                                    println!(
+                                        "{}\t{}\t{}\t{}",
+                                        product.id, product.symbol, product.isin, product.name
+                                    );
+                                }
+                            }
+                            Some(res) => error!(res = ?res, "Unexpected response"),
+                            None => warn!("No response"),
+                        };
+                    }
+                    Commands::GetFinancials {
+                        id,
+                        symbol,
+                        name,
+                        isin,
+                    } => {
                         let query = if let Some(id) = id {
                             ProductQuery::Id(id.clone())
                         } else if let Some(symbol) = symbol {
                             ProductQuery::Symbol(symbol.clone())
                         } else if let Some(name) = name {
                             ProductQuery::Name(name.clone())
+                        } else if let Some(isin) = isin {
+                            ProductQuery::Isin(isin.clone())
                         } else {
                             panic!("No valid argument provided for GetProduct");
                         };
@@ -200,13 +478,20 @@ impl CliExt for App {
                             _ => warn!("Unexpected response"),
                         }
                     }
-                    Commands::GetCandles { id, symbol, name } => {
+                    Commands::GetCandles {
+                        id,
+                        symbol,
+                        name,
+                        isin,
+                    } => {
                         let query = if let Some(id) = id {
                             ProductQuery::Id(id.clone())
                         } else if let Some(symbol) = symbol {
                             ProductQuery::Symbol(symbol.clone())
                         } else if let Some(name) = name {
                             ProductQuery::Name(name.clone())
+                        } else if let Some(isin) = isin {
+                            ProductQuery::Isin(isin.clone())
                         } else {
                             panic!("No valid argument provided for GetProduct");
                         };
@@ -219,10 +504,14 @@ impl CliExt for App {
                     Commands::GetSingleAllocation {
                         id,
                         mode,
+                        ret_kind,
                         risk,
                         risk_free,
+                        period,
+                        interval,
                         symbol,
                         name,
+                        isin,
                     } => {
                         let query = id.map_or_else(
                             || {
@@ -230,7 +519,12 @@ impl CliExt for App {
                                     || {
                                         name.map_or_else(
                                             || {
-                                                panic!("No valid argument provided for GetProduct");
+                                                isin.map_or_else(
+                                                    || {
+                                                        panic!("No valid argument provided for GetProduct");
+                                                    },
+                                                    ProductQuery::Isin,
+                                                )
                                             },
                                             ProductQuery::Name,
                                         )
@@ -243,16 +537,19 @@ impl CliExt for App {
                         let msg = server::Request::GetSingleAllocation {
                             query,
                             mode,
+                            ret_kind,
                             risk,
                             risk_free,
+                            period,
+                            interval,
                         };
                         client.write(msg).await.or_else(|| {
                             warn!("No response");
                             None
                         });
                     }
-                    Commands::GetPortfolio => {
-                        let msg = server::Request::GetPortfolio {};
+                    Commands::GetPortfolio { as_of } => {
+                        let msg = server::Request::GetPortfolio { as_of };
                         match client.write(msg).await {
                             Some(Response::SendPortfolio { portfolio }) => {
                                 if let Some(portfolio) = portfolio {
@@ -265,8 +562,40 @@ impl CliExt for App {
                             None => warn!("No response"),
                         }
                     }
-                    Commands::RecalculateSl { n } => {
-                        let msg = server::Request::RecalculateSl { n };
+                    Commands::Correlations => {
+                        let msg = server::Request::GetCorrelations;
+                        match client.write(msg).await {
+                            Some(Response::SendCorrelations { table }) => {
+                                if let Some(table) = table {
+                                    println!("{}", table);
+                                } else {
+                                    println!("No correlations calculated");
+                                }
+                            }
+                            Some(_) => error!("Unexpected response"),
+                            None => warn!("No response"),
+                        }
+                    }
+                    Commands::RecalculateSl {
+                        nstd,
+                        method,
+                        max_percent,
+                        largest_drawdowns,
+                        mar,
+                        period,
+                        freq,
+                        risk_free,
+                    } => {
+                        let freq = freq.unwrap_or_else(|| period.periods(DEFAULT_INTERVAL));
+                        let msg = server::Request::RecalculateSl {
+                            nstd,
+                            method,
+                            max_percent,
+                            largest_drawdowns,
+                            mar,
+                            freq,
+                            risk_free,
+                        };
                         match client.write(msg).await {
                             Some(Response::SendRecalcucatetSl { table }) => {
                                 if let Some(table) = table {
@@ -277,73 +606,229 @@ impl CliExt for App {
                             None => warn!("No response"),
                         }
                     }
+                    Commands::PlaceStopLosses {
+                        nstd,
+                        method,
+                        period,
+                        freq,
+                        max_percent,
+                        largest_drawdowns,
+                        force,
+                    } => {
+                        let freq = freq.unwrap_or_else(|| period.periods(DEFAULT_INTERVAL));
+                        let msg = server::Request::PlaceStopLosses {
+                            nstd,
+                            method,
+                            freq,
+                            max_percent,
+                            largest_drawdowns,
+                            dry_run: !force,
+                        };
+                        match client.write(msg).await {
+                            Some(Response::SendPlaceStopLosses { table: Some(table) }) => {
+                                println!("{}", table);
+                                if !force {
+                                    println!("Run with --force to place them");
+                                }
+                            }
+                            Some(Response::SendPlaceStopLosses { table: None }) => {
+                                error!("Failed to place stop losses")
+                            }
+                            Some(_) => error!("Unexpected response"),
+                            None => warn!("No response"),
+                        }
+                    }
                     Commands::CalculatePortfolio {
                         mode,
+                        ret_kind,
                         risk,
                         risk_free,
+                        period,
+                        interval,
                         freq,
                         money,
+                        currency,
+                        as_of,
                         max_stocks,
                         min_rsi,
                         max_rsi,
                         min_dd,
                         max_dd,
+                        dd_metric,
                         min_class,
                         max_class,
                         short_sales_constraint,
                         min_roic,
                         roic_wacc_delta,
+                        shrinkage,
+                        types,
+                        mar,
+                        min_percent_b,
+                        max_percent_b,
+                        rsi_cross,
+                        rsi_cross_within,
+                        max_correlation,
+                        return_mode,
+                        benchmark,
                     } => {
+                        let freq = freq.unwrap_or_else(|| period.periods(interval));
                         let req = server::Request::CalculatePortfolio {
                             mode,
+                            ret_kind,
                             risk,
                             risk_free,
+                            period,
+                            interval,
                             freq,
                             money,
+                            currency,
+                            as_of,
                             max_stocks,
                             min_rsi,
                             max_rsi,
                             min_dd,
                             max_dd,
+                            dd_metric,
                             min_class,
                             max_class,
                             short_sales_constraint,
                             min_roic,
                             roic_wacc_delta,
+                            shrinkage,
+                            types: types.map(|types| types.into_iter().collect()),
+                            mar,
+                            min_percent_b,
+                            max_percent_b,
+                            rsi_cross_level: rsi_cross,
+                            rsi_cross_within,
+                            max_correlation,
+                            return_mode,
+                            benchmark,
                         };
                         match client.write(req).await {
-                            Some(Response::SendPortfolio { portfolio }) => {
-                                if let Some(portfolio) = portfolio {
-                                    println!("{}", portfolio);
+                            Some(Response::SendCalculatePortfolio {
+                                portfolio,
+                                error,
+                                excluded,
+                            }) => {
+                                match (portfolio, error) {
+                                    (Some(portfolio), _) => println!("{}", portfolio),
+                                    (None, Some(error)) => {
+                                        println!("No portfolio calculated: {}", error)
+                                    }
+                                    (None, None) => println!("No portfolio calculated"),
+                                }
+                                if !excluded.is_empty() {
+                                    println!("Excluded assets:");
+                                    for asset in excluded {
+                                        println!(
+                                            "  {} ({}): {}",
+                                            asset.name, asset.id, asset.reason
+                                        );
+                                    }
+                                }
+                            }
+                            Some(_) => error!("Unexpected response"),
+                            None => warn!("No response"),
+                        }
+                    }
+                    Commands::CleanUp { force } => {
+                        let msg = server::Request::CleanUp { dry_run: !force };
+                        match client.write(msg).await {
+                            Some(Response::SendCleanUp { ids: Some(ids) }) => {
+                                if ids.is_empty() {
+                                    println!("Nothing to clean up");
                                 } else {
-                                    println!("No portfolio calculated");
+                                    let verb = if force { "Deleted" } else { "Would delete" };
+                                    println!("{} {} products:", verb, ids.len());
+                                    ids.iter().for_each(|id| println!("{}", id));
+                                    if !force {
+                                        println!("Run with --force to delete them");
+                                    }
                                 }
                             }
+                            Some(Response::SendCleanUp { ids: None }) => {
+                                error!("Failed to clean up")
+                            }
                             Some(_) => error!("Unexpected response"),
                             None => warn!("No response"),
                         }
                     }
-                    Commands::CleanUp => {
-                        let msg = server::Request::CleanUp;
-                        client.write(msg).await.or_else(|| {
-                            warn!("No response");
-                            None
-                        });
+                    Commands::RemoveAsset { id, purge } => {
+                        let msg = server::Request::RemoveAsset {
+                            id: id.clone(),
+                            purge_data: purge,
+                        };
+                        match client.write(msg).await {
+                            Some(Response::SendRemoveAsset {
+                                removed: Some(true),
+                            }) => println!("Removed {}", id),
+                            Some(Response::SendRemoveAsset {
+                                removed: Some(false),
+                            }) => println!("{} is not among the configured assets", id),
+                            Some(Response::SendRemoveAsset { removed: None }) => {
+                                error!("Failed to remove {}", id)
+                            }
+                            Some(_) => error!("Unexpected response"),
+                            None => warn!("No response"),
+                        }
                     }
-                    Commands::GetTransactions { from_date, to_date } => {
-                        dbg!(from_date, to_date);
-                        // let msg = server::Request::GetTransactions { from_date, to_date };
-                        // match client.write(msg).await {
-                        //     Some(Response::SendTransactions { table }) => {
-                        //         if let Some(table) = table {
-                        //             println!("{}", table);
-                        //         } else {
-                        //             println!("No transactions found");
-                        //         }
-                        //     }
-                        //     Some(_) => error!("Unexpected response"),
-                        //     None => warn!("No response"),
-                        // }
+                    Commands::ExportDb { path } => {
+                        // The server resolves paths against its own working directory.
+                        let path =
+                            std::env::current_dir().map_or(path.clone(), |dir| dir.join(&path));
+                        let msg = server::Request::ExportDb { path };
+                        match client.write(msg).await {
+                            Some(Response::SendExportDb { error }) => {
+                                if let Some(error) = error {
+                                    error!(error = %error, "Failed to export database");
+                                } else {
+                                    println!("Database exported");
+                                }
+                            }
+                            Some(_) => error!("Unexpected response"),
+                            None => warn!("No response"),
+                        }
+                    }
+                    Commands::ImportDb { path } => {
+                        let path =
+                            std::env::current_dir().map_or(path.clone(), |dir| dir.join(&path));
+                        let msg = server::Request::ImportDb { path };
+                        match client.write(msg).await {
+                            Some(Response::SendImportDb { error }) => {
+                                if let Some(error) = error {
+                                    error!(error = %error, "Failed to import database");
+                                } else {
+                                    println!("Database imported");
+                                }
+                            }
+                            Some(_) => error!("Unexpected response"),
+                            None => warn!("No response"),
+                        }
+                    }
+                    Commands::GetTransactions {
+                        from_date,
+                        to_date,
+                        product_id,
+                        side,
+                    } => {
+                        let msg = server::Request::GetTransactions {
+                            from_date,
+                            to_date,
+                            product_id,
+                            side,
+                        };
+                        match client.write(msg).await {
+                            Some(Response::SendTransactions { table }) => {
+                                if let Some(table) = table {
+                                    println!("{}", table);
+                                } else {
+                                    println!("No transactions found");
+                                }
+                            }
+                            Some(_) => error!("Unexpected response"),
+                            None => warn!("No response"),
+                        }
                     }
                     Commands::GetOrders => {
                         let msg = server::Request::GetOrders;
@@ -359,6 +844,23 @@ impl CliExt for App {
                             None => warn!("No response"),
                         }
                     }
+                    Commands::GetSnapshotHistory { from_date, to_date } => {
+                        let msg = server::Request::GetSnapshotHistory {
+                            from: from_date,
+                            to: to_date,
+                        };
+                        match client.write(msg).await {
+                            Some(Response::SendSnapshotHistory { table }) => {
+                                if let Some(table) = table {
+                                    println!("{}", table);
+                                } else {
+                                    println!("No snapshots found");
+                                }
+                            }
+                            Some(_) => error!("Unexpected response"),
+                            None => warn!("No response"),
+                        }
+                    }
                 }
             }
             None => {
@@ -367,16 +869,35 @@ impl CliExt for App {
                 match server::Server::new(socket).await {
                     Ok(server) => {
                         let mop = MasterOfPuppets::default();
-                        let settings = Settings::new(None);
+                        let config_path = data_dir.join("Config");
+                        let settings = Settings::new(Some(&config_path.to_string_lossy()));
                         let _settings_address = PuppetBuilder::new(settings.clone())
                             .spawn(&mop)
                             .await
                             .unwrap();
                         let server_address = PuppetBuilder::new(server).spawn(&mop).await.unwrap();
                         server_address.send(server::RunServer).await.unwrap();
-                        let _db_address = PuppetBuilder::new(Db::new()).spawn(&mop).await.unwrap();
-                        let degiro = Degiro::new(&settings.username, &settings.password).unwrap();
-                        let _degiro_address = PuppetBuilder::new(degiro).spawn(&mop).await.unwrap();
+                        let db =
+                            Db::new(&data_dir, settings.db_map_size.unwrap_or(DEFAULT_MAP_SIZE))?;
+                        let _db_address = PuppetBuilder::new(db).spawn(&mop).await.unwrap();
+                        let degiro = Degiro::new(
+                            &settings.username,
+                            &settings.password,
+                            settings
+                                .requests_per_second
+                                .unwrap_or(DEFAULT_REQUESTS_PER_SECOND),
+                            &data_dir,
+                        )?;
+                        let degiro_address = PuppetBuilder::new(degiro).spawn(&mop).await.unwrap();
+                        tokio::spawn(async move {
+                            let mut interval = tokio::time::interval(HEARTBEAT_INTERVAL);
+                            loop {
+                                interval.tick().await;
+                                if let Err(err) = degiro_address.send(Heartbeat).await {
+                                    warn!(error = %err, "Failed to send heartbeat");
+                                }
+                            }
+                        });
                         let _calculator_address =
                             PuppetBuilder::new(Calculator::new(settings.clone()))
                                 .spawn(&mop)
@@ -0,0 +1,88 @@
+use erfurt::candle::Candles;
+use qualsdorf::Indicator;
+use statrs::statistics::Statistics;
+
+use super::{aligned_returns, RiskFree};
+
+/// Rolling Jensen's alpha per period, `risk_free` being the per-period rate.
+#[derive(Debug)]
+pub struct Alpha {
+    pub freq: usize,
+    pub risk_free: RiskFree,
+    pub input: Vec<(f64, f64)>,
+    pub values: Vec<Option<f64>>,
+}
+
+impl Alpha {
+    #[must_use]
+    pub fn new(freq: usize, risk_free: impl Into<RiskFree>) -> Self {
+        Self {
+            freq,
+            risk_free: risk_free.into(),
+            input: Vec::with_capacity(freq),
+            values: Vec::with_capacity(freq),
+        }
+    }
+}
+
+impl Indicator for Alpha {
+    type Input = (f64, f64);
+    type Output = f64;
+
+    fn feed(&mut self, value: Self::Input) {
+        self.input.push(value);
+
+        if self.input.len() >= self.freq && self.freq >= 2 {
+            let start = self.input.len() - self.freq;
+            let window = &self.input[start..];
+            let risk_free = self.risk_free.mean(start..self.input.len());
+            let rets = window.iter().map(|(ret, _)| *ret).collect::<Vec<_>>();
+            let benchmark = window.iter().map(|(_, ret)| *ret).collect::<Vec<_>>();
+            let variance = benchmark.iter().variance();
+            let value = (variance > 0.0).then(|| {
+                let beta = rets.iter().covariance(benchmark.iter()) / variance;
+                let expected = beta.mul_add(benchmark.iter().mean() - risk_free, risk_free);
+                rets.iter().mean() - expected
+            });
+            self.values.push(value);
+        } else {
+            self.values.push(None);
+        }
+    }
+
+    fn last(&self) -> Option<&Self::Output> {
+        self.values.last().and_then(|v| v.as_ref())
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = Option<&Self::Output>> + '_> {
+        Box::new(self.values.iter().map(Option::as_ref))
+    }
+}
+
+pub trait AlphaExt {
+    fn alpha(
+        &self,
+        benchmark: &Candles,
+        freq: usize,
+        risk_free: impl Into<RiskFree>,
+    ) -> Option<Alpha>;
+}
+
+impl AlphaExt for Candles {
+    fn alpha(
+        &self,
+        benchmark: &Candles,
+        freq: usize,
+        risk_free: impl Into<RiskFree>,
+    ) -> Option<Alpha> {
+        let (ret, benchmark_ret) = aligned_returns(self, benchmark)?;
+        if ret.len() < freq {
+            return None;
+        }
+        let mut indicator = Alpha::new(freq, risk_free);
+        ret.into_iter()
+            .zip(benchmark_ret)
+            .for_each(|v| indicator.feed(v));
+        Some(indicator)
+    }
+}
@@ -0,0 +1,111 @@
+use erfurt::candle::CandlesExt;
+use qualsdorf::{Indicator, ReturnExt};
+use serde::{Deserialize, Serialize};
+use strum::EnumString;
+
+/// How `AnnualizedReturn` combines the returns of a window.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, EnumString, Serialize, Deserialize)]
+#[strum(serialize_all = "lowercase", ascii_case_insensitive)]
+pub enum AnnualizedReturnMode {
+    /// Compounds the returns.
+    #[default]
+    Geometric,
+    /// Adds the returns up.
+    Simple,
+}
+
+/// Rolling annualized return of the last `freq` returns, `periods` of which make a year.
+#[derive(Debug)]
+pub struct AnnualizedReturn {
+    pub freq: usize,
+    pub periods: usize,
+    pub mode: AnnualizedReturnMode,
+    pub input: Vec<f64>,
+    pub values: Vec<Option<f64>>,
+}
+
+impl AnnualizedReturn {
+    #[must_use]
+    pub fn new(freq: usize, periods: usize, mode: AnnualizedReturnMode) -> Self {
+        Self {
+            freq,
+            periods,
+            mode,
+            input: Vec::with_capacity(freq),
+            values: Vec::with_capacity(freq),
+        }
+    }
+}
+
+impl Indicator for AnnualizedReturn {
+    type Input = f64;
+    type Output = f64;
+
+    fn feed(&mut self, ret: Self::Input) {
+        self.input.push(ret);
+
+        if self.input.len() >= self.freq && self.freq > 0 && self.periods > 0 {
+            let window = &self.input[self.input.len() - self.freq..];
+            let years = self.freq as f64 / self.periods as f64;
+            let value = match self.mode {
+                AnnualizedReturnMode::Geometric => {
+                    window
+                        .iter()
+                        .map(|ret| 1.0 + ret)
+                        .product::<f64>()
+                        .powf(years.recip())
+                        - 1.0
+                }
+                AnnualizedReturnMode::Simple => window.iter().sum::<f64>() / years,
+            };
+            self.values.push(Some(value));
+        } else {
+            self.values.push(None);
+        }
+    }
+
+    fn last(&self) -> Option<&Self::Output> {
+        self.values.last().and_then(|v| v.as_ref())
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = Option<&Self::Output>> + '_> {
+        Box::new(self.values.iter().map(Option::as_ref))
+    }
+}
+
+pub trait AnnualizedReturnExt: ReturnExt {
+    /// Annualized return of the last `freq` returns, `periods` of which make a year.
+    fn annualized_return(
+        &self,
+        freq: usize,
+        periods: usize,
+        mode: AnnualizedReturnMode,
+    ) -> Option<f64> {
+        let mut indicator = AnnualizedReturn::new(freq, periods, mode);
+        self.ret()?.into_iter().for_each(|v| indicator.feed(v));
+        indicator.last().copied()
+    }
+}
+
+impl<T> AnnualizedReturnExt for T where T: CandlesExt {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::indicators::fixtures::XS;
+
+    #[test]
+    fn annualized_return() {
+        let mut geometric = AnnualizedReturn::new(6, 12, AnnualizedReturnMode::Geometric);
+        let mut simple = AnnualizedReturn::new(6, 12, AnnualizedReturnMode::Simple);
+        XS.iter().for_each(|&x| {
+            geometric.feed(x);
+            simple.feed(x);
+        });
+        assert!(geometric.values[..5].iter().all(Option::is_none));
+        assert!((geometric.values[5].unwrap() - 0.068_387_760_499_180_27).abs() < 1e-9);
+        assert!((geometric.last().unwrap() - 0.019_973_420_487_084_06).abs() < 1e-9);
+        assert!((simple.values[5].unwrap() - 0.07).abs() < 1e-9);
+        assert!((simple.last().unwrap() - 0.022).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,77 @@
+use erfurt::candle::CandlesExt;
+use qualsdorf::{Indicator, ReturnExt};
+use statrs::statistics::Statistics;
+
+/// Rolling annualized volatility, the sample standard deviation of the last `freq` returns
+/// scaled by the square root of the `periods` in a year.
+#[derive(Debug)]
+pub struct AnnualizedRisk {
+    pub freq: usize,
+    pub periods: usize,
+    pub input: Vec<f64>,
+    pub values: Vec<Option<f64>>,
+}
+
+impl AnnualizedRisk {
+    #[must_use]
+    pub fn new(freq: usize, periods: usize) -> Self {
+        Self {
+            freq,
+            periods,
+            input: Vec::with_capacity(freq),
+            values: Vec::with_capacity(freq),
+        }
+    }
+}
+
+impl Indicator for AnnualizedRisk {
+    type Input = f64;
+    type Output = f64;
+
+    fn feed(&mut self, ret: Self::Input) {
+        self.input.push(ret);
+
+        // The sample standard deviation needs at least two observations.
+        if self.input.len() >= self.freq && self.freq >= 2 {
+            let window = &self.input[self.input.len() - self.freq..];
+            let value = window.iter().std_dev() * (self.periods as f64).sqrt();
+            self.values.push(Some(value));
+        } else {
+            self.values.push(None);
+        }
+    }
+
+    fn last(&self) -> Option<&Self::Output> {
+        self.values.last().and_then(|v| v.as_ref())
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = Option<&Self::Output>> + '_> {
+        Box::new(self.values.iter().map(Option::as_ref))
+    }
+}
+
+pub trait AnnualizedRiskExt: ReturnExt {
+    /// Annualized volatility of the last `freq` returns, `periods` of which make a year.
+    fn annualized_risk(&self, freq: usize, periods: usize) -> Option<f64> {
+        let mut indicator = AnnualizedRisk::new(freq, periods);
+        self.ret()?.into_iter().for_each(|v| indicator.feed(v));
+        indicator.last().copied()
+    }
+}
+
+impl<T> AnnualizedRiskExt for T where T: CandlesExt {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::indicators::fixtures::XS;
+
+    #[test]
+    fn annualized_risk() {
+        let mut indicator = AnnualizedRisk::new(6, 12);
+        XS.iter().for_each(|&x| indicator.feed(x));
+        assert!(indicator.values[..5].iter().all(Option::is_none));
+        assert!((indicator.values[5].unwrap() - 0.093_912_725_442_295_62).abs() < 1e-9);
+        assert!((indicator.last().unwrap() - 0.072_875_235_848_674_96).abs() < 1e-9);
+    }
+}
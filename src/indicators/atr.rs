@@ -0,0 +1,79 @@
+use erfurt::candle::Candles;
+use qualsdorf::Indicator;
+
+/// Wilder's Average True Range.
+///
+/// The first value is the simple mean of the first `freq` true ranges, every
+/// following value uses Wilder's smoothing `(prev * (freq - 1) + tr) / freq`.
+#[derive(Debug)]
+pub struct Atr {
+    pub freq: usize,
+    pub prev_close: Option<f64>,
+    pub input: Vec<f64>,
+    pub values: Vec<Option<f64>>,
+}
+
+impl Atr {
+    #[must_use]
+    pub fn new(freq: usize) -> Self {
+        Self {
+            freq,
+            prev_close: None,
+            input: Vec::with_capacity(freq),
+            values: Vec::with_capacity(freq),
+        }
+    }
+}
+
+impl Indicator for Atr {
+    /// `(high, low, close)` of a single candle.
+    type Input = (f64, f64, f64);
+    type Output = f64;
+
+    fn feed(&mut self, (high, low, close): Self::Input) {
+        let true_range = self.prev_close.map_or(high - low, |prev_close| {
+            (high - low)
+                .max((high - prev_close).abs())
+                .max((low - prev_close).abs())
+        });
+        self.prev_close = Some(close);
+        self.input.push(true_range);
+
+        let n = self.freq as f64;
+        let value = match self.values.last() {
+            Some(Some(prev)) => Some(prev.mul_add(n - 1.0, true_range) / n),
+            _ if self.freq > 0 && self.input.len() == self.freq => {
+                Some(self.input.iter().sum::<f64>() / n)
+            }
+            _ => None,
+        };
+        self.values.push(value);
+    }
+
+    fn last(&self) -> Option<&Self::Output> {
+        self.values.last().and_then(|v| v.as_ref())
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = Option<&Self::Output>> + '_> {
+        Box::new(self.values.iter().map(Option::as_ref))
+    }
+}
+
+pub trait AtrExt {
+    fn atr(&self, freq: usize) -> Option<Atr>;
+}
+
+impl AtrExt for Candles {
+    fn atr(&self, freq: usize) -> Option<Atr> {
+        if self.close.is_empty() {
+            return None;
+        }
+        let mut indicator = Atr::new(freq);
+        self.high
+            .iter()
+            .zip(self.low.iter())
+            .zip(self.close.iter())
+            .for_each(|((&high, &low), &close)| indicator.feed((high, low, close)));
+        Some(indicator)
+    }
+}
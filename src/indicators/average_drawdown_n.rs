@@ -0,0 +1,97 @@
+use erfurt::candle::CandlesExt;
+use qualsdorf::{Indicator, ReturnExt};
+
+use super::continuous_drawdowns;
+
+/// Rolling mean of the `n` largest continuous drawdowns within the last `freq` returns.
+///
+/// A window with fewer than `n` drawdowns averages the ones it has, one without any drawdown
+/// is `0.0`.
+#[derive(Debug)]
+pub struct AverageDrawdownN {
+    pub freq: usize,
+    pub n: usize,
+    pub input: Vec<f64>,
+    pub values: Vec<Option<f64>>,
+}
+
+impl AverageDrawdownN {
+    #[must_use]
+    pub fn new(freq: usize, n: usize) -> Self {
+        Self {
+            freq,
+            n,
+            input: Vec::with_capacity(freq),
+            values: Vec::with_capacity(freq),
+        }
+    }
+}
+
+impl Indicator for AverageDrawdownN {
+    type Input = f64;
+    type Output = f64;
+
+    fn feed(&mut self, ret: Self::Input) {
+        self.input.push(ret);
+
+        if self.input.len() >= self.freq && self.freq > 0 && self.n > 0 {
+            let window = &self.input[self.input.len() - self.freq..];
+            let mut drawdowns = continuous_drawdowns(window);
+            drawdowns.sort_by(|a, b| b.total_cmp(a));
+            drawdowns.truncate(self.n);
+            let value = if drawdowns.is_empty() {
+                0.0
+            } else {
+                drawdowns.iter().sum::<f64>() / drawdowns.len() as f64
+            };
+            self.values.push(Some(value));
+        } else {
+            self.values.push(None);
+        }
+    }
+
+    fn last(&self) -> Option<&Self::Output> {
+        self.values.last().and_then(|v| v.as_ref())
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = Option<&Self::Output>> + '_> {
+        Box::new(self.values.iter().map(Option::as_ref))
+    }
+}
+
+pub trait AverageDrawdownNExt: ReturnExt {
+    /// Mean of the `n` largest drawdowns of the last `freq` returns.
+    fn average_drawdown_n(&self, freq: usize, n: usize) -> Option<f64> {
+        let mut indicator = AverageDrawdownN::new(freq, n);
+        self.ret()?.into_iter().for_each(|v| indicator.feed(v));
+        indicator.last().copied()
+    }
+}
+
+impl<T> AverageDrawdownNExt for T where T: CandlesExt {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::indicators::fixtures::XS;
+
+    #[test]
+    fn average_drawdown_n() {
+        let mut indicator = AverageDrawdownN::new(6, 2);
+        XS.iter().for_each(|&x| indicator.feed(x));
+        assert_eq!(indicator.values.len(), XS.len());
+        assert!(indicator.values[..5].iter().all(Option::is_none));
+        assert!((indicator.values[5].unwrap() - 0.0245).abs() < 1e-9);
+        assert!((indicator.last().unwrap() - 0.019).abs() < 1e-9);
+    }
+
+    #[test]
+    fn average_drawdown_n_fewer_drawdowns() {
+        let mut indicator = AverageDrawdownN::new(6, 5);
+        XS.iter().for_each(|&x| indicator.feed(x));
+        assert!((indicator.last().unwrap() - 0.014_666_666_666_666_68).abs() < 1e-9);
+        let mut indicator = AverageDrawdownN::new(3, 2);
+        [0.01, 0.02, 0.0].iter().for_each(|&x| indicator.feed(x));
+        assert_eq!(indicator.last(), Some(&0.0));
+    }
+}
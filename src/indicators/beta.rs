@@ -0,0 +1,71 @@
+use erfurt::candle::Candles;
+use qualsdorf::Indicator;
+use statrs::statistics::Statistics;
+
+use super::aligned_returns;
+
+/// Rolling beta of asset returns against benchmark returns.
+#[derive(Debug)]
+pub struct Beta {
+    pub freq: usize,
+    pub input: Vec<(f64, f64)>,
+    pub values: Vec<Option<f64>>,
+}
+
+impl Beta {
+    #[must_use]
+    pub fn new(freq: usize) -> Self {
+        Self {
+            freq,
+            input: Vec::with_capacity(freq),
+            values: Vec::with_capacity(freq),
+        }
+    }
+}
+
+impl Indicator for Beta {
+    type Input = (f64, f64);
+    type Output = f64;
+
+    fn feed(&mut self, value: Self::Input) {
+        self.input.push(value);
+
+        if self.input.len() >= self.freq && self.freq >= 2 {
+            let window = &self.input[self.input.len() - self.freq..];
+            let rets = window.iter().map(|(ret, _)| *ret).collect::<Vec<_>>();
+            let benchmark = window.iter().map(|(_, ret)| *ret).collect::<Vec<_>>();
+            let variance = benchmark.iter().variance();
+            let value =
+                (variance > 0.0).then(|| rets.iter().covariance(benchmark.iter()) / variance);
+            self.values.push(value);
+        } else {
+            self.values.push(None);
+        }
+    }
+
+    fn last(&self) -> Option<&Self::Output> {
+        self.values.last().and_then(|v| v.as_ref())
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = Option<&Self::Output>> + '_> {
+        Box::new(self.values.iter().map(Option::as_ref))
+    }
+}
+
+pub trait BetaExt {
+    fn beta(&self, benchmark: &Candles, freq: usize) -> Option<Beta>;
+}
+
+impl BetaExt for Candles {
+    fn beta(&self, benchmark: &Candles, freq: usize) -> Option<Beta> {
+        let (ret, benchmark_ret) = aligned_returns(self, benchmark)?;
+        if ret.len() < freq {
+            return None;
+        }
+        let mut indicator = Beta::new(freq);
+        ret.into_iter()
+            .zip(benchmark_ret)
+            .for_each(|v| indicator.feed(v));
+        Some(indicator)
+    }
+}
@@ -0,0 +1,119 @@
+use erfurt::candle::Candles;
+use qualsdorf::Indicator;
+
+use super::moving_average::Sma;
+
+/// Rolling Bollinger bands, `sma ± k * std` of the closes over `freq` candles.
+///
+/// The indicator value is `%B`, the position of the close within the bands, `0.0` on the
+/// lower and `1.0` on the upper band. The bands themselves are kept aligned with it for
+/// charting. A window without any spread has bands but no `%B`.
+#[derive(Debug)]
+pub struct Bollinger {
+    pub freq: usize,
+    pub k: f64,
+    sma: Sma,
+    pub upper: Vec<Option<f64>>,
+    pub middle: Vec<Option<f64>>,
+    pub lower: Vec<Option<f64>>,
+    pub values: Vec<Option<f64>>,
+}
+
+impl Bollinger {
+    #[must_use]
+    pub fn new(freq: usize, k: f64) -> Self {
+        Self {
+            freq,
+            k,
+            sma: Sma::new(freq),
+            upper: Vec::with_capacity(freq),
+            middle: Vec::with_capacity(freq),
+            lower: Vec::with_capacity(freq),
+            values: Vec::with_capacity(freq),
+        }
+    }
+}
+
+impl Indicator for Bollinger {
+    type Input = f64;
+    type Output = f64;
+
+    fn feed(&mut self, close: Self::Input) {
+        self.sma.feed(close);
+
+        if let Some(&mean) = self.sma.last() {
+            let window = &self.sma.input[self.sma.input.len() - self.freq..];
+            let n = self.freq as f64;
+            let std = (window.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / n).sqrt();
+            let upper = self.k.mul_add(std, mean);
+            let lower = (-self.k).mul_add(std, mean);
+            self.upper.push(Some(upper));
+            self.middle.push(Some(mean));
+            self.lower.push(Some(lower));
+            self.values
+                .push((upper > lower).then(|| (close - lower) / (upper - lower)));
+        } else {
+            self.upper.push(None);
+            self.middle.push(None);
+            self.lower.push(None);
+            self.values.push(None);
+        }
+    }
+
+    fn last(&self) -> Option<&Self::Output> {
+        self.values.last().and_then(|v| v.as_ref())
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = Option<&Self::Output>> + '_> {
+        Box::new(self.values.iter().map(Option::as_ref))
+    }
+}
+
+pub trait BollingerExt {
+    fn bollinger(&self, freq: usize, k: f64) -> Option<Bollinger>;
+}
+
+impl BollingerExt for Candles {
+    fn bollinger(&self, freq: usize, k: f64) -> Option<Bollinger> {
+        if self.close.is_empty() {
+            return None;
+        }
+        let mut indicator = Bollinger::new(freq, k);
+        self.close.iter().for_each(|&close| indicator.feed(close));
+        Some(indicator)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    static CLOSES: [f64; 12] = [
+        10.0, 10.5, 10.2, 10.8, 11.1, 10.9, 11.4, 11.0, 11.6, 12.0, 11.7, 12.3,
+    ];
+
+    #[test]
+    fn bollinger() {
+        let mut indicator = Bollinger::new(6, 2.0);
+        CLOSES.iter().for_each(|&x| indicator.feed(x));
+        assert_eq!(indicator.values.len(), CLOSES.len());
+        assert_eq!(indicator.upper.len(), CLOSES.len());
+        assert!(indicator.middle[..5].iter().all(Option::is_none));
+        let middle = indicator.middle.last().unwrap().unwrap();
+        let upper = indicator.upper.last().unwrap().unwrap();
+        let lower = indicator.lower.last().unwrap().unwrap();
+        assert!((middle - 11.666_666_666_666_666).abs() < 1e-9);
+        assert!((upper - 12.496_659_973_199_248).abs() < 1e-9);
+        assert!((lower - 10.836_673_360_134_084).abs() < 1e-9);
+        let percent_b = *indicator.last().unwrap();
+        assert!((percent_b - 0.881_529_181_228_687_6).abs() < 1e-9);
+    }
+
+    #[test]
+    fn bollinger_flat() {
+        let mut indicator = Bollinger::new(3, 2.0);
+        [10.0, 10.0, 10.0].iter().for_each(|&x| indicator.feed(x));
+        assert_eq!(indicator.middle.last(), Some(&Some(10.0)));
+        assert!(indicator.last().is_none());
+    }
+}
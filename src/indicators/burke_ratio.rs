@@ -0,0 +1,98 @@
+use erfurt::candle::CandlesExt;
+use qualsdorf::{Indicator, ReturnExt};
+
+use super::{continuous_drawdowns, RiskFree};
+
+/// Rolling Burke ratio, the compounded excess return of the window over the root of the
+/// summed squares of its continuous drawdowns.
+///
+/// The per-period `risk_free` is compounded over the window too. A window without any
+/// drawdown has no value.
+#[derive(Debug)]
+pub struct BurkeRatio {
+    pub freq: usize,
+    pub risk_free: RiskFree,
+    pub input: Vec<f64>,
+    pub values: Vec<Option<f64>>,
+}
+
+impl BurkeRatio {
+    #[must_use]
+    pub fn new(freq: usize, risk_free: impl Into<RiskFree>) -> Self {
+        Self {
+            freq,
+            risk_free: risk_free.into(),
+            input: Vec::with_capacity(freq),
+            values: Vec::with_capacity(freq),
+        }
+    }
+}
+
+impl Indicator for BurkeRatio {
+    type Input = f64;
+    type Output = f64;
+
+    fn feed(&mut self, ret: Self::Input) {
+        self.input.push(ret);
+
+        if self.input.len() >= self.freq && self.freq > 0 {
+            let start = self.input.len() - self.freq;
+            let window = &self.input[start..];
+            let risk_free = self.risk_free.compounded(start..self.input.len());
+            let ret = window.iter().map(|ret| 1.0 + ret).product::<f64>() - 1.0;
+            let risk = continuous_drawdowns(window)
+                .iter()
+                .map(|dd| dd.powi(2))
+                .sum::<f64>()
+                .sqrt();
+            self.values
+                .push((risk > 0.0).then(|| (ret - risk_free) / risk));
+        } else {
+            self.values.push(None);
+        }
+    }
+
+    fn last(&self) -> Option<&Self::Output> {
+        self.values.last().and_then(|v| v.as_ref())
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = Option<&Self::Output>> + '_> {
+        Box::new(self.values.iter().map(Option::as_ref))
+    }
+}
+
+pub trait BurkeRatioExt: ReturnExt {
+    /// Burke ratio of the last `freq` returns, `None` when the window has no drawdown.
+    fn burke_ratio(&self, freq: usize, risk_free: impl Into<RiskFree>) -> Option<f64> {
+        let mut indicator = BurkeRatio::new(freq, risk_free);
+        self.ret()?.into_iter().for_each(|v| indicator.feed(v));
+        indicator.last().copied()
+    }
+}
+
+impl<T> BurkeRatioExt for T where T: CandlesExt {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::indicators::fixtures::XS;
+
+    #[test]
+    fn burke_ratio() {
+        let mut indicator = BurkeRatio::new(6, 0.001);
+        XS.iter().for_each(|&x| indicator.feed(x));
+        assert_eq!(indicator.values.len(), XS.len());
+        assert!(indicator.values[..5].iter().all(Option::is_none));
+        let first = indicator.values[5].unwrap();
+        assert!((first - 0.743_059_214_069_572_4).abs() < 1e-9);
+        let value = *indicator.last().unwrap();
+        assert!((value - 0.131_772_746_690_332_9).abs() < 1e-9);
+    }
+
+    #[test]
+    fn burke_ratio_flat() {
+        let mut indicator = BurkeRatio::new(3, 0.0);
+        [0.01, 0.02, 0.0].iter().for_each(|&x| indicator.feed(x));
+        assert!(indicator.last().is_none());
+    }
+}
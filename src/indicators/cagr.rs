@@ -0,0 +1,39 @@
+use erfurt::candle::Candles;
+
+/// Days in an average year, leap years included.
+const DAYS_PER_YEAR: f64 = 365.25;
+
+/// Compound annual growth rate from `first` to `last` over `years`.
+///
+/// `None` for prices that aren't positive or a span that isn't.
+#[must_use]
+pub fn cagr(first: f64, last: f64, years: f64) -> Option<f64> {
+    (first > 0.0 && last > 0.0 && years > 0.0).then(|| (last / first).powf(years.recip()) - 1.0)
+}
+
+pub trait CagrExt {
+    /// Compound annual growth rate of the close over all the candles, not a rolling window.
+    fn cagr(&self) -> Option<f64>;
+}
+
+impl CagrExt for Candles {
+    fn cagr(&self) -> Option<f64> {
+        let span = *self.time.last()? - *self.time.first()?;
+        let years = span.num_seconds() as f64 / (DAYS_PER_YEAR * 24.0 * 60.0 * 60.0);
+        cagr(*self.close.first()?, *self.close.last()?, years)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn cagr() {
+        let value = super::cagr(100.0, 150.0, 2.0).unwrap();
+        assert!((value - 0.224_744_871_391_589).abs() < 1e-9);
+        assert!((super::cagr(100.0, 50.0, 1.0).unwrap() + 0.5).abs() < 1e-9);
+        assert!(super::cagr(0.0, 50.0, 1.0).is_none());
+        assert!(super::cagr(100.0, 150.0, 0.0).is_none());
+    }
+}
@@ -0,0 +1,89 @@
+use erfurt::candle::CandlesExt;
+use qualsdorf::{Indicator, ReturnExt};
+
+/// Rolling gain-to-pain ratio, the sum of the last `freq` returns over the absolute sum of
+/// their losses.
+///
+/// A window without any losing period has no value.
+#[derive(Debug)]
+pub struct GainToPain {
+    pub freq: usize,
+    pub input: Vec<f64>,
+    pub values: Vec<Option<f64>>,
+}
+
+impl GainToPain {
+    #[must_use]
+    pub fn new(freq: usize) -> Self {
+        Self {
+            freq,
+            input: Vec::with_capacity(freq),
+            values: Vec::with_capacity(freq),
+        }
+    }
+}
+
+impl Indicator for GainToPain {
+    type Input = f64;
+    type Output = f64;
+
+    fn feed(&mut self, ret: Self::Input) {
+        self.input.push(ret);
+
+        if self.input.len() >= self.freq && self.freq > 0 {
+            let window = &self.input[self.input.len() - self.freq..];
+            let pain = window
+                .iter()
+                .filter(|&&ret| ret < 0.0)
+                .map(|ret| -ret)
+                .sum::<f64>();
+            self.values
+                .push((pain > 0.0).then(|| window.iter().sum::<f64>() / pain));
+        } else {
+            self.values.push(None);
+        }
+    }
+
+    fn last(&self) -> Option<&Self::Output> {
+        self.values.last().and_then(|v| v.as_ref())
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = Option<&Self::Output>> + '_> {
+        Box::new(self.values.iter().map(Option::as_ref))
+    }
+}
+
+pub trait GainToPainExt: ReturnExt {
+    /// Gain-to-pain ratio of the last `freq` returns.
+    fn gain_to_pain(&self, freq: usize) -> Option<f64> {
+        let mut indicator = GainToPain::new(freq);
+        self.ret()?.into_iter().for_each(|v| indicator.feed(v));
+        indicator.last().copied()
+    }
+}
+
+impl<T> GainToPainExt for T where T: CandlesExt {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::indicators::fixtures::XS;
+
+    #[test]
+    fn gain_to_pain() {
+        let mut indicator = GainToPain::new(6);
+        XS.iter().for_each(|&x| indicator.feed(x));
+        assert_eq!(indicator.values.len(), XS.len());
+        assert!(indicator.values[..5].iter().all(Option::is_none));
+        assert!((indicator.values[5].unwrap() - 0.714_285_714_285_714_2).abs() < 1e-9);
+        assert!((indicator.values[6].unwrap() + 0.052_631_578_947_368_47).abs() < 1e-9);
+        assert!((indicator.last().unwrap() - 0.25).abs() < 1e-9);
+    }
+
+    #[test]
+    fn gain_to_pain_without_losses() {
+        let mut indicator = GainToPain::new(3);
+        [0.01, 0.02, 0.0].iter().for_each(|&x| indicator.feed(x));
+        assert!(indicator.last().is_none());
+    }
+}
@@ -0,0 +1,182 @@
+use chrono::Duration;
+use erfurt::candle::Candles;
+use serde::{Deserialize, Serialize};
+
+/// How `fill_gaps` treats missing candles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum FillMethod {
+    /// Repeats the last close.
+    ForwardFill,
+    /// Interpolates linearly between the closes around the gap.
+    Linear,
+    /// Drops everything before the most recent gap.
+    Drop,
+}
+
+/// Candle of the filled series, either an existing one or the `step`th one after `prev`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Slot {
+    Candle(usize),
+    Fill {
+        prev: usize,
+        step: usize,
+        weight: f64,
+    },
+}
+
+/// Lays out the filled series from the `distances` between consecutive candles, measured in
+/// intervals. Distances are rounded, so months of different lengths still count as one step.
+fn plan(distances: &[f64], method: FillMethod) -> Vec<Slot> {
+    let mut slots = vec![Slot::Candle(0)];
+    for (prev, &distance) in distances.iter().enumerate() {
+        let missing = (distance.round() as usize).saturating_sub(1);
+        if missing > 0 {
+            match method {
+                FillMethod::Drop => slots.clear(),
+                FillMethod::ForwardFill | FillMethod::Linear => {
+                    slots.extend((1..=missing).map(|step| Slot::Fill {
+                        prev,
+                        step,
+                        weight: step as f64 / (missing + 1) as f64,
+                    }));
+                }
+            }
+        }
+        slots.push(Slot::Candle(prev + 1));
+    }
+    slots
+}
+
+/// Values of `xs` laid out in `slots`, a filled slot taking the filled `close`.
+fn fill(xs: &[f64], close: &[f64], slots: &[Slot], method: FillMethod) -> Vec<f64> {
+    slots
+        .iter()
+        .map(|slot| match *slot {
+            Slot::Candle(i) => xs[i],
+            Slot::Fill { prev, weight, .. } => match method {
+                FillMethod::Linear => (close[prev + 1] - close[prev]).mul_add(weight, close[prev]),
+                FillMethod::ForwardFill | FillMethod::Drop => close[prev],
+            },
+        })
+        .collect()
+}
+
+pub trait FillGapsExt {
+    fn fill_gaps(&self, interval: Duration, method: FillMethod) -> Candles;
+}
+
+impl FillGapsExt for Candles {
+    /// Evenly spaced copy of the candles, one every `interval`.
+    ///
+    /// A filled candle has its open, high, low and close all set to the filled close, and a
+    /// volume of `0.0` as nothing traded. Candles without volume stay without it.
+    fn fill_gaps(&self, interval: Duration, method: FillMethod) -> Candles {
+        if self.time.is_empty() || interval <= Duration::zero() {
+            return self.clone();
+        }
+        let seconds = interval.num_seconds() as f64;
+        let distances = self
+            .time
+            .windows(2)
+            .map(|w| (w[1] - w[0]).num_seconds() as f64 / seconds)
+            .collect::<Vec<_>>();
+        let slots = plan(&distances, method);
+        let pick = |xs: &[f64]| fill(xs, &self.close, &slots, method);
+
+        let mut filled = self.clone();
+        filled.time = slots
+            .iter()
+            .map(|slot| match *slot {
+                Slot::Candle(i) => self.time[i],
+                Slot::Fill { prev, step, .. } => self.time[prev] + interval * step as i32,
+            })
+            .collect();
+        filled.open = pick(&self.open);
+        filled.high = pick(&self.high);
+        filled.low = pick(&self.low);
+        filled.close = pick(&self.close);
+        filled.volume = self.volume.as_deref().map(|volume| {
+            slots
+                .iter()
+                .map(|slot| match *slot {
+                    Slot::Candle(i) => volume[i],
+                    Slot::Fill { .. } => 0.0,
+                })
+                .collect()
+        });
+        filled
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn plan_without_gaps() {
+        let slots = plan(&[1.0, 0.97, 1.03], FillMethod::Linear);
+        assert_eq!(
+            slots,
+            vec![
+                Slot::Candle(0),
+                Slot::Candle(1),
+                Slot::Candle(2),
+                Slot::Candle(3)
+            ]
+        );
+    }
+
+    #[test]
+    fn plan_fills_gaps() {
+        let slots = plan(&[1.0, 3.0], FillMethod::ForwardFill);
+        assert_eq!(
+            slots,
+            vec![
+                Slot::Candle(0),
+                Slot::Candle(1),
+                Slot::Fill {
+                    prev: 1,
+                    step: 1,
+                    weight: 1.0 / 3.0
+                },
+                Slot::Fill {
+                    prev: 1,
+                    step: 2,
+                    weight: 2.0 / 3.0
+                },
+                Slot::Candle(2),
+            ]
+        );
+    }
+
+    #[test]
+    fn forward_filled_closes() {
+        let close = [10.0, 20.0, 50.0];
+        let slots = plan(&[1.0, 3.0], FillMethod::ForwardFill);
+        let filled = fill(&close, &close, &slots, FillMethod::ForwardFill);
+        assert_eq!(filled, vec![10.0, 20.0, 20.0, 20.0, 50.0]);
+        // The other prices of a filled candle take its close too.
+        let open = [9.0, 19.0, 45.0];
+        let filled = fill(&open, &close, &slots, FillMethod::ForwardFill);
+        assert_eq!(filled, vec![9.0, 19.0, 20.0, 20.0, 45.0]);
+    }
+
+    #[test]
+    fn linear_filled_closes() {
+        let close = [10.0, 20.0, 50.0];
+        let slots = plan(&[1.0, 3.0], FillMethod::Linear);
+        let filled = fill(&close, &close, &slots, FillMethod::Linear);
+        let expected = [10.0, 20.0, 30.0, 40.0, 50.0];
+        assert_eq!(filled.len(), expected.len());
+        assert!(filled
+            .iter()
+            .zip(expected)
+            .all(|(x, expected)| (x - expected).abs() < 1e-9));
+    }
+
+    #[test]
+    fn plan_drops_before_last_gap() {
+        let slots = plan(&[2.0, 1.0, 2.0, 1.0], FillMethod::Drop);
+        assert_eq!(slots, vec![Slot::Candle(3), Slot::Candle(4)]);
+    }
+}
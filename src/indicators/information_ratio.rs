@@ -0,0 +1,96 @@
+use erfurt::candle::Candles;
+use qualsdorf::Indicator;
+use statrs::statistics::Statistics;
+
+use super::aligned_returns;
+
+/// Rolling mean active return divided by the tracking error over the same window.
+#[derive(Debug)]
+pub struct InformationRatio {
+    pub freq: usize,
+    pub input: Vec<f64>,
+    pub values: Vec<Option<f64>>,
+}
+
+impl InformationRatio {
+    #[must_use]
+    pub fn new(freq: usize) -> Self {
+        Self {
+            freq,
+            input: Vec::with_capacity(freq),
+            values: Vec::with_capacity(freq),
+        }
+    }
+}
+
+impl Indicator for InformationRatio {
+    type Input = (f64, f64);
+    type Output = f64;
+
+    fn feed(&mut self, (ret, benchmark): Self::Input) {
+        self.input.push(ret - benchmark);
+
+        if self.input.len() >= self.freq {
+            let active = &self.input[self.input.len() - self.freq..];
+            let tracking_error = active.iter().std_dev();
+            // A zero tracking error means the asset mirrors the benchmark exactly.
+            let value = (tracking_error > 0.0).then(|| active.iter().mean() / tracking_error);
+            self.values.push(value);
+        } else {
+            self.values.push(None);
+        }
+    }
+
+    fn last(&self) -> Option<&Self::Output> {
+        self.values.last().and_then(|v| v.as_ref())
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = Option<&Self::Output>> + '_> {
+        Box::new(self.values.iter().map(Option::as_ref))
+    }
+}
+
+pub trait InformationRatioExt {
+    fn information_ratio(&self, benchmark: &Candles, freq: usize) -> Option<InformationRatio>;
+}
+
+impl InformationRatioExt for Candles {
+    fn information_ratio(&self, benchmark: &Candles, freq: usize) -> Option<InformationRatio> {
+        let (ret, benchmark_ret) = aligned_returns(self, benchmark)?;
+        if ret.len() < freq {
+            return None;
+        }
+        let mut indicator = InformationRatio::new(freq);
+        ret.into_iter()
+            .zip(benchmark_ret)
+            .for_each(|v| indicator.feed(v));
+        Some(indicator)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::indicators::fixtures::{BS, XS};
+
+    #[test]
+    fn information_ratio() {
+        let mut indicator = InformationRatio::new(6);
+        XS.iter().zip(BS).for_each(|(&x, b)| indicator.feed((x, b)));
+        assert_eq!(indicator.values.len(), XS.len());
+        assert!(indicator.values[..5].iter().all(Option::is_none));
+        let first = indicator.values[5].unwrap();
+        assert!((first - 0.089_359_831_618_212_59).abs() < 1e-9);
+        // The last window's active returns average 0.001 with a tracking error of
+        // sqrt(0.000232 / 5).
+        let value = *indicator.last().unwrap();
+        assert!((value - 0.001 / (0.000_232_f64 / 5.0).sqrt()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn information_ratio_of_benchmark() {
+        let mut indicator = InformationRatio::new(3);
+        BS.iter().zip(BS).for_each(|(&x, b)| indicator.feed((x, b)));
+        assert!(indicator.last().is_none());
+    }
+}
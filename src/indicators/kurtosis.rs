@@ -0,0 +1,84 @@
+use erfurt::candle::CandlesExt;
+use qualsdorf::{Indicator, ReturnExt};
+use statrs::statistics::Statistics;
+
+/// Rolling sample excess kurtosis of returns.
+#[derive(Debug)]
+pub struct Kurtosis {
+    pub freq: usize,
+    pub input: Vec<f64>,
+    pub values: Vec<Option<f64>>,
+}
+
+impl Kurtosis {
+    #[must_use]
+    pub fn new(freq: usize) -> Self {
+        Self {
+            freq,
+            input: Vec::with_capacity(freq),
+            values: Vec::with_capacity(freq),
+        }
+    }
+}
+
+impl Indicator for Kurtosis {
+    type Input = f64;
+    type Output = f64;
+
+    fn feed(&mut self, ret: Self::Input) {
+        self.input.push(ret);
+
+        // The sample formula needs at least four observations.
+        if self.input.len() >= self.freq && self.freq >= 4 {
+            let window = &self.input[self.input.len() - self.freq..];
+            let n = window.len() as f64;
+            let mean = window.iter().mean();
+            let std_dev = window.iter().std_dev();
+            let value = (std_dev > 0.0).then(|| {
+                let sum: f64 = window.iter().map(|x| ((x - mean) / std_dev).powi(4)).sum();
+                let a = n * (n + 1.0) / ((n - 1.0) * (n - 2.0) * (n - 3.0));
+                let b = 3.0 * (n - 1.0).powi(2) / ((n - 2.0) * (n - 3.0));
+                a.mul_add(sum, -b)
+            });
+            self.values.push(value);
+        } else {
+            self.values.push(None);
+        }
+    }
+
+    fn last(&self) -> Option<&Self::Output> {
+        self.values.last().and_then(|v| v.as_ref())
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = Option<&Self::Output>> + '_> {
+        Box::new(self.values.iter().map(Option::as_ref))
+    }
+}
+
+pub trait KurtosisExt: ReturnExt {
+    fn kurtosis(&self, freq: usize) -> Option<Kurtosis> {
+        let mut indicator = Kurtosis::new(freq);
+        self.ret().map(|ret| {
+            ret.into_iter().for_each(|v| indicator.feed(v));
+            indicator
+        })
+    }
+}
+
+impl<T> KurtosisExt for T where T: CandlesExt {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::indicators::fixtures::XS;
+
+    #[test]
+    fn kurtosis() {
+        let mut indicator = Kurtosis::new(6);
+        XS.iter().for_each(|&x| indicator.feed(x));
+        assert_eq!(indicator.values.len(), XS.len());
+        assert!(indicator.values[..5].iter().all(Option::is_none));
+        let value = *indicator.last().unwrap();
+        assert!((value - -0.772_387_291_265_302_7).abs() < 1e-9);
+    }
+}
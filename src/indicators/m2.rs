@@ -0,0 +1,109 @@
+use erfurt::candle::Candles;
+use qualsdorf::Indicator;
+use statrs::statistics::Statistics;
+
+use super::{aligned_returns, RiskFree};
+
+/// Rolling Modigliani risk-adjusted performance, the Sharpe ratio of the asset rescaled to
+/// the volatility of the benchmark, `risk_free + sharpe * benchmark_std_dev`.
+///
+/// The Sharpe ratio and the benchmark volatility come from the same window of returns, and
+/// `risk_free` is the per-period rate, so the value is a return per period.
+#[derive(Debug)]
+pub struct M2 {
+    pub freq: usize,
+    pub risk_free: RiskFree,
+    pub input: Vec<(f64, f64)>,
+    pub values: Vec<Option<f64>>,
+}
+
+impl M2 {
+    #[must_use]
+    pub fn new(freq: usize, risk_free: impl Into<RiskFree>) -> Self {
+        Self {
+            freq,
+            risk_free: risk_free.into(),
+            input: Vec::with_capacity(freq),
+            values: Vec::with_capacity(freq),
+        }
+    }
+}
+
+impl Indicator for M2 {
+    type Input = (f64, f64);
+    type Output = f64;
+
+    fn feed(&mut self, value: Self::Input) {
+        self.input.push(value);
+
+        if self.input.len() >= self.freq && self.freq >= 2 {
+            let start = self.input.len() - self.freq;
+            let window = &self.input[start..];
+            let risk_free = self.risk_free.mean(start..self.input.len());
+            let rets = window.iter().map(|(ret, _)| *ret).collect::<Vec<_>>();
+            let benchmark = window.iter().map(|(_, ret)| *ret).collect::<Vec<_>>();
+            let std_dev = rets.iter().std_dev();
+            let value = (std_dev > 0.0).then(|| {
+                let sharpe = (rets.iter().mean() - risk_free) / std_dev;
+                sharpe.mul_add(benchmark.iter().std_dev(), risk_free)
+            });
+            self.values.push(value);
+        } else {
+            self.values.push(None);
+        }
+    }
+
+    fn last(&self) -> Option<&Self::Output> {
+        self.values.last().and_then(|v| v.as_ref())
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = Option<&Self::Output>> + '_> {
+        Box::new(self.values.iter().map(Option::as_ref))
+    }
+}
+
+pub trait M2Ext {
+    /// M2 over the last `freq` returns both series have, `None` for a flat asset.
+    fn m2(&self, benchmark: &Candles, freq: usize, risk_free: impl Into<RiskFree>) -> Option<f64>;
+}
+
+impl M2Ext for Candles {
+    fn m2(&self, benchmark: &Candles, freq: usize, risk_free: impl Into<RiskFree>) -> Option<f64> {
+        let (ret, benchmark_ret) = aligned_returns(self, benchmark)?;
+        if ret.len() < freq {
+            return None;
+        }
+        let mut indicator = M2::new(freq, risk_free);
+        ret.into_iter()
+            .zip(benchmark_ret)
+            .for_each(|v| indicator.feed(v));
+        indicator.last().copied()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::indicators::fixtures::{BS, XS};
+
+    #[test]
+    fn m2() {
+        let mut indicator = M2::new(6, 0.001);
+        XS.iter().zip(BS).for_each(|(&x, b)| indicator.feed((x, b)));
+        assert_eq!(indicator.values.len(), XS.len());
+        assert!(indicator.values[..5].iter().all(Option::is_none));
+        let first = indicator.values[5].unwrap();
+        assert!((first - 0.004_189_245_234_829_653).abs() < 1e-9);
+        let value = *indicator.last().unwrap();
+        assert!((value - 0.001_568_220_522_059_707).abs() < 1e-9);
+    }
+
+    #[test]
+    fn m2_flat_asset() {
+        let mut indicator = M2::new(3, 0.0);
+        [(0.01, 0.02), (0.01, -0.01), (0.01, 0.0)]
+            .iter()
+            .for_each(|&v| indicator.feed(v));
+        assert!(indicator.last().is_none());
+    }
+}
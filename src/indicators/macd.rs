@@ -0,0 +1,133 @@
+use erfurt::candle::Candles;
+use qualsdorf::Indicator;
+
+use super::moving_average::Ema;
+
+/// Moving Average Convergence Divergence of the closes.
+///
+/// `line` is the `fast` EMA minus the `slow` EMA, `signal_line` is the `signal` EMA of the
+/// line and the indicator value is the histogram, line minus signal. Every EMA is seeded
+/// with the simple mean of its first values, so all three series stay aligned with the
+/// input and are `None` until they have enough data.
+#[derive(Debug)]
+pub struct Macd {
+    pub fast: usize,
+    pub slow: usize,
+    pub signal: usize,
+    pub input: Vec<f64>,
+    fast_ema: Ema,
+    slow_ema: Ema,
+    signal_ema: Ema,
+    pub line: Vec<Option<f64>>,
+    pub signal_line: Vec<Option<f64>>,
+    pub values: Vec<Option<f64>>,
+}
+
+impl Macd {
+    #[must_use]
+    pub fn new(fast: usize, slow: usize, signal: usize) -> Self {
+        Self {
+            fast,
+            slow,
+            signal,
+            input: Vec::with_capacity(slow),
+            fast_ema: Ema::new(fast),
+            slow_ema: Ema::new(slow),
+            signal_ema: Ema::new(signal),
+            line: Vec::with_capacity(slow),
+            signal_line: Vec::with_capacity(slow),
+            values: Vec::with_capacity(slow),
+        }
+    }
+}
+
+impl Indicator for Macd {
+    type Input = f64;
+    type Output = f64;
+
+    fn feed(&mut self, close: Self::Input) {
+        self.input.push(close);
+        self.fast_ema.feed(close);
+        self.slow_ema.feed(close);
+
+        let line = self
+            .fast_ema
+            .last()
+            .zip(self.slow_ema.last())
+            .map(|(fast, slow)| fast - slow);
+        let signal = line.and_then(|line| {
+            self.signal_ema.feed(line);
+            self.signal_ema.last().copied()
+        });
+        self.line.push(line);
+        self.signal_line.push(signal);
+        self.values
+            .push(line.zip(signal).map(|(line, signal)| line - signal));
+    }
+
+    fn last(&self) -> Option<&Self::Output> {
+        self.values.last().and_then(|v| v.as_ref())
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = Option<&Self::Output>> + '_> {
+        Box::new(self.values.iter().map(Option::as_ref))
+    }
+}
+
+pub trait MacdExt {
+    fn macd(&self, fast: usize, slow: usize, signal: usize) -> Option<Macd>;
+}
+
+impl MacdExt for Candles {
+    fn macd(&self, fast: usize, slow: usize, signal: usize) -> Option<Macd> {
+        if self.close.is_empty() {
+            return None;
+        }
+        let mut indicator = Macd::new(fast, slow, signal);
+        self.close.iter().for_each(|&close| indicator.feed(close));
+        Some(indicator)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    static CLOSES: [f64; 12] = [
+        10.0, 10.5, 10.2, 10.8, 11.1, 10.9, 11.4, 11.0, 11.6, 12.0, 11.7, 12.3,
+    ];
+
+    #[test]
+    fn macd() {
+        let mut indicator = Macd::new(3, 6, 3);
+        CLOSES.iter().for_each(|&x| indicator.feed(x));
+        assert_eq!(indicator.values.len(), CLOSES.len());
+        assert!(indicator.line[..5].iter().all(Option::is_none));
+        assert!(indicator.line[5].is_some());
+        assert!(indicator.signal_line[..7].iter().all(Option::is_none));
+        assert!(indicator.signal_line[7].is_some());
+        let line = indicator.line.last().unwrap().unwrap();
+        assert!((line - 0.303_027_055_164_266_2).abs() < 1e-9);
+        let signal = indicator.signal_line.last().unwrap().unwrap();
+        assert!((signal - 0.283_889_337_972_190_4).abs() < 1e-9);
+    }
+
+    #[test]
+    fn macd_histogram() {
+        let mut indicator = Macd::new(3, 6, 3);
+        CLOSES.iter().for_each(|&x| indicator.feed(x));
+        for ((line, signal), histogram) in indicator
+            .line
+            .iter()
+            .zip(indicator.signal_line.iter())
+            .zip(indicator.values.iter())
+        {
+            match (line, signal, histogram) {
+                (Some(line), Some(signal), Some(histogram)) => {
+                    assert!((histogram - (line - signal)).abs() < 1e-12);
+                }
+                (_, _, histogram) => assert!(histogram.is_none()),
+            }
+        }
+    }
+}
@@ -0,0 +1,266 @@
+use std::{cmp::Ordering, collections::BTreeMap, ops::Range};
+
+use chrono::{DateTime, NaiveDate, Utc};
+use erfurt::candle::Candles;
+use serde::{Deserialize, Serialize};
+
+pub mod alpha;
+pub mod annualized_return;
+pub mod annualized_risk;
+pub mod atr;
+pub mod average_drawdown_n;
+pub mod beta;
+pub mod bollinger;
+pub mod burke_ratio;
+pub mod cagr;
+pub mod gain_to_pain;
+pub mod gaps;
+pub mod information_ratio;
+pub mod kurtosis;
+pub mod m2;
+pub mod macd;
+pub mod moving_average;
+pub mod pain_index;
+pub mod rsi_cross;
+pub mod skewness;
+pub mod sterling_ratio;
+pub mod tracking_error;
+pub mod treynor_ratio;
+pub mod upside_potential_ratio;
+pub mod vwap;
+
+/// Simple returns `y / x - 1` of consecutive prices.
+///
+/// A step from or to a price that is zero, negative or not finite has a return of `0.0`,
+/// so a single glitched candle can't turn every downstream statistic into `NaN` or `inf`.
+#[must_use]
+pub fn simple_returns(prices: &[f64]) -> Vec<f64> {
+    prices
+        .windows(2)
+        .map(|w| {
+            let valid = |x: f64| x.is_finite() && x > 0.0;
+            if valid(w[0]) && valid(w[1]) {
+                w[1] / w[0] - 1.0
+            } else {
+                0.0
+            }
+        })
+        .collect()
+}
+
+/// Log returns `ln(y / x)` of consecutive prices, guarded like [`simple_returns`].
+#[must_use]
+pub fn log_returns(prices: &[f64]) -> Vec<f64> {
+    simple_returns(prices).into_iter().map(f64::ln_1p).collect()
+}
+
+pub trait LogReturnExt {
+    fn log_ret(&self) -> Option<Vec<f64>>;
+}
+
+impl LogReturnExt for Candles {
+    fn log_ret(&self) -> Option<Vec<f64>> {
+        (self.close.len() >= 2).then(|| log_returns(&self.close))
+    }
+}
+
+/// Simple returns of `candles` and `benchmark` over the dates both series share.
+///
+/// Candles whose timestamp is missing from the other series are skipped, so the
+/// two returned vectors always have the same length. Returns `None` when fewer
+/// than two common dates exist.
+#[must_use]
+pub fn aligned_returns(candles: &Candles, benchmark: &Candles) -> Option<(Vec<f64>, Vec<f64>)> {
+    let mut xs = Vec::new();
+    let mut ys = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < candles.time.len() && j < benchmark.time.len() {
+        match candles.time[i].cmp(&benchmark.time[j]) {
+            Ordering::Less => i += 1,
+            Ordering::Greater => j += 1,
+            Ordering::Equal => {
+                xs.push(candles.close[i]);
+                ys.push(benchmark.close[j]);
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    (xs.len() >= 2).then(|| (simple_returns(&xs), simple_returns(&ys)))
+}
+
+/// Magnitudes of the continuous drawdowns in `rets`, one per run of consecutive negative
+/// returns, compounded over the run.
+#[must_use]
+pub fn continuous_drawdowns(rets: &[f64]) -> Vec<f64> {
+    let mut drawdowns = Vec::new();
+    let mut wealth = 1.0;
+    for &ret in rets {
+        if ret < 0.0 {
+            wealth *= 1.0 + ret;
+        } else if wealth < 1.0 {
+            drawdowns.push(1.0 - wealth);
+            wealth = 1.0;
+        }
+    }
+    if wealth < 1.0 {
+        drawdowns.push(1.0 - wealth);
+    }
+    drawdowns
+}
+
+/// Risk free rate earned over one period, the span of a single return, either fixed or one
+/// rate per return.
+///
+/// A `Series` is indexed like the returns the indicator is fed, from the oldest one, so the
+/// benchmark-relative indicators index it by the returns of the dates both series share.
+/// Returns past its end reuse its last rate and an empty series is a rate of `0.0`. Ratios
+/// quoted per year scale the rates up themselves.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum RiskFree {
+    Constant(f64),
+    Series(Vec<f64>),
+}
+
+impl Default for RiskFree {
+    fn default() -> Self {
+        Self::Constant(0.0)
+    }
+}
+
+impl From<f64> for RiskFree {
+    fn from(rate: f64) -> Self {
+        Self::Constant(rate)
+    }
+}
+
+impl RiskFree {
+    /// Rates of the returns between `times`, each the last one in `rates` dated on or before
+    /// the end of the return. Returns older than every rate take the oldest one.
+    #[must_use]
+    pub fn dated(rates: &BTreeMap<NaiveDate, f64>, times: &[DateTime<Utc>]) -> Self {
+        let Some((_, &oldest)) = rates.first_key_value() else {
+            return Self::default();
+        };
+        Self::Series(
+            times
+                .iter()
+                .skip(1)
+                .map(|time| {
+                    rates
+                        .range(..=time.date_naive())
+                        .next_back()
+                        .map_or(oldest, |(_, rate)| *rate)
+                })
+                .collect(),
+        )
+    }
+
+    /// Rate of the `i`th return.
+    #[must_use]
+    pub fn rate(&self, i: usize) -> f64 {
+        match self {
+            Self::Constant(rate) => *rate,
+            Self::Series(rates) => rates.get(i).or(rates.last()).copied().unwrap_or(0.0),
+        }
+    }
+
+    /// Mean rate over the returns in `window`, the rate of its start when it's empty.
+    #[must_use]
+    pub fn mean(&self, window: Range<usize>) -> f64 {
+        if window.is_empty() {
+            return self.rate(window.start);
+        }
+        let len = window.len() as f64;
+        window.map(|i| self.rate(i)).sum::<f64>() / len
+    }
+
+    /// Rate compounded over the returns in `window`.
+    #[must_use]
+    pub fn compounded(&self, window: Range<usize>) -> f64 {
+        window.map(|i| 1.0 + self.rate(i)).product::<f64>() - 1.0
+    }
+}
+
+/// Returns shared by the indicator tests, `BS` being a benchmark for `XS`.
+#[cfg(test)]
+pub(crate) mod fixtures {
+    pub static XS: [f64; 12] = [
+        0.012, -0.034, 0.021, 0.008, -0.015, 0.043, -0.027, 0.019, 0.005, -0.011, 0.031, -0.006,
+    ];
+
+    pub static BS: [f64; 12] = [
+        0.010, -0.020, 0.015, 0.005, -0.010, 0.030, -0.020, 0.012, 0.004, -0.008, 0.020, -0.003,
+    ];
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn risk_free_mean() {
+        assert!((RiskFree::from(0.02).mean(3..9) - 0.02).abs() < 1e-12);
+        let series = RiskFree::Series(vec![0.01, 0.02, 0.03]);
+        assert!((series.mean(0..2) - 0.015).abs() < 1e-12);
+        assert!((series.mean(1..5) - 0.0275).abs() < 1e-12);
+        assert!((series.mean(4..4) - 0.03).abs() < 1e-12);
+        assert!(RiskFree::Series(Vec::new()).mean(0..3).abs() < 1e-12);
+    }
+
+    #[test]
+    fn risk_free_compounded() {
+        assert!((RiskFree::from(0.1).compounded(0..2) - 0.21).abs() < 1e-12);
+        let series = RiskFree::Series(vec![0.1, 0.2]);
+        assert!((series.compounded(0..3) - 0.584).abs() < 1e-12);
+        assert!(series.compounded(1..1).abs() < 1e-12);
+    }
+
+    #[test]
+    fn risk_free_dated() {
+        let date = |m| NaiveDate::from_ymd_opt(2024, m, 1).unwrap();
+        let time = |m| Utc.with_ymd_and_hms(2024, m, 1, 0, 0, 0).unwrap();
+        let rates = BTreeMap::from([(date(2), 0.01), (date(4), 0.02)]);
+        let times = [1, 2, 3, 4, 5].map(time);
+        assert_eq!(
+            RiskFree::dated(&rates, &times),
+            RiskFree::Series(vec![0.01, 0.01, 0.02, 0.02])
+        );
+        assert_eq!(
+            RiskFree::dated(&rates, &times[..1]),
+            RiskFree::Series(Vec::new())
+        );
+        assert_eq!(
+            RiskFree::dated(&BTreeMap::new(), &times),
+            RiskFree::default()
+        );
+    }
+
+    #[test]
+    fn simple_returns_guard_bad_prices() {
+        let rets = simple_returns(&[10.0, 11.0, 0.0, 12.0, f64::NAN, -1.0, 12.0, 6.0]);
+        assert_eq!(rets.len(), 7);
+        assert!((rets[0] - 0.1).abs() < 1e-12);
+        assert!(rets[1..6].iter().all(|ret| ret.abs() < f64::EPSILON));
+        assert!((rets[6] + 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn log_returns_guard_bad_prices() {
+        let rets = log_returns(&[10.0, 20.0, 0.0, 5.0]);
+        assert!((rets[0] - 2.0_f64.ln()).abs() < 1e-12);
+        assert!(rets[1..].iter().all(|ret| ret.abs() < f64::EPSILON));
+    }
+
+    #[test]
+    fn continuous_drawdowns_compound_negative_runs() {
+        let drawdowns = continuous_drawdowns(&[0.01, -0.1, -0.1, 0.02, -0.05, 0.0, -0.02]);
+        assert_eq!(drawdowns.len(), 3);
+        assert!((drawdowns[0] - 0.19).abs() < 1e-12);
+        assert!((drawdowns[1] - 0.05).abs() < 1e-12);
+        assert!((drawdowns[2] - 0.02).abs() < 1e-12);
+        assert!(continuous_drawdowns(&[0.01, 0.02]).is_empty());
+    }
+}
@@ -0,0 +1,154 @@
+use erfurt::candle::Candles;
+use qualsdorf::Indicator;
+
+/// Rolling simple moving average over `freq` values.
+#[derive(Debug)]
+pub struct Sma {
+    pub freq: usize,
+    pub input: Vec<f64>,
+    pub values: Vec<Option<f64>>,
+}
+
+impl Sma {
+    #[must_use]
+    pub fn new(freq: usize) -> Self {
+        Self {
+            freq,
+            input: Vec::with_capacity(freq),
+            values: Vec::with_capacity(freq),
+        }
+    }
+}
+
+impl Indicator for Sma {
+    type Input = f64;
+    type Output = f64;
+
+    fn feed(&mut self, x: Self::Input) {
+        self.input.push(x);
+
+        if self.input.len() >= self.freq && self.freq > 0 {
+            let window = &self.input[self.input.len() - self.freq..];
+            self.values
+                .push(Some(window.iter().sum::<f64>() / self.freq as f64));
+        } else {
+            self.values.push(None);
+        }
+    }
+
+    fn last(&self) -> Option<&Self::Output> {
+        self.values.last().and_then(|v| v.as_ref())
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = Option<&Self::Output>> + '_> {
+        Box::new(self.values.iter().map(Option::as_ref))
+    }
+}
+
+/// Exponential moving average over `freq` values with `alpha = 2 / (freq + 1)`.
+///
+/// The first value is the simple mean of the first `freq` values.
+#[derive(Debug)]
+pub struct Ema {
+    pub freq: usize,
+    pub input: Vec<f64>,
+    pub values: Vec<Option<f64>>,
+}
+
+impl Ema {
+    #[must_use]
+    pub fn new(freq: usize) -> Self {
+        Self {
+            freq,
+            input: Vec::with_capacity(freq),
+            values: Vec::with_capacity(freq),
+        }
+    }
+}
+
+impl Indicator for Ema {
+    type Input = f64;
+    type Output = f64;
+
+    fn feed(&mut self, x: Self::Input) {
+        self.input.push(x);
+
+        let n = self.freq as f64;
+        let value = match self.values.last() {
+            Some(Some(prev)) => Some((x - prev).mul_add(2.0 / (n + 1.0), *prev)),
+            _ if self.freq > 0 && self.input.len() == self.freq => {
+                Some(self.input.iter().sum::<f64>() / n)
+            }
+            _ => None,
+        };
+        self.values.push(value);
+    }
+
+    fn last(&self) -> Option<&Self::Output> {
+        self.values.last().and_then(|v| v.as_ref())
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = Option<&Self::Output>> + '_> {
+        Box::new(self.values.iter().map(Option::as_ref))
+    }
+}
+
+pub trait SmaExt {
+    fn sma(&self, freq: usize) -> Option<Sma>;
+}
+
+impl SmaExt for Candles {
+    fn sma(&self, freq: usize) -> Option<Sma> {
+        if self.close.is_empty() {
+            return None;
+        }
+        let mut indicator = Sma::new(freq);
+        self.close.iter().for_each(|&close| indicator.feed(close));
+        Some(indicator)
+    }
+}
+
+pub trait EmaExt {
+    fn ema(&self, freq: usize) -> Option<Ema>;
+}
+
+impl EmaExt for Candles {
+    fn ema(&self, freq: usize) -> Option<Ema> {
+        if self.close.is_empty() {
+            return None;
+        }
+        let mut indicator = Ema::new(freq);
+        self.close.iter().for_each(|&close| indicator.feed(close));
+        Some(indicator)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    static CLOSES: [f64; 12] = [
+        10.0, 10.5, 10.2, 10.8, 11.1, 10.9, 11.4, 11.0, 11.6, 12.0, 11.7, 12.3,
+    ];
+
+    #[test]
+    fn sma() {
+        let mut indicator = Sma::new(3);
+        CLOSES.iter().for_each(|&x| indicator.feed(x));
+        assert_eq!(indicator.values.len(), CLOSES.len());
+        assert!(indicator.values[..2].iter().all(Option::is_none));
+        assert!((indicator.values[2].unwrap() - 10.233_333_333_333_333).abs() < 1e-9);
+        assert!((indicator.last().unwrap() - 12.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn ema() {
+        let mut indicator = Ema::new(3);
+        CLOSES.iter().for_each(|&x| indicator.feed(x));
+        assert_eq!(indicator.values.len(), CLOSES.len());
+        assert!(indicator.values[..2].iter().all(Option::is_none));
+        assert!((indicator.values[2].unwrap() - 10.233_333_333_333_333).abs() < 1e-9);
+        assert!((indicator.values[3].unwrap() - 10.516_666_666_666_666).abs() < 1e-9);
+        assert!((indicator.last().unwrap() - 11.991_471_354_166_666).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,162 @@
+use erfurt::candle::CandlesExt;
+use qualsdorf::{Indicator, ReturnExt};
+use statrs::statistics::Statistics;
+
+use super::RiskFree;
+
+/// Mean drawdown from the running peak of the wealth curve compounded from `rets`.
+fn mean_drawdown(rets: &[f64]) -> f64 {
+    let mut wealth = 1.0;
+    let mut peak = 1.0_f64;
+    rets.iter()
+        .map(|ret| {
+            wealth *= 1.0 + ret;
+            peak = peak.max(wealth);
+            1.0 - wealth / peak
+        })
+        .mean()
+}
+
+/// Rolling Pain Index, the mean drawdown over the window.
+#[derive(Debug)]
+pub struct PainIndex {
+    pub freq: usize,
+    pub input: Vec<f64>,
+    pub values: Vec<Option<f64>>,
+}
+
+impl PainIndex {
+    #[must_use]
+    pub fn new(freq: usize) -> Self {
+        Self {
+            freq,
+            input: Vec::with_capacity(freq),
+            values: Vec::with_capacity(freq),
+        }
+    }
+}
+
+impl Indicator for PainIndex {
+    type Input = f64;
+    type Output = f64;
+
+    fn feed(&mut self, ret: Self::Input) {
+        self.input.push(ret);
+
+        if self.input.len() >= self.freq && self.freq > 0 {
+            let window = &self.input[self.input.len() - self.freq..];
+            self.values.push(Some(mean_drawdown(window)));
+        } else {
+            self.values.push(None);
+        }
+    }
+
+    fn last(&self) -> Option<&Self::Output> {
+        self.values.last().and_then(|v| v.as_ref())
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = Option<&Self::Output>> + '_> {
+        Box::new(self.values.iter().map(Option::as_ref))
+    }
+}
+
+/// Rolling Pain Ratio, mean excess return per period over the Pain Index.
+///
+/// `risk_free` is the risk free rate per period. A window without any drawdown has no
+/// value.
+#[derive(Debug)]
+pub struct PainRatio {
+    pub freq: usize,
+    pub risk_free: RiskFree,
+    pub input: Vec<f64>,
+    pub values: Vec<Option<f64>>,
+}
+
+impl PainRatio {
+    #[must_use]
+    pub fn new(freq: usize, risk_free: impl Into<RiskFree>) -> Self {
+        Self {
+            freq,
+            risk_free: risk_free.into(),
+            input: Vec::with_capacity(freq),
+            values: Vec::with_capacity(freq),
+        }
+    }
+}
+
+impl Indicator for PainRatio {
+    type Input = f64;
+    type Output = f64;
+
+    fn feed(&mut self, ret: Self::Input) {
+        self.input.push(ret);
+
+        if self.input.len() >= self.freq && self.freq > 0 {
+            let start = self.input.len() - self.freq;
+            let window = &self.input[start..];
+            let risk_free = self.risk_free.mean(start..self.input.len());
+            let pain = mean_drawdown(window);
+            let value = (pain > 0.0).then(|| (window.iter().mean() - risk_free) / pain);
+            self.values.push(value);
+        } else {
+            self.values.push(None);
+        }
+    }
+
+    fn last(&self) -> Option<&Self::Output> {
+        self.values.last().and_then(|v| v.as_ref())
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = Option<&Self::Output>> + '_> {
+        Box::new(self.values.iter().map(Option::as_ref))
+    }
+}
+
+pub trait PainIndexExt: ReturnExt {
+    /// Pain Index of the last `freq` returns.
+    fn pain_index(&self, freq: usize) -> Option<f64> {
+        let mut indicator = PainIndex::new(freq);
+        self.ret()?.into_iter().for_each(|v| indicator.feed(v));
+        indicator.last().copied()
+    }
+
+    /// Pain Ratio of the last `freq` returns, `None` when the window has no drawdown.
+    fn pain_ratio(&self, freq: usize, risk_free: impl Into<RiskFree>) -> Option<f64> {
+        let mut indicator = PainRatio::new(freq, risk_free);
+        self.ret()?.into_iter().for_each(|v| indicator.feed(v));
+        indicator.last().copied()
+    }
+}
+
+impl<T> PainIndexExt for T where T: CandlesExt {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::indicators::fixtures::XS;
+
+    #[test]
+    fn pain_index() {
+        let mut indicator = PainIndex::new(6);
+        XS.iter().for_each(|&x| indicator.feed(x));
+        assert_eq!(indicator.values.len(), XS.len());
+        assert!(indicator.values[..5].iter().all(Option::is_none));
+        let value = *indicator.last().unwrap();
+        assert!((value - 0.009_930_836_464_166_74).abs() < 1e-9);
+    }
+
+    #[test]
+    fn pain_ratio() {
+        let mut indicator = PainRatio::new(6, 0.001);
+        XS.iter().for_each(|&x| indicator.feed(x));
+        let value = *indicator.last().unwrap();
+        assert!((value - 0.083_913_710_223_729_43).abs() < 1e-9);
+    }
+
+    #[test]
+    fn pain_ratio_flat() {
+        let mut indicator = PainRatio::new(3, 0.0);
+        [0.01, 0.02, 0.0].iter().for_each(|&x| indicator.feed(x));
+        assert!(indicator.last().is_none());
+    }
+}
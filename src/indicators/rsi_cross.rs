@@ -0,0 +1,82 @@
+use erfurt::candle::Candles;
+use qualsdorf::{rsi::RsiExt, Indicator};
+use serde::{Deserialize, Serialize};
+
+/// Most recent RSI crossing of the oversold or overbought level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum CrossSignal {
+    /// RSI crossed up through the oversold level, an entry signal.
+    OversoldUp,
+    /// RSI crossed down through the overbought level, an exit signal.
+    OverboughtDown,
+}
+
+/// Finds the most recent crossing in the last `within` values of an RSI series.
+///
+/// `level` is the oversold level, the overbought one mirrors it at `100 - level`. Missing
+/// values break the series, so a cross is only reported between two consecutive values.
+/// Older crosses are stale and ignored, `within` of 1 only reports a cross on the last value.
+#[must_use]
+pub fn last_cross(rsi: &[Option<f64>], level: f64, within: usize) -> Option<CrossSignal> {
+    let overbought = 100.0 - level;
+    let start = rsi.len().saturating_sub(within.saturating_add(1));
+    rsi[start..]
+        .windows(2)
+        .rev()
+        .find_map(|w| match (w[0], w[1]) {
+            (Some(prev), Some(curr)) if prev <= level && curr > level => {
+                Some(CrossSignal::OversoldUp)
+            }
+            (Some(prev), Some(curr)) if prev >= overbought && curr < overbought => {
+                Some(CrossSignal::OverboughtDown)
+            }
+            _ => None,
+        })
+}
+
+pub trait RsiCrossExt {
+    /// Most recent cross of the `freq` RSI within the last `within` candles.
+    fn rsi_cross(&self, freq: usize, level: f64, within: usize) -> Option<CrossSignal>;
+}
+
+impl RsiCrossExt for Candles {
+    fn rsi_cross(&self, freq: usize, level: f64, within: usize) -> Option<CrossSignal> {
+        let rsi = self.rsi(freq)?;
+        let values = rsi.iter().map(|v| v.copied()).collect::<Vec<_>>();
+        last_cross(&values, level, within)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn oversold_cross() {
+        let rsi = [Some(45.0), Some(28.0), Some(25.0), Some(33.0), Some(40.0)];
+        assert_eq!(last_cross(&rsi, 30.0, 4), Some(CrossSignal::OversoldUp));
+    }
+
+    #[test]
+    fn overbought_cross_after_oversold() {
+        let rsi = [Some(25.0), Some(35.0), Some(72.0), Some(68.0)];
+        assert_eq!(last_cross(&rsi, 30.0, 3), Some(CrossSignal::OverboughtDown));
+    }
+
+    #[test]
+    fn no_cross() {
+        let rsi = [None, Some(45.0), Some(50.0), Some(55.0)];
+        assert_eq!(last_cross(&rsi, 30.0, 3), None);
+        let rsi = [Some(25.0), None, Some(35.0)];
+        assert_eq!(last_cross(&rsi, 30.0, 2), None);
+    }
+
+    #[test]
+    fn stale_cross() {
+        let rsi = [Some(45.0), Some(28.0), Some(33.0), Some(40.0), Some(45.0)];
+        assert_eq!(last_cross(&rsi, 30.0, 3), Some(CrossSignal::OversoldUp));
+        assert_eq!(last_cross(&rsi, 30.0, 2), None);
+        assert_eq!(last_cross(&rsi, 30.0, 0), None);
+        assert_eq!(last_cross(&rsi, 30.0, 10), Some(CrossSignal::OversoldUp));
+    }
+}
@@ -0,0 +1,103 @@
+use erfurt::candle::CandlesExt;
+use qualsdorf::{Indicator, ReturnExt};
+
+use super::continuous_drawdowns;
+
+/// Rolling Sterling ratio, the compounded return of the window over the mean of its `n`
+/// largest continuous drawdowns plus `excess`.
+///
+/// The window of `freq` returns is taken as one year. `excess` is traditionally `0.1`, it
+/// keeps the ratio finite for windows without any drawdown. A non-positive denominator has
+/// no value.
+#[derive(Debug)]
+pub struct SterlingRatio {
+    pub freq: usize,
+    pub n: usize,
+    pub excess: f64,
+    pub input: Vec<f64>,
+    pub values: Vec<Option<f64>>,
+}
+
+impl SterlingRatio {
+    #[must_use]
+    pub fn new(freq: usize, n: usize, excess: f64) -> Self {
+        Self {
+            freq,
+            n,
+            excess,
+            input: Vec::with_capacity(freq),
+            values: Vec::with_capacity(freq),
+        }
+    }
+}
+
+impl Indicator for SterlingRatio {
+    type Input = f64;
+    type Output = f64;
+
+    fn feed(&mut self, ret: Self::Input) {
+        self.input.push(ret);
+
+        if self.input.len() >= self.freq && self.freq > 0 {
+            let window = &self.input[self.input.len() - self.freq..];
+            let ret = window.iter().map(|ret| 1.0 + ret).product::<f64>() - 1.0;
+            let mut drawdowns = continuous_drawdowns(window);
+            drawdowns.sort_by(|a, b| b.total_cmp(a));
+            drawdowns.truncate(self.n);
+            let mean = if drawdowns.is_empty() {
+                0.0
+            } else {
+                drawdowns.iter().sum::<f64>() / drawdowns.len() as f64
+            };
+            let denominator = mean + self.excess;
+            self.values
+                .push((denominator > 0.0).then(|| ret / denominator));
+        } else {
+            self.values.push(None);
+        }
+    }
+
+    fn last(&self) -> Option<&Self::Output> {
+        self.values.last().and_then(|v| v.as_ref())
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = Option<&Self::Output>> + '_> {
+        Box::new(self.values.iter().map(Option::as_ref))
+    }
+}
+
+pub trait SterlingRatioExt: ReturnExt {
+    /// Sterling ratio of the last `freq` returns.
+    fn sterling_ratio(&self, freq: usize, n: usize, excess: f64) -> Option<f64> {
+        let mut indicator = SterlingRatio::new(freq, n, excess);
+        self.ret()?.into_iter().for_each(|v| indicator.feed(v));
+        indicator.last().copied()
+    }
+}
+
+impl<T> SterlingRatioExt for T where T: CandlesExt {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::indicators::fixtures::XS;
+
+    #[test]
+    fn sterling_ratio() {
+        let mut indicator = SterlingRatio::new(6, 2, 0.1);
+        XS.iter().for_each(|&x| indicator.feed(x));
+        assert_eq!(indicator.values.len(), XS.len());
+        assert!(indicator.values[..5].iter().all(Option::is_none));
+        let first = indicator.values[5].unwrap();
+        assert!((first - 0.270_107_984_919_989_8).abs() < 1e-9);
+        let value = *indicator.last().unwrap();
+        assert!((value - 0.083_507_016_225_031_51).abs() < 1e-9);
+    }
+
+    #[test]
+    fn sterling_ratio_without_excess_or_drawdown() {
+        let mut indicator = SterlingRatio::new(3, 2, 0.0);
+        [0.01, 0.02, 0.0].iter().for_each(|&x| indicator.feed(x));
+        assert!(indicator.last().is_none());
+    }
+}
@@ -0,0 +1,94 @@
+use erfurt::candle::Candles;
+use qualsdorf::Indicator;
+use statrs::statistics::Statistics;
+
+use super::aligned_returns;
+
+/// Rolling standard deviation of active returns (asset minus benchmark).
+#[derive(Debug)]
+pub struct TrackingError {
+    pub freq: usize,
+    pub input: Vec<f64>,
+    pub values: Vec<Option<f64>>,
+}
+
+impl TrackingError {
+    #[must_use]
+    pub fn new(freq: usize) -> Self {
+        Self {
+            freq,
+            input: Vec::with_capacity(freq),
+            values: Vec::with_capacity(freq),
+        }
+    }
+}
+
+impl Indicator for TrackingError {
+    type Input = (f64, f64);
+    type Output = f64;
+
+    fn feed(&mut self, (ret, benchmark): Self::Input) {
+        self.input.push(ret - benchmark);
+
+        if self.input.len() >= self.freq {
+            let active = &self.input[self.input.len() - self.freq..];
+            self.values.push(Some(active.iter().std_dev()));
+        } else {
+            self.values.push(None);
+        }
+    }
+
+    fn last(&self) -> Option<&Self::Output> {
+        self.values.last().and_then(|v| v.as_ref())
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = Option<&Self::Output>> + '_> {
+        Box::new(self.values.iter().map(Option::as_ref))
+    }
+}
+
+pub trait TrackingErrorExt {
+    fn tracking_error(&self, benchmark: &Candles, freq: usize) -> Option<TrackingError>;
+}
+
+impl TrackingErrorExt for Candles {
+    fn tracking_error(&self, benchmark: &Candles, freq: usize) -> Option<TrackingError> {
+        let (ret, benchmark_ret) = aligned_returns(self, benchmark)?;
+        if ret.len() < freq {
+            return None;
+        }
+        let mut indicator = TrackingError::new(freq);
+        ret.into_iter()
+            .zip(benchmark_ret)
+            .for_each(|v| indicator.feed(v));
+        Some(indicator)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::indicators::fixtures::{BS, XS};
+
+    #[test]
+    fn tracking_error() {
+        let mut indicator = TrackingError::new(6);
+        XS.iter().zip(BS).for_each(|(&x, b)| indicator.feed((x, b)));
+        assert_eq!(indicator.values.len(), XS.len());
+        assert!(indicator.values[..5].iter().all(Option::is_none));
+        let first = indicator.values[5].unwrap();
+        assert!((first - 0.009_325_592_027_676_67).abs() < 1e-9);
+        // Active returns of the last window are -0.007, 0.007, 0.001, -0.003, 0.011 and
+        // -0.003, their squared deviations from the 0.001 mean add up to 0.000232, so the
+        // tracking error is sqrt(0.000232 / 5).
+        let value = *indicator.last().unwrap();
+        assert!((value - (0.000_232_f64 / 5.0).sqrt()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn tracking_error_of_benchmark() {
+        let mut indicator = TrackingError::new(3);
+        BS.iter().zip(BS).for_each(|(&x, b)| indicator.feed((x, b)));
+        assert!(indicator.last().unwrap().abs() < 1e-12);
+    }
+}
@@ -0,0 +1,122 @@
+use erfurt::candle::Candles;
+use qualsdorf::Indicator;
+use statrs::statistics::Statistics;
+
+use super::{aligned_returns, RiskFree};
+
+/// Betas closer to zero than this leave the ratio undefined.
+const MIN_BETA: f64 = 1e-8;
+
+/// Rolling Treynor ratio, annualized excess return per unit of beta.
+///
+/// The window of `freq` returns is taken as one year, so the mean excess return over the
+/// per-period `risk_free` is scaled by `freq`.
+#[derive(Debug)]
+pub struct TreynorRatio {
+    pub freq: usize,
+    pub risk_free: RiskFree,
+    pub input: Vec<(f64, f64)>,
+    pub values: Vec<Option<f64>>,
+}
+
+impl TreynorRatio {
+    #[must_use]
+    pub fn new(freq: usize, risk_free: impl Into<RiskFree>) -> Self {
+        Self {
+            freq,
+            risk_free: risk_free.into(),
+            input: Vec::with_capacity(freq),
+            values: Vec::with_capacity(freq),
+        }
+    }
+}
+
+impl Indicator for TreynorRatio {
+    type Input = (f64, f64);
+    type Output = f64;
+
+    fn feed(&mut self, value: Self::Input) {
+        self.input.push(value);
+
+        if self.input.len() >= self.freq && self.freq >= 2 {
+            let start = self.input.len() - self.freq;
+            let window = &self.input[start..];
+            let risk_free = self.risk_free.mean(start..self.input.len());
+            let rets = window.iter().map(|(ret, _)| *ret).collect::<Vec<_>>();
+            let benchmark = window.iter().map(|(_, ret)| *ret).collect::<Vec<_>>();
+            let variance = benchmark.iter().variance();
+            let beta = rets.iter().covariance(benchmark.iter()) / variance;
+            let value = (variance > 0.0 && beta.abs() > MIN_BETA).then(|| {
+                let excess = (rets.iter().mean() - risk_free) * self.freq as f64;
+                excess / beta
+            });
+            self.values.push(value);
+        } else {
+            self.values.push(None);
+        }
+    }
+
+    fn last(&self) -> Option<&Self::Output> {
+        self.values.last().and_then(|v| v.as_ref())
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = Option<&Self::Output>> + '_> {
+        Box::new(self.values.iter().map(Option::as_ref))
+    }
+}
+
+pub trait TreynorRatioExt {
+    fn treynor_ratio(
+        &self,
+        benchmark: &Candles,
+        freq: usize,
+        risk_free: impl Into<RiskFree>,
+    ) -> Option<TreynorRatio>;
+}
+
+impl TreynorRatioExt for Candles {
+    fn treynor_ratio(
+        &self,
+        benchmark: &Candles,
+        freq: usize,
+        risk_free: impl Into<RiskFree>,
+    ) -> Option<TreynorRatio> {
+        let (ret, benchmark_ret) = aligned_returns(self, benchmark)?;
+        if ret.len() < freq {
+            return None;
+        }
+        let mut indicator = TreynorRatio::new(freq, risk_free);
+        ret.into_iter()
+            .zip(benchmark_ret)
+            .for_each(|v| indicator.feed(v));
+        Some(indicator)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::indicators::fixtures::{BS, XS};
+
+    #[test]
+    fn treynor_ratio() {
+        let mut indicator = TreynorRatio::new(6, 0.001);
+        XS.iter().zip(BS).for_each(|(&x, b)| indicator.feed((x, b)));
+        assert_eq!(indicator.values.len(), XS.len());
+        assert!(indicator.values[..5].iter().all(Option::is_none));
+        // Mean return 0.035 / 6, beta 1.5125: (0.035 / 6 - 0.001) * 6 / 1.5125.
+        let first = indicator.values[5].unwrap();
+        assert!((first - 0.029 / 1.5125).abs() < 1e-9);
+        let value = *indicator.last().unwrap();
+        assert!((value - 0.003_418_429_504_928_563).abs() < 1e-9);
+    }
+
+    #[test]
+    fn treynor_ratio_flat_benchmark() {
+        let mut indicator = TreynorRatio::new(3, 0.0);
+        [(0.01, 0.02), (-0.01, 0.02), (0.03, 0.02)]
+            .iter()
+            .for_each(|&v| indicator.feed(v));
+        assert!(indicator.last().is_none());
+    }
+}
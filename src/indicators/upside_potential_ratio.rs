@@ -0,0 +1,101 @@
+use erfurt::candle::CandlesExt;
+use qualsdorf::{Indicator, ReturnExt};
+
+/// Rolling upside potential ratio, the mean excess over `mar` of the last `freq` returns
+/// divided by their downside deviation below it.
+///
+/// A window that never falls below `mar` has no value.
+#[derive(Debug)]
+pub struct UpsidePotentialRatio {
+    pub freq: usize,
+    pub mar: f64,
+    pub input: Vec<f64>,
+    pub values: Vec<Option<f64>>,
+}
+
+impl UpsidePotentialRatio {
+    #[must_use]
+    pub fn new(freq: usize, mar: f64) -> Self {
+        Self {
+            freq,
+            mar,
+            input: Vec::with_capacity(freq),
+            values: Vec::with_capacity(freq),
+        }
+    }
+}
+
+impl Indicator for UpsidePotentialRatio {
+    type Input = f64;
+    type Output = f64;
+
+    fn feed(&mut self, ret: Self::Input) {
+        self.input.push(ret);
+
+        if self.input.len() >= self.freq && self.freq > 0 {
+            let window = &self.input[self.input.len() - self.freq..];
+            let n = window.len() as f64;
+            let upside = window
+                .iter()
+                .map(|ret| (ret - self.mar).max(0.0))
+                .sum::<f64>()
+                / n;
+            let downside = (window
+                .iter()
+                .map(|ret| (ret - self.mar).min(0.0).powi(2))
+                .sum::<f64>()
+                / n)
+                .sqrt();
+            self.values
+                .push((downside > 0.0).then(|| upside / downside));
+        } else {
+            self.values.push(None);
+        }
+    }
+
+    fn last(&self) -> Option<&Self::Output> {
+        self.values.last().and_then(|v| v.as_ref())
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = Option<&Self::Output>> + '_> {
+        Box::new(self.values.iter().map(Option::as_ref))
+    }
+}
+
+pub trait UpsidePotentialRatioExt: ReturnExt {
+    /// Upside potential ratio of the last `freq` returns.
+    fn upside_potential_ratio(&self, freq: usize, mar: f64) -> Option<f64> {
+        let mut indicator = UpsidePotentialRatio::new(freq, mar);
+        self.ret()?.into_iter().for_each(|v| indicator.feed(v));
+        indicator.last().copied()
+    }
+}
+
+impl<T> UpsidePotentialRatioExt for T where T: CandlesExt {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::indicators::fixtures::XS;
+
+    #[test]
+    fn upside_potential_ratio() {
+        let mut indicator = UpsidePotentialRatio::new(6, 0.0);
+        XS.iter().for_each(|&x| indicator.feed(x));
+        assert_eq!(indicator.values.len(), XS.len());
+        assert!(indicator.values[..5].iter().all(Option::is_none));
+        assert!((indicator.values[5].unwrap() - 0.922_798_376_064_613_1).abs() < 1e-9);
+        assert!((indicator.last().unwrap() - 0.754_345_325_316_554).abs() < 1e-9);
+
+        let mut indicator = UpsidePotentialRatio::new(6, 0.01);
+        XS.iter().for_each(|&x| indicator.feed(x));
+        assert!((indicator.last().unwrap() - 0.267_835_792_002_790_1).abs() < 1e-9);
+    }
+
+    #[test]
+    fn upside_potential_ratio_without_downside() {
+        let mut indicator = UpsidePotentialRatio::new(3, 0.0);
+        [0.01, 0.02, 0.0].iter().for_each(|&x| indicator.feed(x));
+        assert!(indicator.last().is_none());
+    }
+}
@@ -0,0 +1,70 @@
+use erfurt::candle::Candles;
+
+/// Rolling volume weighted average of `prices` over `window` values, one per full window.
+///
+/// A window without any volume falls back to the plain mean of its prices.
+#[must_use]
+pub fn rolling_vwap(prices: &[f64], volume: &[f64], window: usize) -> Vec<f64> {
+    if window == 0 {
+        return Vec::new();
+    }
+    prices
+        .windows(window)
+        .zip(volume.windows(window))
+        .map(|(prices, volume)| {
+            let total = volume.iter().sum::<f64>();
+            if total > 0.0 {
+                prices.iter().zip(volume).map(|(p, v)| p * v).sum::<f64>() / total
+            } else {
+                prices.iter().sum::<f64>() / window as f64
+            }
+        })
+        .collect()
+}
+
+pub trait VwapExt {
+    /// Typical price `(high + low + close) / 3` of every candle.
+    fn typical_price(&self) -> Vec<f64>;
+    /// Rolling VWAP of the typical price over `window` candles, one value per full window.
+    /// `None` without volume.
+    fn vwap(&self, window: usize) -> Option<Vec<f64>>;
+}
+
+impl VwapExt for Candles {
+    fn typical_price(&self) -> Vec<f64> {
+        self.high
+            .iter()
+            .zip(&self.low)
+            .zip(&self.close)
+            .map(|((high, low), close)| (high + low + close) / 3.0)
+            .collect()
+    }
+
+    fn vwap(&self, window: usize) -> Option<Vec<f64>> {
+        let volume = self.volume.as_deref()?;
+        Some(rolling_vwap(&self.typical_price(), volume, window))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn vwap() {
+        let prices = [10.0, 11.0, 12.0, 13.0];
+        let volume = [100.0, 300.0, 0.0, 200.0];
+        let vwap = rolling_vwap(&prices, &volume, 2);
+        assert_eq!(vwap.len(), 3);
+        assert!((vwap[0] - 10.75).abs() < 1e-9);
+        assert!((vwap[1] - 11.0).abs() < 1e-9);
+        assert!((vwap[2] - 13.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn vwap_without_volume() {
+        let vwap = rolling_vwap(&[10.0, 12.0], &[0.0, 0.0], 2);
+        assert!((vwap[0] - 11.0).abs() < 1e-9);
+        assert!(rolling_vwap(&[10.0], &[1.0], 0).is_empty());
+    }
+}
@@ -1,11 +1,15 @@
 use anyhow::Result;
 use tracing::info;
 
+pub mod candles;
 pub mod cli;
 pub mod cmd;
+pub mod indicators;
+pub mod period;
 pub mod portfolio;
 pub mod puppet;
 pub mod server;
+pub mod table;
 
 use crate::cli::CliExt;
 
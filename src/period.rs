@@ -0,0 +1,70 @@
+use degiro_rs::util::Period;
+
+/// Calendar length of a period, counted in the unit it's naturally measured in.
+#[derive(Debug, Clone, Copy)]
+enum Span {
+    TradingDays(usize),
+    Weeks(usize),
+    Months(usize),
+}
+
+impl Span {
+    /// Length in years, a year being 252 trading days, 52 weeks or 12 months.
+    fn years(self) -> f64 {
+        match self {
+            Self::TradingDays(n) => n as f64 / 252.0,
+            Self::Weeks(n) => n as f64 / 52.0,
+            Self::Months(n) => n as f64 / 12.0,
+        }
+    }
+}
+
+const fn span(period: Period) -> Option<Span> {
+    match period {
+        Period::P1D => Some(Span::TradingDays(1)),
+        Period::P1W => Some(Span::Weeks(1)),
+        Period::P1M => Some(Span::Months(1)),
+        Period::P3M => Some(Span::Months(3)),
+        Period::P6M => Some(Span::Months(6)),
+        Period::P1Y => Some(Span::Months(12)),
+        Period::P3Y => Some(Span::Months(36)),
+        Period::P5Y => Some(Span::Months(60)),
+        Period::P50Y => Some(Span::Months(600)),
+        _ => None,
+    }
+}
+
+pub trait PeriodExt {
+    /// Number of `interval` candles in the period.
+    ///
+    /// `Period::div` measures both in milliseconds with 30 day months and 365 day years, so
+    /// `P1Y / P1M` is 12.17 and longer spans drift. Daily to yearly periods are instead counted
+    /// on a calendar of 252 trading days, 52 weeks or 12 months a year, rounded to the nearest
+    /// whole candle: `P1Y` holds 12 `P1M`, 52 `P1W` and 252 `P1D`, and `P1W` holds 5 `P1D`.
+    /// Intraday periods fall back to `Period::div`.
+    fn periods(self, interval: Period) -> usize;
+}
+
+impl PeriodExt for Period {
+    fn periods(self, interval: Period) -> usize {
+        match (span(self), span(interval)) {
+            (Some(period), Some(interval)) => (period.years() / interval.years()).round() as usize,
+            _ => self.div(interval),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn periods() {
+        assert_eq!(Period::P1Y.periods(Period::P1M), 12);
+        assert_eq!(Period::P1Y.periods(Period::P1W), 52);
+        assert_eq!(Period::P1Y.periods(Period::P1D), 252);
+        assert_eq!(Period::P1W.periods(Period::P1D), 5);
+        assert_eq!(Period::P1M.periods(Period::P1D), 21);
+        assert_eq!(Period::P50Y.periods(Period::P1M), 600);
+    }
+}
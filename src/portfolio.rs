@@ -1,4 +1,6 @@
-use anyhow::{anyhow, Result};
+use std::{collections::HashSet, future::Future};
+
+use anyhow::{anyhow, Context, Result};
 use async_trait::async_trait;
 use degiro_rs::{
     api::{
@@ -18,6 +20,11 @@ use serde::{Deserialize, Serialize};
 use statrs::statistics::Statistics;
 use strum::EnumString;
 
+use crate::{
+    indicators::{log_returns, simple_returns},
+    period::PeriodExt,
+};
+
 #[derive(Debug)]
 pub struct LSV {
     pub freq: usize,
@@ -83,9 +90,52 @@ pub trait LsvExt: ReturnExt {
 impl<T> LsvExt for T where T: CandlesExt {}
 
 #[derive(Debug, Clone, Copy, EnumString, Serialize, Deserialize)]
+#[strum(ascii_case_insensitive)]
 pub enum RiskMode {
     STD,
     LSV,
+    /// Mean absolute deviation of the returns, less sensitive to outliers than `STD`.
+    MAD,
+}
+
+/// Mean of `|x - mean(xs)|`, `0.0` for no values.
+#[must_use]
+pub fn mean_absolute_deviation(xs: &[f64]) -> f64 {
+    if xs.is_empty() {
+        return 0.0;
+    }
+    let n = xs.len() as f64;
+    let mean = xs.iter().sum::<f64>() / n;
+    xs.iter().map(|x| (x - mean).abs()).sum::<f64>() / n
+}
+
+/// Fraction of capital held in a single asset with Sharpe ratio `sr`, risk `risk_metric` and
+/// rolling economic drawdown `redp`, clamped to `0.0..=1.0`.
+fn redp_single_allocation(sr: f64, risk_metric: f64, risk: f64, redp: f64) -> f64 {
+    1.0_f64.min(0.0_f64.max(
+        ((sr / risk_metric) + 0.5 / risk.mul_add(-risk, 1.0)).mul_add(risk, -(redp / (1.0 - redp))),
+    ))
+}
+
+/// Kind of returns the risk model is computed from.
+#[derive(Debug, Clone, Copy, Default, EnumString, Serialize, Deserialize)]
+#[strum(serialize_all = "lowercase")]
+pub enum ReturnKind {
+    /// `y / x - 1`
+    #[default]
+    Simple,
+    /// `ln(y / x)`
+    Log,
+}
+
+impl ReturnKind {
+    #[must_use]
+    pub fn returns(self, prices: &[f64]) -> Vec<f64> {
+        match self {
+            Self::Simple => simple_returns(prices),
+            Self::Log => log_returns(prices),
+        }
+    }
 }
 
 #[async_trait]
@@ -93,6 +143,7 @@ pub trait SingleAllocation {
     async fn single_allocation(
         &self,
         mode: RiskMode,
+        ret_kind: ReturnKind,
         risk: f64,
         risk_free: f64,
         period: Period,
@@ -105,6 +156,7 @@ impl SingleAllocation for Product {
     async fn single_allocation(
         &self,
         mode: RiskMode,
+        ret_kind: ReturnKind,
         risk: f64,
         risk_free: f64,
         period: Period,
@@ -112,7 +164,7 @@ impl SingleAllocation for Product {
     ) -> Result<f64> {
         let candles: Candles = self.quotes(period, interval).await?.into();
         candles
-            .single_allocation(mode, risk, risk_free, period, interval)
+            .single_allocation(mode, ret_kind, risk, risk_free, period, interval)
             .await
     }
 }
@@ -122,13 +174,14 @@ impl SingleAllocation for Quotes {
     async fn single_allocation(
         &self,
         mode: RiskMode,
+        ret_kind: ReturnKind,
         risk: f64,
         risk_free: f64,
         period: Period,
         interval: Period,
     ) -> Result<f64> {
         Into::<Candles>::into(self)
-            .single_allocation(mode, risk, risk_free, period, interval)
+            .single_allocation(mode, ret_kind, risk, risk_free, period, interval)
             .await
     }
 }
@@ -138,19 +191,28 @@ impl SingleAllocation for Candles {
     async fn single_allocation(
         &self,
         mode: RiskMode,
+        ret_kind: ReturnKind,
         risk: f64,
         risk_free: f64,
         period: Period,
         interval: Period,
     ) -> Result<f64> {
-        let freq = period.div(interval);
+        let freq = period.periods(interval);
         let risk_metric = match mode {
             RiskMode::STD => {
-                let ret = self
-                    .ret()
-                    .ok_or_else(|| anyhow!("can't calculate return"))?;
+                let ret = ret_kind.returns(&self.close);
+                if ret.is_empty() {
+                    return Err(anyhow!("can't calculate return"));
+                }
                 ret.iter().std_dev()
             }
+            RiskMode::MAD => {
+                let ret = ret_kind.returns(&self.close);
+                if ret.is_empty() {
+                    return Err(anyhow!("can't calculate return"));
+                }
+                mean_absolute_deviation(&ret)
+            }
             RiskMode::LSV => self
                 .lsv(freq)
                 .ok_or_else(|| anyhow!("can't calculate lsv"))?
@@ -170,14 +232,120 @@ impl SingleAllocation for Candles {
             .last()
             .ok_or_else(|| anyhow!("can't get value"))?
             .to_owned();
-        let allocation = 1.0_f64.min(
-            0.0_f64.max(
-                ((sr / risk_metric) + 0.5 / risk.mul_add(-risk, 1.0))
-                    .mul_add(risk, -(redp / (1.0 - redp))),
-            ),
+        Ok(redp_single_allocation(sr, risk_metric, risk, redp))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BacktestResult {
+    pub equity: Vec<f64>,
+    pub cumulative_return: f64,
+    pub max_drawdown: f64,
+    pub sharpe_ratio: f64,
+}
+
+pub(crate) fn candles_until(candles: &Candles, end: usize) -> Candles {
+    let mut candles = candles.clone();
+    candles.time.truncate(end);
+    candles.open.truncate(end);
+    candles.high.truncate(end);
+    candles.low.truncate(end);
+    candles.close.truncate(end);
+    if let Some(volume) = candles.volume.as_mut() {
+        volume.truncate(end);
+    }
+    candles
+}
+
+/// Returns of holding the allocations `allocate` makes along `closes`.
+///
+/// From candle `freq` on, every `rebalance_every` candles `allocate` is given how many candles
+/// it may see, the decision candle being the last of them, and the allocation is held over the
+/// following returns up to the next decision.
+async fn replay<F, Fut>(
+    closes: &[f64],
+    freq: usize,
+    rebalance_every: usize,
+    mut allocate: F,
+) -> Result<Vec<f64>>
+where
+    F: FnMut(usize) -> Fut,
+    Fut: Future<Output = Result<f64>>,
+{
+    let len = closes.len();
+    let mut returns = Vec::with_capacity(len.saturating_sub(freq));
+    let mut decision = freq;
+    while decision + 1 < len {
+        let allocation = allocate(decision + 1).await?;
+        let next_decision = (decision + rebalance_every).min(len - 1);
+        returns.extend(
+            (decision..next_decision).map(|i| allocation * (closes[i + 1] / closes[i] - 1.0)),
         );
-        Ok(allocation)
+        decision = next_decision;
+    }
+    Ok(returns)
+}
+
+/// Replays `single_allocation` over the candle history.
+///
+/// Every `rebalance_every` candles the allocation is recomputed from the candles up to and
+/// including the decision candle only, then held over the following returns. A failed
+/// allocation fails the backtest. The Sharpe ratio is annualized with `period / interval`
+/// candles per period and `risk_free` spread evenly over them.
+pub async fn backtest_single(
+    candles: &Candles,
+    mode: RiskMode,
+    ret_kind: ReturnKind,
+    risk: f64,
+    risk_free: f64,
+    period: Period,
+    interval: Period,
+    rebalance_every: usize,
+) -> Result<BacktestResult> {
+    if rebalance_every == 0 {
+        return Err(anyhow!("rebalance_every must be greater than zero"));
+    }
+    let freq = period.periods(interval);
+    let len = candles.close.len();
+    if len < freq + 2 {
+        return Err(anyhow!("not enough candles to backtest"));
+    }
+
+    let returns = replay(&candles.close, freq, rebalance_every, |end| async move {
+        candles_until(candles, end)
+            .single_allocation(mode, ret_kind, risk, risk_free, period, interval)
+            .await
+            .with_context(|| format!("no allocation from the first {end} candles"))
+    })
+    .await?;
+    let mut equity = Vec::with_capacity(returns.len() + 1);
+    equity.push(1.0);
+    for ret in &returns {
+        let last = equity.last().copied().unwrap_or(1.0);
+        equity.push(last * (1.0 + ret));
+    }
+
+    let cumulative_return = equity.last().copied().unwrap_or(1.0) - 1.0;
+    let mut peak = f64::MIN;
+    let mut max_drawdown = 0.0_f64;
+    for value in &equity {
+        peak = peak.max(*value);
+        max_drawdown = max_drawdown.max(1.0 - value / peak);
     }
+    let std_dev = returns.iter().std_dev();
+    let sharpe_ratio = if std_dev > 0.0 {
+        let excess = returns.iter().mean() - risk_free / freq as f64;
+        excess / std_dev * (freq as f64).sqrt()
+    } else {
+        0.0
+    };
+
+    Ok(BacktestResult {
+        equity,
+        cumulative_return,
+        max_drawdown,
+        sharpe_ratio,
+    })
 }
 
 pub struct AssetsSeq(pub Vec<(ProductDetails, Candles)>);
@@ -218,25 +386,118 @@ fn na_covariance(matrix: &na::DMatrix<f64>) -> na::DMatrix<f64> {
     covariance_matrix
 }
 
+/// Shrinks `sigma` toward its own diagonal, `(1 - delta) * sigma + delta * diag(sigma)`.
+///
+/// Variances are kept while covariances are pulled toward zero, which makes a near singular
+/// covariance of highly correlated assets invertible again.
+fn shrink_covariance(sigma: &na::DMatrix<f64>, delta: f64) -> na::DMatrix<f64> {
+    let target = na::DMatrix::from_diagonal(&sigma.diagonal());
+    sigma * (1.0 - delta) + target * delta
+}
+
+/// Pearson correlation of the rows of `matrix`, one instrument per row.
+fn na_correlation(matrix: &na::DMatrix<f64>) -> na::DMatrix<f64> {
+    let ncols = matrix.ncols() as f64;
+    let mut centered = matrix.clone();
+    for mut row in centered.row_iter_mut() {
+        let mean = row.sum() / ncols;
+        row.add_scalar_mut(-mean);
+    }
+    let covariance = &centered * centered.transpose() / (ncols - 1.0);
+    let std_devs = covariance.diagonal().map(f64::sqrt);
+    na::DMatrix::from_fn(matrix.nrows(), matrix.nrows(), |i, j| {
+        covariance[(i, j)] / (std_devs[i] * std_devs[j])
+    })
+}
+
+/// Fewest common candles a correlation is computed from, two returns per asset.
+const MIN_CORRELATION_WINDOW: usize = 3;
+
 impl AssetsSeq {
+    /// Restricts every asset to the timestamps shared by all of them.
+    pub fn aligned(&self, min_len: usize) -> Result<Self> {
+        let Some((_, first)) = self.0.first() else {
+            return Err(anyhow!("no assets to align"));
+        };
+        let mut common = first.time.iter().copied().collect::<HashSet<_>>();
+        for (_, candles) in self.0.iter().skip(1) {
+            let times = candles.time.iter().collect::<HashSet<_>>();
+            common.retain(|time| times.contains(time));
+        }
+        if common.len() < min_len {
+            return Err(anyhow!(
+                "common history of {} candles is shorter than {}",
+                common.len(),
+                min_len
+            ));
+        }
+        let xs = self
+            .0
+            .iter()
+            .map(|(product, candles)| {
+                let idx = candles
+                    .time
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(i, time)| common.contains(time).then_some(i))
+                    .collect::<Vec<_>>();
+                let pick = |xs: &[f64]| idx.iter().map(|&i| xs[i]).collect::<Vec<_>>();
+                let mut aligned = candles.clone();
+                aligned.time = idx.iter().map(|&i| candles.time[i]).collect();
+                aligned.open = pick(&candles.open);
+                aligned.high = pick(&candles.high);
+                aligned.low = pick(&candles.low);
+                aligned.close = pick(&candles.close);
+                aligned.volume = candles.volume.as_deref().map(pick);
+                (product.clone(), aligned)
+            })
+            .collect();
+        Ok(Self(xs))
+    }
+
+    /// Pearson correlation matrix of the asset returns over their common window, rows and
+    /// columns ordered as the returned product ids.
+    pub fn correlation_matrix(&self) -> Result<(Vec<String>, na::DMatrix<f64>)> {
+        let aligned = self.aligned(MIN_CORRELATION_WINDOW)?;
+        let mut ids = Vec::with_capacity(aligned.0.len());
+        let mut rets_rows = Vec::with_capacity(aligned.0.len());
+        for (product, candles) in &aligned.0 {
+            let ret = simple_returns(&candles.close);
+            if ret.is_empty() {
+                return Err(anyhow!("can't calculate return"));
+            }
+            ids.push(product.id.clone());
+            rets_rows.push(na::RowDVector::from_vec(ret));
+        }
+        let rets = na::DMatrix::from_rows(&rets_rows);
+        Ok((ids, na_correlation(&rets)))
+    }
+
     pub async fn redp_multiple_allocation(
         &self,
         mode: RiskMode,
+        ret_kind: ReturnKind,
         risk: f64,
         risk_free: f64,
         period: Period,
         interval: Period,
         short_sales_constraint: bool,
+        shrinkage: Option<f64>,
     ) -> Result<Vec<(ProductDetails, f64)>> {
-        let freq = period.div(interval);
+        if shrinkage.is_some_and(|delta| !(0.0..=1.0).contains(&delta)) {
+            return Err(anyhow!("shrinkage must be between 0 and 1"));
+        }
+        let freq = period.periods(interval);
+        let assets = self.aligned(freq)?;
         let mut rets_rows = Vec::new();
 
         let mut ys = Vec::new();
         let mut mu = Vec::new();
-        for (_p, candles) in self.0.clone() {
-            let ret = candles
-                .ret()
-                .ok_or_else(|| anyhow!("can't calculate return"))?;
+        for (_p, candles) in &assets.0 {
+            let ret = ret_kind.returns(&candles.close);
+            if ret.is_empty() {
+                return Err(anyhow!("can't calculate return"));
+            }
             let row = na::RowDVector::from_vec(ret.clone());
             rets_rows.push(row);
             let risk_metric = match mode {
@@ -247,6 +508,7 @@ impl AssetsSeq {
                     .last()
                     .ok_or_else(|| anyhow!("can't get value"))?
                     .to_owned(),
+                RiskMode::MAD => mean_absolute_deviation(&ret),
             };
             let mean_ret = ret.mean();
             let redp = candles
@@ -266,7 +528,10 @@ impl AssetsSeq {
         let rets = na::DMatrix::from_rows(&rets_rows);
         let ys = na::DVector::<f64>::from_vec(ys);
         let mu = na::DVector::<f64>::from_vec(mu);
-        let sigma = na_covariance(&rets);
+        let mut sigma = na_covariance(&rets);
+        if let Some(delta) = shrinkage {
+            sigma = shrink_covariance(&sigma, delta);
+        }
         if !sigma.is_invertible() {
             return Err(anyhow!("Covariance matrix is not invertible"));
         };
@@ -285,7 +550,7 @@ impl AssetsSeq {
         let x_redp_sum_abs = x_redp.iter().map(|x| x.abs()).sum::<f64>();
         let x_redp_normalized = x_redp.iter().map(|x| x / x_redp_sum_abs);
         let mut r: Vec<(ProductDetails, f64)> = Vec::new();
-        for ((p, _), allocation) in self.0.iter().zip(x_redp_normalized) {
+        for ((p, _), allocation) in assets.0.iter().zip(x_redp_normalized) {
             if short_sales_constraint {
                 if allocation > 0.0 {
                     r.push((p.clone(), allocation));
@@ -312,11 +577,96 @@ mod test {
         client.account_config().await.unwrap();
         let product = client.product("1089390").await.unwrap();
         let allocation = product
-            .single_allocation(RiskMode::STD, 0.3, 0.0, Period::P1Y, Period::P1M)
+            .single_allocation(
+                RiskMode::STD,
+                ReturnKind::Simple,
+                0.3,
+                0.0,
+                Period::P1Y,
+                Period::P1M,
+            )
             .await
             .unwrap();
         dbg!(product, allocation);
     }
+    #[tokio::test]
+    async fn replay_without_look_ahead() {
+        // Returns alternate between +10% and -10%, so following the last return always loses
+        // and only an allocation that peeks at the next candle could win.
+        let closes = (0..10)
+            .scan(100.0, |close, i| {
+                *close *= if i % 2 == 0 { 1.1 } else { 0.9 };
+                Some(*close)
+            })
+            .collect::<Vec<_>>();
+        let mut seen = Vec::new();
+        let returns = replay(&closes, 2, 1, |end| {
+            seen.push(end);
+            let visible = &closes[..end];
+            let last = visible[end - 1] / visible[end - 2] - 1.0;
+            async move { Ok(last.signum()) }
+        })
+        .await
+        .unwrap();
+        assert_eq!(seen, (3..10).collect::<Vec<_>>());
+        assert_eq!(returns.len(), 7);
+        assert!(returns.iter().all(|ret| *ret < 0.0));
+    }
+
+    #[tokio::test]
+    async fn replay_propagates_allocation_errors() {
+        let closes = [100.0, 101.0, 102.0, 103.0, 104.0];
+        let result = replay(&closes, 2, 1, |end| async move {
+            if end > 3 {
+                Err(anyhow!("no allocation"))
+            } else {
+                Ok(1.0)
+            }
+        })
+        .await;
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn shrinkage_makes_singular_covariance_invertible() {
+        let sigma = na::DMatrix::from_row_slice(2, 2, &[0.04, 0.04, 0.04, 0.04]);
+        assert!(!sigma.is_invertible());
+        let shrunk = shrink_covariance(&sigma, 0.2);
+        assert!(shrunk.is_invertible());
+        assert!((shrunk[(0, 0)] - 0.04).abs() < 1e-12);
+        assert!((shrunk[(0, 1)] - 0.032).abs() < 1e-12);
+        assert_eq!(shrink_covariance(&sigma, 0.0), sigma);
+    }
+
+    #[test]
+    fn mad_allocation() {
+        let ret = [0.01, -0.005, 0.012, 0.003, -0.008, 0.15, 0.007, -0.002];
+        let mad = mean_absolute_deviation(&ret);
+        let std = ret.iter().std_dev();
+        assert!((mad - 0.032_281_25).abs() < 1e-12);
+        assert!((std - 0.052_656_399_692_453_61).abs() < 1e-12);
+        // The outlier weighs less on MAD, so the same asset gets a larger allocation.
+        let mad_allocation = redp_single_allocation(0.005, mad, 0.3, 0.1);
+        let std_allocation = redp_single_allocation(0.005, std, 0.3, 0.1);
+        assert!((mad_allocation - 0.100_190_655_853_772_97).abs() < 1e-9);
+        assert!((std_allocation - 0.082_210_619_626_032_2).abs() < 1e-9);
+        assert!(mean_absolute_deviation(&[]).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn correlation() {
+        let xs = [0.01, -0.02, 0.03, 0.005, -0.01];
+        let rows = [
+            na::RowDVector::from_iterator(5, xs.iter().copied()),
+            na::RowDVector::from_iterator(5, xs.iter().map(|x| 2.0f64.mul_add(*x, 0.01))),
+            na::RowDVector::from_iterator(5, xs.iter().map(|x| -x)),
+        ];
+        let corr = na_correlation(&na::DMatrix::from_rows(&rows));
+        assert!((corr[(0, 0)] - 1.0).abs() < 1e-12);
+        assert!((corr[(0, 1)] - 1.0).abs() < 1e-12);
+        assert!((corr[(0, 2)] + 1.0).abs() < 1e-12);
+        assert!((corr[(2, 1)] + 1.0).abs() < 1e-12);
+    }
     // TODO:
     // #[tokio::test]
     // async fn multiple_allocation() {
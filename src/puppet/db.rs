@@ -1,25 +1,138 @@
-use std::{collections::HashSet, fmt};
+use std::{
+    collections::HashSet,
+    fmt,
+    ops::Deref,
+    path::{Path, PathBuf},
+    sync::{Arc, PoisonError, RwLock, RwLockReadGuard},
+};
 
 use async_trait::async_trait;
-use degiro_rs::api::{
-    company_ratios::CompanyRatios, financial_statements::FinancialReports, product::ProductDetails,
-    quotes::Quotes,
+use chrono::NaiveDate;
+use dashmap::DashMap;
+use degiro_rs::{
+    api::{
+        company_ratios::CompanyRatios, financial_statements::FinancialReports,
+        portfolio::Portfolio, product::ProductDetails, quotes::Quotes,
+    },
+    util::Period,
 };
 use erfurt::prelude::Candles;
 use master_of_puppets::prelude::*;
 use serde::{Deserialize, Serialize};
-use tracing::{error, info};
+use thiserror::Error;
+use tracing::{error, info, warn};
+
+use super::settings::{DeleteAsset, GetSettings, Settings};
+use crate::candles::ValidateExt;
+
+pub const DEFAULT_MAP_SIZE: usize = 1024 * 1024 * 1024; // 1GB
+
+/// Candle interval used when a query or a fetch doesn't ask for another one.
+pub const DEFAULT_INTERVAL: Period = Period::P1M;
+
+/// Name of `interval` in the candles keys, `None` for the intraday ones, which aren't stored.
+///
+/// Spelled out instead of taken from `Period`'s `Debug`, so a change in degiro-rs can't orphan
+/// the stored candles. The names must never change.
+const fn interval_name(interval: Period) -> Option<&'static str> {
+    match interval {
+        Period::P1D => Some("P1D"),
+        Period::P1W => Some("P1W"),
+        Period::P1M => Some("P1M"),
+        Period::P3M => Some("P3M"),
+        Period::P6M => Some("P6M"),
+        Period::P1Y => Some("P1Y"),
+        Period::P3Y => Some("P3Y"),
+        Period::P5Y => Some("P5Y"),
+        Period::P50Y => Some("P50Y"),
+        _ => None,
+    }
+}
 
-use super::settings::{GetSettings, Settings};
+/// `interval_name` of `DEFAULT_INTERVAL`.
+const DEFAULT_INTERVAL_NAME: &str = "P1M";
+
+/// Key of the candles of product `id` in the interval named `interval`.
+fn candles_key(id: &str, interval: &str) -> String {
+    format!("{id}:{interval}")
+}
+
+/// Version of the on-disk layout, bump it together with a new entry in `MIGRATIONS`.
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// Migrations indexed by the schema version they upgrade from.
+type Migration = fn(&Db, &mut heed::RwTxn) -> heed::Result<()>;
+
+const MIGRATIONS: &[Migration] = &[
+    // 0 -> 1: databases created before versioning already share the current layout.
+    |_db, _wtx| Ok(()),
+    // 1 -> 2: candles are keyed by product and interval, everything stored so far is monthly.
+    |db, wtx| {
+        let entries = db
+            .candles
+            .iter(wtx)?
+            .filter_map(|res| {
+                res.map(|(id, candles)| (!id.contains(':')).then(|| (id.to_owned(), candles)))
+                    .transpose()
+            })
+            .collect::<heed::Result<Vec<_>>>()?;
+        for (id, candles) in entries {
+            db.candles.delete(wtx, &id)?;
+            db.candles
+                .put(wtx, &candles_key(&id, DEFAULT_INTERVAL_NAME), &candles)?;
+        }
+        Ok(())
+    },
+];
+
+#[derive(Debug, Error)]
+pub enum DbError {
+    #[error("can't create database directory: {0}")]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Heed(#[from] heed::Error),
+    #[error(
+        "database schema version {found} is not supported (expected {expected}), remove vogelsang.mdb and fetch the data again"
+    )]
+    SchemaVersion { found: u32, expected: u32 },
+}
 
 #[derive(Clone)]
 pub struct Db {
+    pub path: PathBuf,
+    pub map_size: usize,
     pub env: heed::Env,
     pub candles: heed::Database<heed::types::Str, heed::types::SerdeBincode<Candles>>,
     pub products: heed::Database<heed::types::Str, heed::types::SerdeBincode<ProductDetails>>,
     pub financial_reports:
         heed::Database<heed::types::Str, heed::types::SerdeBincode<FinancialReports>>,
     pub company_ratios: heed::Database<heed::types::Str, heed::types::SerdeBincode<CompanyRatios>>,
+    pub metadata: heed::Database<heed::types::Str, heed::types::SerdeBincode<u32>>,
+    /// Portfolio summaries keyed by ISO date, so keys sort chronologically.
+    pub portfolio_snapshots:
+        heed::Database<heed::types::Str, heed::types::SerdeBincode<PortfolioSnapshot>>,
+    /// Lowercased symbol -> product id, filled lazily by symbol lookups.
+    symbol_ids: Arc<DashMap<String, String>>,
+    /// Name pattern -> product id, filled lazily by name lookups.
+    name_ids: Arc<DashMap<String, String>>,
+    /// Held shared by every transaction and exclusively to resize the map, which LMDB only
+    /// allows while no transaction is open.
+    resize_lock: Arc<RwLock<()>>,
+}
+
+/// Read transaction holding off a resize of the map until it ends.
+pub struct ReadTxn<'a> {
+    // Declared first to end the transaction before the lock is released.
+    txn: heed::RoTxn<'a>,
+    _guard: RwLockReadGuard<'a, ()>,
+}
+
+impl<'a> Deref for ReadTxn<'a> {
+    type Target = heed::RoTxn<'a>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.txn
+    }
 }
 
 impl fmt::Debug for Db {
@@ -29,31 +142,188 @@ impl fmt::Debug for Db {
 }
 
 impl Db {
-    #[must_use]
-    pub fn new() -> Self {
-        std::fs::create_dir_all("vogelsang.mdb").expect("Failed to create db directory.");
-        let env = heed::EnvOpenOptions::new()
-            .map_size(1024 * 1024 * 1024) // 1GB
-            .max_dbs(10)
-            .open("vogelsang.mdb")
-            .unwrap();
-        let candles = env.create_database(Some("candles")).unwrap();
-        let products = env.create_database(Some("products")).unwrap();
-        let financial_reports = env.create_database(Some("financial_reports")).unwrap();
-        let company_ratios = env.create_database(Some("company_ratios")).unwrap();
-        Self {
+    /// Opens the database kept in `data_dir`.
+    pub fn new(data_dir: impl AsRef<Path>, map_size: usize) -> Result<Self, DbError> {
+        Self::open(data_dir.as_ref().join("vogelsang.mdb"), map_size)
+    }
+
+    pub fn open(path: impl AsRef<Path>, map_size: usize) -> Result<Self, DbError> {
+        let path = path.as_ref();
+        std::fs::create_dir_all(path)?;
+        // SAFETY: the environment is only ever opened by this process with these options,
+        // reopening the same path returns the already opened environment.
+        let env = unsafe {
+            heed::EnvOpenOptions::new()
+                .map_size(map_size)
+                .max_dbs(10)
+                .open(path)?
+        };
+        let mut wtx = env.write_txn()?;
+        let candles = env.create_database(&mut wtx, Some("candles"))?;
+        let products = env.create_database(&mut wtx, Some("products"))?;
+        let financial_reports = env.create_database(&mut wtx, Some("financial_reports"))?;
+        let company_ratios = env.create_database(&mut wtx, Some("company_ratios"))?;
+        let metadata = env.create_database(&mut wtx, Some("metadata"))?;
+        let portfolio_snapshots = env.create_database(&mut wtx, Some("portfolio_snapshots"))?;
+        wtx.commit()?;
+        let db = Self {
+            path: path.to_owned(),
+            map_size,
             env,
             candles,
             products,
             financial_reports,
             company_ratios,
+            metadata,
+            portfolio_snapshots,
+            symbol_ids: Arc::default(),
+            name_ids: Arc::default(),
+            resize_lock: Arc::default(),
+        };
+        db.migrate()?;
+        Ok(db)
+    }
+
+    fn migrate(&self) -> Result<(), DbError> {
+        let mut wtx = self.env.write_txn()?;
+        let found = self.metadata.get(&wtx, "schema_version")?.unwrap_or(0);
+        let unsupported = DbError::SchemaVersion {
+            found,
+            expected: SCHEMA_VERSION,
+        };
+        if found > SCHEMA_VERSION {
+            return Err(unsupported);
+        }
+        for version in found..SCHEMA_VERSION {
+            let Some(migration) = MIGRATIONS.get(version as usize) else {
+                return Err(unsupported);
+            };
+            info!(
+                from = version,
+                to = version + 1,
+                "Migrating database schema."
+            );
+            migration(self, &mut wtx)?;
+        }
+        self.metadata
+            .put(&mut wtx, "schema_version", &SCHEMA_VERSION)?;
+        wtx.commit()?;
+        Ok(())
+    }
+
+    /// Id of the product matching `query`, if it's known.
+    fn product_id(&self, query: ProductQuery) -> heed::Result<Option<String>> {
+        match query {
+            ProductQuery::Id(id) => Ok(Some(id)),
+            ProductQuery::Symbol(symbol) => self.product_id_by_symbol(&symbol),
+            ProductQuery::Name(name) => self.product_id_by_name(&name),
+            ProductQuery::Isin(isin) => {
+                let isin = isin.to_lowercase();
+                let rtxn = self.read_txn()?;
+                let mut iter = self.products.iter(&rtxn)?;
+                Ok(iter.find_map(|res| {
+                    res.ok()
+                        .filter(|(_, product)| product.isin.to_lowercase() == isin)
+                        .map(|(id, _)| id.to_owned())
+                }))
+            }
         }
     }
-}
 
-impl Default for Db {
-    fn default() -> Self {
-        Self::new()
+    /// Id of the first product whose symbol matches case-insensitively.
+    fn product_id_by_symbol(&self, symbol: &str) -> heed::Result<Option<String>> {
+        let symbol = symbol.to_lowercase();
+        if let Some(id) = self.symbol_ids.get(&symbol) {
+            return Ok(Some(id.clone()));
+        }
+        let rtxn = self.read_txn()?;
+        let id = self.products.iter(&rtxn)?.find_map(|res| {
+            res.ok()
+                .filter(|(_, product)| product.symbol.to_lowercase() == symbol)
+                .map(|(_, product)| product.id)
+        });
+        if let Some(id) = &id {
+            self.symbol_ids.insert(symbol, id.clone());
+        }
+        Ok(id)
+    }
+
+    /// Id of the first product whose name matches the case-insensitive pattern.
+    fn product_id_by_name(&self, name: &str) -> heed::Result<Option<String>> {
+        if let Some(id) = self.name_ids.get(name) {
+            return Ok(Some(id.clone()));
+        }
+        let rgx = regex::Regex::new(&format!("(?i){}", name)).unwrap();
+        let rtxn = self.read_txn()?;
+        let id = self.products.iter(&rtxn)?.find_map(|res| {
+            res.ok()
+                .filter(|(_, product)| rgx.is_match(&product.name))
+                .map(|(_, product)| product.id)
+        });
+        if let Some(id) = &id {
+            self.name_ids.insert(name.to_owned(), id.clone());
+        }
+        Ok(id)
+    }
+
+    /// Drops cached lookups that could resolve differently once `product` is stored.
+    fn invalidate_product(&self, product: &ProductDetails) {
+        let symbol = product.symbol.to_lowercase();
+        self.symbol_ids
+            .retain(|key, id| *id != product.id && *key != symbol);
+        self.name_ids.retain(|key, id| {
+            *id != product.id
+                && !regex::Regex::new(&format!("(?i){}", key))
+                    .is_ok_and(|rgx| rgx.is_match(&product.name))
+        });
+    }
+
+    /// Opens a read transaction, every read goes through it so the map can't be resized under
+    /// it.
+    pub fn read_txn(&self) -> heed::Result<ReadTxn<'_>> {
+        let guard = self
+            .resize_lock
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        Ok(ReadTxn {
+            txn: self.env.read_txn()?,
+            _guard: guard,
+        })
+    }
+
+    /// Runs `f` in a write transaction, doubling the map size and retrying once when the
+    /// environment is full.
+    pub fn write<F>(&self, f: F) -> heed::Result<()>
+    where
+        F: Fn(&mut heed::RwTxn) -> heed::Result<()>,
+    {
+        let run = || {
+            let _guard = self
+                .resize_lock
+                .read()
+                .unwrap_or_else(PoisonError::into_inner);
+            let mut wtx = self.env.write_txn()?;
+            f(&mut wtx)?;
+            wtx.commit()
+        };
+        match run() {
+            Err(heed::Error::Mdb(heed::MdbError::MapFull)) => {
+                let map_size = self.env.info().map_size * 2;
+                warn!(map_size, "Database map is full, growing.");
+                {
+                    let _guard = self
+                        .resize_lock
+                        .write()
+                        .unwrap_or_else(PoisonError::into_inner);
+                    // SAFETY: every transaction of this process holds the lock shared, so
+                    // none is open while it's held exclusively, and the environment isn't
+                    // opened by another process.
+                    unsafe { self.env.resize(map_size)? };
+                }
+                run()
+            }
+            res => res,
+        }
     }
 }
 
@@ -61,8 +331,9 @@ impl Default for Db {
 impl Lifecycle for Db {
     type Supervision = OneToOne;
 
-    async fn reset(&self, _puppeter: &Puppeter) -> Result<Self, CriticalError> {
-        Ok(Self::new())
+    async fn reset(&self, puppeter: &Puppeter) -> Result<Self, CriticalError> {
+        Self::open(&self.path, self.map_size)
+            .map_err(|e| CriticalError::new(puppeter.pid, e.to_string()))
     }
 }
 
@@ -78,51 +349,63 @@ impl Handler<ProductDetails> for Db {
         puppeter: &Puppeter,
     ) -> Result<Self::Response, PuppetError> {
         info!(id = msg.id, symbol = msg.symbol, "Saving product.");
-        let mut wtx = self
-            .env
-            .write_txn()
-            .map_err(|e| PuppetError::critical(puppeter.pid, e))?;
-        self.products.put(&mut wtx, &msg.id, &msg).map_err(|e| {
-            error!(
-                id = msg.id,
-                symbol = msg.symbol,
-                error = %e,
-                "Failed to save product."
-            );
-            PuppetError::critical(puppeter.pid, e)
-        })?;
-        wtx.commit()
-            .map_err(|e| PuppetError::critical(puppeter.pid, e))
+        self.write(|wtx| self.products.put(wtx, &msg.id, &msg))
+            .map_err(|e| {
+                error!(
+                    id = msg.id,
+                    symbol = msg.symbol,
+                    error = %e,
+                    "Failed to save product."
+                );
+                PuppetError::critical(puppeter.pid, e)
+            })?;
+        self.invalidate_product(&msg);
+        Ok(())
     }
 }
 
+/// Stores the candles of `quotes` fetched in `interval`.
+#[derive(Debug, Clone)]
+pub struct StoreQuotes {
+    pub quotes: Quotes,
+    pub interval: Period,
+}
+
 #[async_trait]
-impl Handler<Quotes> for Db {
+impl Handler<StoreQuotes> for Db {
     type Response = ();
 
     type Executor = SequentialExecutor;
 
     async fn handle_message(
         &mut self,
-        msg: Quotes,
+        msg: StoreQuotes,
         puppeter: &Puppeter,
     ) -> Result<Self::Response, PuppetError> {
-        info!(id = msg.id, "Saving candles.");
-        let mut wtx = self
-            .env
-            .write_txn()
-            .map_err(|e| PuppetError::critical(puppeter.pid, e))?;
-        let candles = Candles::from(msg.clone());
-        self.candles.put(&mut wtx, &msg.id, &candles).map_err(|e| {
-            error!(
-                id = msg.id,
-                error = %e,
-                "Failed to save candles."
-            );
-            PuppetError::critical(puppeter.pid, e)
-        })?;
-        wtx.commit()
-            .map_err(|e| PuppetError::critical(puppeter.pid, e))
+        let id = msg.quotes.id.clone();
+        info!(id, interval = ?msg.interval, "Saving candles.");
+        let Some(interval) = interval_name(msg.interval) else {
+            error!(id, interval = ?msg.interval, "Refusing to save intraday candles.");
+            return Err(PuppetError::non_critical(
+                puppeter.pid,
+                "intraday candles aren't stored",
+            ));
+        };
+        let key = candles_key(&id, interval);
+        let candles = Candles::from(msg.quotes);
+        if let Err(e) = candles.validate() {
+            error!(id, error = %e, "Refusing to save malformed candles.");
+            return Err(PuppetError::non_critical(puppeter.pid, e));
+        }
+        self.write(|wtx| self.candles.put(wtx, &key, &candles))
+            .map_err(|e| {
+                error!(
+                    id,
+                    error = %e,
+                    "Failed to save candles."
+                );
+                PuppetError::critical(puppeter.pid, e)
+            })
     }
 }
 
@@ -136,12 +419,7 @@ impl Handler<FinancialReports> for Db {
         puppeter: &Puppeter,
     ) -> Result<Self::Response, PuppetError> {
         info!(id = msg.id, "Saving financial reports.");
-        let mut wtx = self
-            .env
-            .write_txn()
-            .map_err(|e| PuppetError::critical(puppeter.pid, e))?;
-        self.financial_reports
-            .put(&mut wtx, &msg.id, &msg)
+        self.write(|wtx| self.financial_reports.put(wtx, &msg.id, &msg))
             .map_err(|e| {
                 error!(
                     id = msg.id,
@@ -149,9 +427,7 @@ impl Handler<FinancialReports> for Db {
                     "Failed to save financial reports."
                 );
                 PuppetError::critical(puppeter.pid, e)
-            })?;
-        wtx.commit()
-            .map_err(|e| PuppetError::critical(puppeter.pid, e))
+            })
     }
 }
 
@@ -165,12 +441,7 @@ impl Handler<CompanyRatios> for Db {
         puppeter: &Puppeter,
     ) -> Result<Self::Response, PuppetError> {
         info!(id = msg.id, "Saving company ratios.");
-        let mut wtx = self
-            .env
-            .write_txn()
-            .map_err(|e| PuppetError::critical(puppeter.pid, e))?;
-        self.company_ratios
-            .put(&mut wtx, &msg.id, &msg)
+        self.write(|wtx| self.company_ratios.put(wtx, &msg.id, &msg))
             .map_err(|e| {
                 error!(
                     id = msg.id,
@@ -178,17 +449,166 @@ impl Handler<CompanyRatios> for Db {
                     "Failed to save company ratios."
                 );
                 PuppetError::critical(puppeter.pid, e)
-            })?;
-        wtx.commit()
+            })
+    }
+}
+
+/// Position as it was when its portfolio snapshot was taken.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PositionSnapshot {
+    pub id: String,
+    pub size: f64,
+    pub value: f64,
+    pub profit: f64,
+}
+
+/// Summary of the portfolio on a day, values are in the account currency.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PortfolioSnapshot {
+    pub date: NaiveDate,
+    /// Value of all positions, cash included.
+    pub value: f64,
+    pub profit: f64,
+    pub positions: Vec<PositionSnapshot>,
+}
+
+impl PortfolioSnapshot {
+    #[must_use]
+    pub fn new(date: NaiveDate, portfolio: &Portfolio) -> Self {
+        let positions = portfolio
+            .0
+            .iter()
+            .map(|position| PositionSnapshot {
+                id: position.inner.id.clone(),
+                size: position.inner.size,
+                value: position.inner.value,
+                profit: position.inner.total_profit.amount,
+            })
+            .collect::<Vec<_>>();
+        Self::from_positions(date, positions)
+    }
+
+    /// Totals the value and profit of the positions.
+    #[must_use]
+    pub fn from_positions(date: NaiveDate, positions: Vec<PositionSnapshot>) -> Self {
+        Self {
+            date,
+            value: positions.iter().map(|position| position.value).sum(),
+            profit: positions.iter().map(|position| position.profit).sum(),
+            positions,
+        }
+    }
+}
+
+/// Stores a snapshot, replacing any taken earlier the same day.
+#[derive(Debug, Clone)]
+pub struct SaveSnapshot(pub PortfolioSnapshot);
+
+#[async_trait]
+impl Handler<SaveSnapshot> for Db {
+    type Response = ();
+    type Executor = SequentialExecutor;
+    async fn handle_message(
+        &mut self,
+        msg: SaveSnapshot,
+        puppeter: &Puppeter,
+    ) -> Result<Self::Response, PuppetError> {
+        let key = msg.0.date.to_string();
+        info!(date = %key, "Saving portfolio snapshot.");
+        self.write(|wtx| self.portfolio_snapshots.put(wtx, &key, &msg.0))
+            .map_err(|e| {
+                error!(date = %key, error = %e, "Failed to save portfolio snapshot.");
+                PuppetError::critical(puppeter.pid, e)
+            })
+    }
+}
+
+/// Number of entries in each table.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct EntryCounts {
+    pub products: u64,
+    pub candles: u64,
+    pub financial_reports: u64,
+    pub company_ratios: u64,
+    pub portfolio_snapshots: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CountEntries;
+
+#[async_trait]
+impl Handler<CountEntries> for Db {
+    type Response = EntryCounts;
+
+    type Executor = ConcurrentExecutor;
+
+    async fn handle_message(
+        &mut self,
+        _msg: CountEntries,
+        puppeter: &Puppeter,
+    ) -> Result<Self::Response, PuppetError> {
+        let rtxn = self
+            .read_txn()
+            .map_err(|e| PuppetError::critical(puppeter.pid, e))?;
+        let count = || -> heed::Result<EntryCounts> {
+            Ok(EntryCounts {
+                products: self.products.len(&rtxn)?,
+                candles: self.candles.len(&rtxn)?,
+                financial_reports: self.financial_reports.len(&rtxn)?,
+                company_ratios: self.company_ratios.len(&rtxn)?,
+                portfolio_snapshots: self.portfolio_snapshots.len(&rtxn)?,
+            })
+        };
+        count().map_err(|e| PuppetError::critical(puppeter.pid, e))
+    }
+}
+
+/// Snapshots taken between the dates, inclusive, oldest first.
+#[derive(Debug, Clone, Copy)]
+pub struct GetSnapshotHistory {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+}
+
+#[async_trait]
+impl Handler<GetSnapshotHistory> for Db {
+    type Response = Vec<PortfolioSnapshot>;
+
+    type Executor = ConcurrentExecutor;
+
+    async fn handle_message(
+        &mut self,
+        msg: GetSnapshotHistory,
+        puppeter: &Puppeter,
+    ) -> Result<Self::Response, PuppetError> {
+        self.snapshot_history(msg.from, msg.to)
             .map_err(|e| PuppetError::critical(puppeter.pid, e))
     }
 }
 
+impl Db {
+    fn snapshot_history(
+        &self,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> heed::Result<Vec<PortfolioSnapshot>> {
+        let (from, to) = (from.to_string(), to.to_string());
+        let rtxn = self.read_txn()?;
+        let snapshots = self
+            .portfolio_snapshots
+            .range(&rtxn, &(from.as_str()..=to.as_str()))?
+            .map(|res| res.map(|(_, snapshot)| snapshot))
+            .collect();
+        snapshots
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ProductQuery {
     Id(String),
     Symbol(String),
     Name(String),
+    Isin(String),
 }
 
 #[async_trait]
@@ -202,60 +622,86 @@ impl Handler<ProductQuery> for Db {
         msg: ProductQuery,
         puppeter: &Puppeter,
     ) -> Result<Self::Response, PuppetError> {
+        let id = self
+            .product_id(msg)
+            .map_err(|e| PuppetError::critical(puppeter.pid, e))?;
+        let Some(id) = id else {
+            return Ok(None);
+        };
+        let rtxn = self
+            .read_txn()
+            .map_err(|e| PuppetError::critical(puppeter.pid, e))?;
+        self.products
+            .get(&rtxn, &id)
+            .map_err(|e| PuppetError::critical(puppeter.pid, e))
+    }
+}
+
+/// All products matching `query`, exact symbol or ISIN matches first, then name matches.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchProducts {
+    pub query: String,
+    pub limit: usize,
+}
+
+#[async_trait]
+impl Handler<SearchProducts> for Db {
+    type Response = Vec<ProductDetails>;
+
+    type Executor = ConcurrentExecutor;
+
+    async fn handle_message(
+        &mut self,
+        msg: SearchProducts,
+        puppeter: &Puppeter,
+    ) -> Result<Self::Response, PuppetError> {
+        let rgx = regex::Regex::new(&format!("(?i){}", regex::escape(&msg.query)))
+            .map_err(|e| PuppetError::non_critical(puppeter.pid, e))?;
+        let query = msg.query.to_lowercase();
         let rtxn = self
-            .env
             .read_txn()
             .map_err(|e| PuppetError::critical(puppeter.pid, e))?;
-        match msg {
-            ProductQuery::Id(id) => {
-                return self
-                    .products
-                    .get(&rtxn, &id)
-                    .map_err(|e| PuppetError::critical(puppeter.pid, e));
-            }
-            ProductQuery::Symbol(symbol) => {
-                let mut iter = self
-                    .products
-                    .iter(&rtxn)
-                    .map_err(|e| PuppetError::critical(puppeter.pid, e))?;
-                while let Some(Ok((_, product))) = iter.next() {
-                    println!("{:?}", product.symbol);
-                    if product.symbol.to_lowercase() == symbol.to_lowercase() {
-                        return Ok(Some(product));
-                    }
-                }
-            }
-            ProductQuery::Name(name) => {
-                let rgx = regex::Regex::new(&format!("(?i){}", name)).unwrap();
-                let mut iter = self
-                    .products
-                    .iter(&rtxn)
-                    .map_err(|e| PuppetError::critical(puppeter.pid, e))?;
-                while let Some(Ok((_, product))) = iter.next() {
-                    if rgx.is_match(&product.name) {
-                        return Ok(Some(product));
-                    }
+        let iter = self
+            .products
+            .iter(&rtxn)
+            .map_err(|e| PuppetError::critical(puppeter.pid, e))?;
+        let mut matches = iter
+            .filter_map(|res| {
+                let (_, product) = res.ok()?;
+                if product.symbol.to_lowercase() == query || product.isin.to_lowercase() == query {
+                    Some((0, product))
+                } else {
+                    rgx.is_match(&product.name).then_some((1, product))
                 }
-            }
-        }
-        Ok(None)
+            })
+            .collect::<Vec<_>>();
+        matches.sort_by_key(|(rank, _)| *rank);
+        Ok(matches
+            .into_iter()
+            .take(msg.limit)
+            .map(|(_, product)| product)
+            .collect())
     }
 }
 
+/// Candles of a product in a single interval.
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub enum CandlesQuery {
-    Id(String),
-    Symbol(String),
-    Name(String),
+pub struct CandlesQuery {
+    pub product: ProductQuery,
+    pub interval: Period,
 }
 
+impl CandlesQuery {
+    #[must_use]
+    pub const fn new(product: ProductQuery, interval: Period) -> Self {
+        Self { product, interval }
+    }
+}
+
+/// Candles of the product in the `DEFAULT_INTERVAL`.
 impl From<ProductQuery> for CandlesQuery {
     fn from(value: ProductQuery) -> Self {
-        match value {
-            ProductQuery::Id(id) => Self::Id(id),
-            ProductQuery::Symbol(symbol) => Self::Symbol(symbol),
-            ProductQuery::Name(name) => Self::Name(name),
-        }
+        Self::new(value, DEFAULT_INTERVAL)
     }
 }
 
@@ -270,69 +716,31 @@ impl Handler<CandlesQuery> for Db {
         msg: CandlesQuery,
         puppeter: &Puppeter,
     ) -> Result<Self::Response, PuppetError> {
-        match msg {
-            CandlesQuery::Id(id) => {
-                let rtxn = self
-                    .env
-                    .read_txn()
-                    .map_err(|e| PuppetError::critical(puppeter.pid, e))?;
-                return self
-                    .candles
-                    .get(&rtxn, &id)
-                    .map_err(|e| PuppetError::critical(puppeter.pid, e));
-            }
-            CandlesQuery::Symbol(symbol) => {
-                let new_msg = {
-                    let rtxn = self
-                        .env
-                        .read_txn()
-                        .map_err(|e| PuppetError::critical(puppeter.pid, e))?;
-                    let mut iter = self
-                        .products
-                        .iter(&rtxn)
-                        .map_err(|e| PuppetError::critical(puppeter.pid, e))?;
-                    iter.find_map(|res| {
-                        res.ok()
-                            .filter(|(_, product)| {
-                                product.symbol.to_lowercase() == symbol.to_lowercase()
-                            })
-                            .map(|(_, product)| CandlesQuery::Id(product.id))
-                    })
-                };
-                if let Some(msg) = new_msg {
-                    return puppeter
-                        .ask::<Self, _>(msg)
-                        .await
-                        .map_err(|e| PuppetError::critical(puppeter.pid, e));
-                }
-                return Ok(None);
-            }
-            CandlesQuery::Name(name) => {
-                let rgx = regex::Regex::new(&format!("(?i){}", name)).unwrap();
-                let new_msg = {
-                    let rtxn = self
-                        .env
-                        .read_txn()
-                        .map_err(|e| PuppetError::critical(puppeter.pid, e))?;
-                    let mut iter = self
-                        .products
-                        .iter(&rtxn)
-                        .map_err(|e| PuppetError::critical(puppeter.pid, e))?;
-                    iter.find_map(|res| {
-                        res.ok()
-                            .filter(|(_, product)| rgx.is_match(&product.name))
-                            .map(|(_, product)| CandlesQuery::Id(product.id))
-                    })
-                };
-                if let Some(msg) = new_msg {
-                    return puppeter
-                        .ask::<Self, _>(msg)
-                        .await
-                        .map_err(|e| PuppetError::critical(puppeter.pid, e));
-                }
-                return Ok(None);
+        let Some(id) = self
+            .product_id(msg.product)
+            .map_err(|e| PuppetError::critical(puppeter.pid, e))?
+        else {
+            return Ok(None);
+        };
+        let Some(interval) = interval_name(msg.interval) else {
+            warn!(id, interval = ?msg.interval, "Intraday candles aren't stored.");
+            return Ok(None);
+        };
+        let rtxn = self
+            .read_txn()
+            .map_err(|e| PuppetError::critical(puppeter.pid, e))?;
+        let candles = self
+            .candles
+            .get(&rtxn, &candles_key(&id, interval))
+            .map_err(|e| PuppetError::critical(puppeter.pid, e))?;
+        // Malformed candles are treated as missing rather than turning into NaN further on.
+        Ok(candles.filter(|candles| match candles.validate() {
+            Ok(()) => true,
+            Err(e) => {
+                warn!(id, error = %e, "Stored candles are malformed.");
+                false
             }
-        }
+        }))
     }
 }
 
@@ -341,6 +749,7 @@ pub enum FinanclaReportsQuery {
     Id(String),
     Symbol(String),
     Name(String),
+    Isin(String),
 }
 
 impl From<ProductQuery> for FinanclaReportsQuery {
@@ -349,6 +758,7 @@ impl From<ProductQuery> for FinanclaReportsQuery {
             ProductQuery::Id(id) => Self::Id(id),
             ProductQuery::Symbol(symbol) => Self::Symbol(symbol),
             ProductQuery::Name(name) => Self::Name(name),
+            ProductQuery::Isin(isin) => Self::Isin(isin),
         }
     }
 }
@@ -365,7 +775,6 @@ impl Handler<FinanclaReportsQuery> for Db {
         match msg {
             FinanclaReportsQuery::Id(id) => {
                 let rtxn = self
-                    .env
                     .read_txn()
                     .map_err(|e| PuppetError::critical(puppeter.pid, e))?;
                 return self
@@ -374,9 +783,21 @@ impl Handler<FinanclaReportsQuery> for Db {
                     .map_err(|e| PuppetError::critical(puppeter.pid, e));
             }
             FinanclaReportsQuery::Symbol(symbol) => {
+                let new_msg = self
+                    .product_id_by_symbol(&symbol)
+                    .map_err(|e| PuppetError::critical(puppeter.pid, e))?
+                    .map(FinanclaReportsQuery::Id);
+                if let Some(msg) = new_msg {
+                    return puppeter
+                        .ask::<Self, _>(msg)
+                        .await
+                        .map_err(|e| PuppetError::critical(puppeter.pid, e));
+                }
+                return Ok(None);
+            }
+            FinanclaReportsQuery::Isin(isin) => {
                 let new_msg = {
                     let rtxn = self
-                        .env
                         .read_txn()
                         .map_err(|e| PuppetError::critical(puppeter.pid, e))?;
                     let mut iter = self
@@ -386,7 +807,7 @@ impl Handler<FinanclaReportsQuery> for Db {
                     iter.find_map(|res| {
                         res.ok()
                             .filter(|(_, product)| {
-                                product.symbol.to_lowercase() == symbol.to_lowercase()
+                                product.isin.to_lowercase() == isin.to_lowercase()
                             })
                             .map(|(_, product)| FinanclaReportsQuery::Id(product.id))
                     })
@@ -400,22 +821,10 @@ impl Handler<FinanclaReportsQuery> for Db {
                 return Ok(None);
             }
             FinanclaReportsQuery::Name(name) => {
-                let rgx = regex::Regex::new(&format!("(?i){}", name)).unwrap();
-                let new_msg = {
-                    let rtxn = self
-                        .env
-                        .read_txn()
-                        .map_err(|e| PuppetError::critical(puppeter.pid, e))?;
-                    let mut iter = self
-                        .products
-                        .iter(&rtxn)
-                        .map_err(|e| PuppetError::critical(puppeter.pid, e))?;
-                    iter.find_map(|res| {
-                        res.ok()
-                            .filter(|(_, product)| rgx.is_match(&product.name))
-                            .map(|(_, product)| FinanclaReportsQuery::Id(product.id))
-                    })
-                };
+                let new_msg = self
+                    .product_id_by_name(&name)
+                    .map_err(|e| PuppetError::critical(puppeter.pid, e))?
+                    .map(FinanclaReportsQuery::Id);
                 if let Some(msg) = new_msg {
                     return puppeter
                         .ask::<Self, _>(msg)
@@ -433,6 +842,7 @@ pub enum CompanyRatiosQuery {
     Id(String),
     Symbol(String),
     Name(String),
+    Isin(String),
 }
 
 impl From<ProductQuery> for CompanyRatiosQuery {
@@ -441,6 +851,7 @@ impl From<ProductQuery> for CompanyRatiosQuery {
             ProductQuery::Id(id) => Self::Id(id),
             ProductQuery::Symbol(symbol) => Self::Symbol(symbol),
             ProductQuery::Name(name) => Self::Name(name),
+            ProductQuery::Isin(isin) => Self::Isin(isin),
         }
     }
 }
@@ -457,7 +868,6 @@ impl Handler<CompanyRatiosQuery> for Db {
         match msg {
             CompanyRatiosQuery::Id(id) => {
                 let rtxn = self
-                    .env
                     .read_txn()
                     .map_err(|e| PuppetError::critical(puppeter.pid, e))?;
                 return self
@@ -466,9 +876,21 @@ impl Handler<CompanyRatiosQuery> for Db {
                     .map_err(|e| PuppetError::critical(puppeter.pid, e));
             }
             CompanyRatiosQuery::Symbol(symbol) => {
+                let new_msg = self
+                    .product_id_by_symbol(&symbol)
+                    .map_err(|e| PuppetError::critical(puppeter.pid, e))?
+                    .map(CompanyRatiosQuery::Id);
+                if let Some(msg) = new_msg {
+                    return puppeter
+                        .ask::<Self, _>(msg)
+                        .await
+                        .map_err(|e| PuppetError::critical(puppeter.pid, e));
+                }
+                return Ok(None);
+            }
+            CompanyRatiosQuery::Isin(isin) => {
                 let new_msg = {
                     let rtxn = self
-                        .env
                         .read_txn()
                         .map_err(|e| PuppetError::critical(puppeter.pid, e))?;
                     let mut iter = self
@@ -478,7 +900,7 @@ impl Handler<CompanyRatiosQuery> for Db {
                     iter.find_map(|res| {
                         res.ok()
                             .filter(|(_, product)| {
-                                product.symbol.to_lowercase() == symbol.to_lowercase()
+                                product.isin.to_lowercase() == isin.to_lowercase()
                             })
                             .map(|(_, product)| CompanyRatiosQuery::Id(product.id))
                     })
@@ -492,22 +914,10 @@ impl Handler<CompanyRatiosQuery> for Db {
                 return Ok(None);
             }
             CompanyRatiosQuery::Name(name) => {
-                let rgx = regex::Regex::new(&format!("(?i){}", name)).unwrap();
-                let new_msg = {
-                    let rtxn = self
-                        .env
-                        .read_txn()
-                        .map_err(|e| PuppetError::critical(puppeter.pid, e))?;
-                    let mut iter = self
-                        .products
-                        .iter(&rtxn)
-                        .map_err(|e| PuppetError::critical(puppeter.pid, e))?;
-                    iter.find_map(|res| {
-                        res.ok()
-                            .filter(|(_, product)| rgx.is_match(&product.name))
-                            .map(|(_, product)| CompanyRatiosQuery::Id(product.id))
-                    })
-                };
+                let new_msg = self
+                    .product_id_by_name(&name)
+                    .map_err(|e| PuppetError::critical(puppeter.pid, e))?
+                    .map(CompanyRatiosQuery::Id);
                 if let Some(msg) = new_msg {
                     return puppeter
                         .ask::<Self, _>(msg)
@@ -533,39 +943,84 @@ impl Handler<DeleteData> for Db {
         puppeter: &Puppeter,
     ) -> Result<Self::Response, PuppetError> {
         info!(id = %msg.0, "Deleting data.");
-        let mut wtx = self
-            .env
-            .write_txn()
-            .map_err(|e| PuppetError::critical(puppeter.pid, e))?;
-        self.candles
-            .delete(&mut wtx, &msg.0)
-            .map_err(|e| PuppetError::critical(puppeter.pid, e))?;
-        self.products
-            .delete(&mut wtx, &msg.0)
-            .map_err(|e| PuppetError::critical(puppeter.pid, e))?;
-        self.financial_reports
-            .delete(&mut wtx, &msg.0)
-            .map_err(|e| PuppetError::critical(puppeter.pid, e))?;
-        self.company_ratios
-            .delete(&mut wtx, &msg.0)
-            .map_err(|e| PuppetError::critical(puppeter.pid, e))?;
-        wtx.commit()
-            .map_err(|e| PuppetError::critical(puppeter.pid, e))
+        let prefix = format!("{}:", msg.0);
+        self.write(|wtx| {
+            let keys = self
+                .candles
+                .prefix_iter(wtx, &prefix)?
+                .map(|res| res.map(|(key, _)| key.to_owned()))
+                .collect::<heed::Result<Vec<_>>>()?;
+            for key in &keys {
+                self.candles.delete(wtx, key)?;
+            }
+            self.products.delete(wtx, &msg.0)?;
+            self.financial_reports.delete(wtx, &msg.0)?;
+            self.company_ratios.delete(wtx, &msg.0)?;
+            Ok(())
+        })
+        .map_err(|e| {
+            error!(id = %msg.0, error = %e, "Failed to delete data.");
+            PuppetError::critical(puppeter.pid, e)
+        })?;
+        self.symbol_ids.retain(|_, id| *id != msg.0);
+        self.name_ids.retain(|_, id| *id != msg.0);
+        Ok(())
+    }
+}
+
+/// Disables an asset in the settings and, with `purge_data`, deletes its data as well, so the
+/// config and the databases can't drift apart. Answers whether the asset was configured.
+#[derive(Debug, Clone)]
+pub struct RemoveAsset {
+    pub id: String,
+    pub purge_data: bool,
+}
+
+#[async_trait]
+impl Handler<RemoveAsset> for Db {
+    type Response = bool;
+    type Executor = ConcurrentExecutor;
+    async fn handle_message(
+        &mut self,
+        msg: RemoveAsset,
+        puppeter: &Puppeter,
+    ) -> Result<Self::Response, PuppetError> {
+        let removed = puppeter
+            .ask::<Settings, _>(DeleteAsset(msg.id.clone()))
+            .await
+            .map_err(|e| {
+                error!(error = %e, id = %msg.id, "Failed to remove asset from settings");
+                PuppetError::critical(puppeter.pid, e)
+            })?;
+        if msg.purge_data {
+            puppeter
+                .ask::<Self, _>(DeleteData(msg.id.clone()))
+                .await
+                .map_err(|e| {
+                    error!(error = %e, id = %msg.id, "Failed to delete asset from database");
+                    PuppetError::critical(puppeter.pid, e)
+                })?;
+        }
+        Ok(removed)
     }
 }
 
+/// Deletes the data of every product that isn't among the configured assets and returns the
+/// affected ids. With `dry_run` nothing is deleted.
 #[derive(Debug, Clone)]
-pub struct CleanUp;
+pub struct CleanUp {
+    pub dry_run: bool,
+}
 
 #[async_trait]
 impl Handler<CleanUp> for Db {
-    type Response = ();
+    type Response = Vec<String>;
 
     type Executor = ConcurrentExecutor;
 
     async fn handle_message(
         &mut self,
-        _msg: CleanUp,
+        msg: CleanUp,
         puppeter: &Puppeter,
     ) -> Result<Self::Response, PuppetError> {
         let settings = puppeter
@@ -582,9 +1037,14 @@ impl Handler<CleanUp> for Db {
             .map(|(id, _)| id.clone())
             .collect::<HashSet<_>>();
 
+        // An empty or partially loaded config would otherwise wipe the whole database.
+        if assets.is_empty() {
+            warn!("No assets configured, skipping clean up");
+            return Ok(Vec::new());
+        }
+
         let to_delete = {
             let rtxn = self
-                .env
                 .read_txn()
                 .map_err(|e| PuppetError::critical(puppeter.pid, e))?;
 
@@ -593,21 +1053,369 @@ impl Handler<CleanUp> for Db {
                 .iter(&rtxn)
                 .map_err(|e| PuppetError::critical(puppeter.pid, e))?;
 
-            iter.filter_map(|res| {
-                let (id, _) = res.unwrap();
-                (!assets.contains(id)).then(|| id.to_owned())
-            })
-            .collect::<HashSet<_>>()
+            let mut ids = iter
+                .filter_map(|res| {
+                    let (id, _) = res.unwrap();
+                    (!assets.contains(id)).then(|| id.to_owned())
+                })
+                .collect::<Vec<_>>();
+            ids.sort();
+            ids
         };
 
-        for id in to_delete {
+        if msg.dry_run {
+            info!(count = to_delete.len(), "Clean up dry run");
+            return Ok(to_delete);
+        }
+
+        for id in &to_delete {
             puppeter
-                .ask::<Self, _>(DeleteData(id))
+                .ask::<Self, _>(DeleteData(id.clone()))
                 .await
                 .map_err(|e| PuppetError::critical(puppeter.pid, e))?;
         }
+        info!(count = to_delete.len(), "Cleaned up");
+
+        Ok(to_delete)
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct DbArchive {
+    pub candles: Vec<(String, Candles)>,
+    pub products: Vec<(String, ProductDetails)>,
+    pub financial_reports: Vec<(String, FinancialReports)>,
+    pub company_ratios: Vec<(String, CompanyRatios)>,
+    pub portfolio_snapshots: Vec<(String, PortfolioSnapshot)>,
+}
+
+/// Archive layout exported before portfolio snapshots were stored.
+#[derive(Debug, Deserialize)]
+struct LegacyDbArchive {
+    candles: Vec<(String, Candles)>,
+    products: Vec<(String, ProductDetails)>,
+    financial_reports: Vec<(String, FinancialReports)>,
+    company_ratios: Vec<(String, CompanyRatios)>,
+}
+
+impl From<LegacyDbArchive> for DbArchive {
+    fn from(archive: LegacyDbArchive) -> Self {
+        Self {
+            candles: archive.candles,
+            products: archive.products,
+            financial_reports: archive.financial_reports,
+            company_ratios: archive.company_ratios,
+            portfolio_snapshots: Vec::new(),
+        }
+    }
+}
+
+impl DbArchive {
+    /// Decodes an archive, falling back to the layout without portfolio snapshots.
+    pub fn from_bytes(bytes: &[u8]) -> bincode::Result<Self> {
+        bincode::deserialize::<Self>(bytes).or_else(|e| {
+            bincode::deserialize::<LegacyDbArchive>(bytes)
+                .map(Self::from)
+                .map_err(|_| e)
+        })
+    }
+}
+
+impl Db {
+    fn archive(&self) -> heed::Result<DbArchive> {
+        fn entries<T>(
+            db: &heed::Database<heed::types::Str, heed::types::SerdeBincode<T>>,
+            rtxn: &heed::RoTxn<'_>,
+        ) -> heed::Result<Vec<(String, T)>>
+        where
+            T: Serialize + serde::de::DeserializeOwned,
+        {
+            db.iter(rtxn)?
+                .map(|res| res.map(|(key, value)| (key.to_owned(), value)))
+                .collect()
+        }
+        let rtxn = self.read_txn()?;
+        Ok(DbArchive {
+            candles: entries(&self.candles, &rtxn)?,
+            products: entries(&self.products, &rtxn)?,
+            financial_reports: entries(&self.financial_reports, &rtxn)?,
+            company_ratios: entries(&self.company_ratios, &rtxn)?,
+            portfolio_snapshots: entries(&self.portfolio_snapshots, &rtxn)?,
+        })
+    }
+
+    /// Replaces every table with the archive's content in a single transaction, an error
+    /// aborts the whole restore.
+    fn restore(&self, archive: &DbArchive) -> heed::Result<()> {
+        self.write(|wtx| {
+            self.candles.clear(wtx)?;
+            self.products.clear(wtx)?;
+            self.financial_reports.clear(wtx)?;
+            self.company_ratios.clear(wtx)?;
+            self.portfolio_snapshots.clear(wtx)?;
+            // Archives exported before candles were keyed by interval hold monthly candles.
+            for (key, candles) in &archive.candles {
+                if key.contains(':') {
+                    self.candles.put(wtx, key, candles)?;
+                } else {
+                    self.candles
+                        .put(wtx, &candles_key(key, DEFAULT_INTERVAL_NAME), candles)?;
+                }
+            }
+            for (id, product) in &archive.products {
+                self.products.put(wtx, id, product)?;
+            }
+            for (id, financial_reports) in &archive.financial_reports {
+                self.financial_reports.put(wtx, id, financial_reports)?;
+            }
+            for (id, company_ratios) in &archive.company_ratios {
+                self.company_ratios.put(wtx, id, company_ratios)?;
+            }
+            for (date, snapshot) in &archive.portfolio_snapshots {
+                self.portfolio_snapshots.put(wtx, date, snapshot)?;
+            }
+            Ok(())
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ExportDb {
+    pub path: PathBuf,
+}
+
+#[async_trait]
+impl Handler<ExportDb> for Db {
+    type Response = ();
+    type Executor = ConcurrentExecutor;
+    async fn handle_message(
+        &mut self,
+        msg: ExportDb,
+        puppeter: &Puppeter,
+    ) -> Result<Self::Response, PuppetError> {
+        info!(path = %msg.path.display(), "Exporting database.");
+        let archive = self
+            .archive()
+            .map_err(|e| PuppetError::critical(puppeter.pid, e))?;
+        let bytes = bincode::serialize(&archive).map_err(|e| {
+            error!(error = %e, "Failed to serialize database archive.");
+            PuppetError::non_critical(puppeter.pid, e)
+        })?;
+        tokio::fs::write(&msg.path, bytes).await.map_err(|e| {
+            error!(path = %msg.path.display(), error = %e, "Failed to write database archive.");
+            PuppetError::non_critical(puppeter.pid, e)
+        })?;
+        info!(
+            products = archive.products.len(),
+            candles = archive.candles.len(),
+            snapshots = archive.portfolio_snapshots.len(),
+            "Exported database."
+        );
+        Ok(())
+    }
+}
 
+#[derive(Debug, Clone)]
+pub struct ImportDb {
+    pub path: PathBuf,
+}
+
+#[async_trait]
+impl Handler<ImportDb> for Db {
+    type Response = ();
+    type Executor = SequentialExecutor;
+    async fn handle_message(
+        &mut self,
+        msg: ImportDb,
+        puppeter: &Puppeter,
+    ) -> Result<Self::Response, PuppetError> {
+        info!(path = %msg.path.display(), "Importing database.");
+        let bytes = tokio::fs::read(&msg.path).await.map_err(|e| {
+            error!(path = %msg.path.display(), error = %e, "Failed to read database archive.");
+            PuppetError::non_critical(puppeter.pid, e)
+        })?;
+        let archive = DbArchive::from_bytes(&bytes).map_err(|e| {
+            error!(error = %e, "Failed to deserialize database archive.");
+            PuppetError::non_critical(puppeter.pid, e)
+        })?;
+
+        self.restore(&archive).map_err(|e| {
+            error!(error = %e, "Failed to import database archive.");
+            PuppetError::critical(puppeter.pid, e)
+        })?;
+        self.symbol_ids.clear();
+        self.name_ids.clear();
+        info!(
+            products = archive.products.len(),
+            candles = archive.candles.len(),
+            snapshots = archive.portfolio_snapshots.len(),
+            "Imported database."
+        );
         Ok(())
     }
 }
 
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn write_grows_full_map() {
+        let path = std::env::temp_dir().join("vogelsang-test-map-full.mdb");
+        let _ = std::fs::remove_dir_all(&path);
+        let map_size = 1024 * 1024;
+        let db = Db::open(&path, map_size).unwrap();
+        for batch in 0..100_u32 {
+            db.write(|wtx| {
+                for i in 0..1000_u32 {
+                    let key = format!("key-{batch:03}-{i:04}");
+                    db.metadata.put(wtx, &key, &i)?;
+                }
+                Ok(())
+            })
+            .unwrap();
+        }
+        assert!(db.env.info().map_size > map_size);
+        let rtxn = db.read_txn().unwrap();
+        assert_eq!(db.metadata.get(&rtxn, "key-099-0999").unwrap(), Some(999));
+        drop(rtxn);
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn resize_waits_for_readers() {
+        let path = std::env::temp_dir().join("vogelsang-test-resize-readers.mdb");
+        let _ = std::fs::remove_dir_all(&path);
+        let db = Db::open(&path, 1024 * 1024).unwrap();
+        let rtxn = db.read_txn().unwrap();
+        // An open read transaction keeps the map from being resized until it ends.
+        assert!(db.resize_lock.try_write().is_err());
+        drop(rtxn);
+        assert!(db.resize_lock.try_write().is_ok());
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn candles_keys() {
+        assert_eq!(interval_name(DEFAULT_INTERVAL), Some(DEFAULT_INTERVAL_NAME));
+        let names = [
+            Period::P1D,
+            Period::P1W,
+            Period::P1M,
+            Period::P3M,
+            Period::P6M,
+            Period::P1Y,
+            Period::P3Y,
+            Period::P5Y,
+            Period::P50Y,
+        ]
+        .map(interval_name);
+        assert!(names.iter().all(Option::is_some));
+        let unique = names.iter().collect::<std::collections::HashSet<_>>();
+        assert_eq!(unique.len(), names.len());
+        assert_eq!(candles_key("123", "P1W"), "123:P1W");
+    }
+
+    fn temp_db(name: &str) -> (PathBuf, Db) {
+        let path = std::env::temp_dir().join(format!("vogelsang-test-{name}.mdb"));
+        let _ = std::fs::remove_dir_all(&path);
+        let db = Db::open(&path, DEFAULT_MAP_SIZE).unwrap();
+        (path, db)
+    }
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 1, day).unwrap()
+    }
+
+    fn snapshot(day: u32) -> PortfolioSnapshot {
+        PortfolioSnapshot::from_positions(
+            date(day),
+            vec![
+                PositionSnapshot {
+                    id: "1".to_owned(),
+                    size: 10.0,
+                    value: 100.0,
+                    profit: 5.0,
+                },
+                PositionSnapshot {
+                    id: "2".to_owned(),
+                    size: 2.0,
+                    value: 50.0,
+                    profit: -2.5,
+                },
+            ],
+        )
+    }
+
+    fn save(db: &Db, snapshot: &PortfolioSnapshot) {
+        db.write(|wtx| {
+            db.portfolio_snapshots
+                .put(wtx, &snapshot.date.to_string(), snapshot)
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn snapshot_totals_positions() {
+        let snapshot = snapshot(1);
+        assert_eq!(snapshot.date, date(1));
+        assert_eq!(snapshot.positions.len(), 2);
+        assert!((snapshot.value - 150.0).abs() < 1e-9);
+        assert!((snapshot.profit - 2.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn snapshot_history_is_inclusive() {
+        let (path, db) = temp_db("snapshot-history");
+        for day in [1, 5, 10, 15] {
+            save(&db, &snapshot(day));
+        }
+        let dates = |from, to| {
+            db.snapshot_history(date(from), date(to))
+                .unwrap()
+                .into_iter()
+                .map(|snapshot| snapshot.date)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(dates(5, 10), vec![date(5), date(10)]);
+        assert_eq!(dates(2, 14), vec![date(5), date(10)]);
+        assert_eq!(dates(10, 10), vec![date(10)]);
+        assert!(dates(16, 20).is_empty());
+        drop(db);
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn archive_keeps_snapshots() {
+        let (from_path, from) = temp_db("archive-from");
+        save(&from, &snapshot(1));
+        save(&from, &snapshot(2));
+        let bytes = bincode::serialize(&from.archive().unwrap()).unwrap();
+        let archive = DbArchive::from_bytes(&bytes).unwrap();
+        assert_eq!(archive.portfolio_snapshots.len(), 2);
+
+        let (to_path, to) = temp_db("archive-to");
+        save(&to, &snapshot(3));
+        to.restore(&archive).unwrap();
+        let dates = to
+            .snapshot_history(date(1), date(31))
+            .unwrap()
+            .into_iter()
+            .map(|snapshot| snapshot.date)
+            .collect::<Vec<_>>();
+        assert_eq!(dates, vec![date(1), date(2)]);
+        drop((from, to));
+        std::fs::remove_dir_all(&from_path).unwrap();
+        std::fs::remove_dir_all(&to_path).unwrap();
+    }
+
+    #[test]
+    fn legacy_archive_has_no_snapshots() {
+        // Four empty tables, the layout exported before snapshots were archived.
+        let bytes = bincode::serialize(&[Vec::<()>::new(), vec![], vec![], vec![]]).unwrap();
+        let archive = DbArchive::from_bytes(&bytes).unwrap();
+        assert!(archive.products.is_empty());
+        assert!(archive.portfolio_snapshots.is_empty());
+        assert!(DbArchive::from_bytes(&bytes[..4]).is_err());
+    }
+}
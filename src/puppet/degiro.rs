@@ -1,32 +1,160 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
 use async_trait::async_trait;
-use chrono::{NaiveDate, Utc};
+use chrono::{DateTime, NaiveDate, Utc};
 use degiro_rs::{
-    api::{orders::Orders, portfolio::Portfolio, transactions::Transactions},
+    api::{
+        orders::{CreateOrderRequest, Orders},
+        portfolio::Portfolio,
+        product::ProductDetails,
+        transactions::Transactions,
+    },
     client::{Client, ClientBuilder, ClientError},
-    util::Period,
+    util::{Period, TransactionType},
 };
+use erfurt::prelude::Candles;
+use futures::{future, stream, StreamExt};
 use master_of_puppets::prelude::*;
+use serde::{Deserialize, Serialize};
+use strum::EnumString;
+use tokio::{io::AsyncWriteExt, sync::Mutex};
 use tracing::{error, info, warn};
 
 use crate::puppet::{
-    db::{Db, DeleteData},
-    settings::{DeleteAsset, GetSettings},
+    db::{
+        CandlesQuery, Db, PortfolioSnapshot, ProductQuery, RemoveAsset, SaveSnapshot, StoreQuotes,
+        DEFAULT_INTERVAL,
+    },
+    settings::GetSettings,
 };
 
 use super::settings::Settings;
 
+/// Instrument type of a DEGIRO product, anything unrecognised falls back to `Unknown`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, EnumString, Serialize, Deserialize)]
+#[strum(serialize_all = "lowercase", ascii_case_insensitive)]
+#[serde(rename_all = "lowercase")]
+pub enum ProductType {
+    Stock,
+    Etf,
+    Bond,
+    Fund,
+    Option,
+    Future,
+    Warrant,
+    Cfd,
+    Index,
+    Currency,
+    #[serde(other)]
+    Unknown,
+}
+
+pub trait ProductTypeExt {
+    fn product_type(&self) -> ProductType;
+}
+
+impl ProductTypeExt for ProductDetails {
+    /// Parses the raw `product_type` string DEGIRO sends, e.g. `STOCK` or `ETF`.
+    fn product_type(&self) -> ProductType {
+        self.product_type.parse().unwrap_or(ProductType::Unknown)
+    }
+}
+
+pub trait OrdersExt {
+    fn has_open_order(&self, product_id: u64, transaction_type: TransactionType) -> bool;
+}
+
+impl OrdersExt for Orders {
+    /// Whether a pending order on `product_id` in the same direction is already open.
+    fn has_open_order(&self, product_id: u64, transaction_type: TransactionType) -> bool {
+        self.filter_product_id(product_id)
+            .iter()
+            .any(|order| order.transaction_type == transaction_type)
+    }
+}
+
+/// Direction of a transaction, parsed from `buy` or `sell`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumString, Serialize, Deserialize)]
+#[strum(serialize_all = "lowercase", ascii_case_insensitive)]
+pub enum TransactionSide {
+    Buy,
+    Sell,
+}
+
+impl From<TransactionSide> for TransactionType {
+    fn from(side: TransactionSide) -> Self {
+        match side {
+            TransactionSide::Buy => Self::Buy,
+            TransactionSide::Sell => Self::Sell,
+        }
+    }
+}
+
+/// DEGIRO drops a session after about half an hour without requests.
+pub const SESSION_TTL: Duration = Duration::from_secs(25 * 60);
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10 * 60);
+pub const DEFAULT_REQUESTS_PER_SECOND: f64 = 5.0;
+
+/// Spaces outgoing requests evenly so that at most `requests_per_second` are sent.
+#[derive(Debug)]
+pub struct RateLimiter {
+    interval: Duration,
+    next: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    /// # Panics
+    ///
+    /// Panics unless `requests_per_second` is positive and finite.
+    #[must_use]
+    pub fn new(requests_per_second: f64) -> Self {
+        Self {
+            interval: Duration::from_secs_f64(1.0 / requests_per_second),
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Waits for the next free slot.
+    pub async fn acquire(&self) {
+        let slot = {
+            let mut next = self.next.lock().await;
+            let slot = (*next).max(Instant::now());
+            *next = slot + self.interval;
+            slot
+        };
+        tokio::time::sleep_until(slot.into()).await;
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Degiro {
     pub username: String,
     pub password: String,
     pub client: Client,
+    pub requests_per_second: f64,
+    /// Directory the orders log is written to.
+    pub data_dir: PathBuf,
+    limiter: Arc<RateLimiter>,
+    fetch_status: Arc<Mutex<FetchStatus>>,
+    /// Last time the session was known to be alive, either authorized or pinged.
+    last_auth: Arc<Mutex<Option<Instant>>>,
 }
 
 impl Degiro {
     pub fn new(
         username: impl AsRef<str>,
         password: impl AsRef<str>,
-    ) -> Result<Self, reqwest::Error> {
+        requests_per_second: f64,
+        data_dir: impl AsRef<Path>,
+    ) -> anyhow::Result<Self> {
+        if !(requests_per_second.is_finite() && requests_per_second > 0.0) {
+            anyhow::bail!("requests_per_second must be positive, got {requests_per_second}");
+        }
         let client = ClientBuilder::default()
             .username(username.as_ref())
             .password(password.as_ref())
@@ -35,8 +163,59 @@ impl Degiro {
             username: username.as_ref().to_owned(),
             password: password.as_ref().to_owned(),
             client,
+            requests_per_second,
+            data_dir: data_dir.as_ref().to_path_buf(),
+            limiter: Arc::new(RateLimiter::new(requests_per_second)),
+            fetch_status: Arc::default(),
+            last_auth: Arc::default(),
         })
     }
+
+    async fn session_valid(&self) -> bool {
+        self.last_auth
+            .lock()
+            .await
+            .is_some_and(|at| at.elapsed() < SESSION_TTL)
+    }
+
+    async fn touch_session(&self) {
+        *self.last_auth.lock().await = Some(Instant::now());
+    }
+
+    /// Appends `entry` to the orders log. Failing to do so is only logged, the order itself
+    /// already went through or failed.
+    async fn log_order(&self, entry: &OrderLogEntry) {
+        let path = self.data_dir.join(ORDERS_LOG);
+        let write = async {
+            let mut line = serde_json::to_vec(entry)?;
+            line.push(b'\n');
+            tokio::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .await?
+                .write_all(&line)
+                .await
+        };
+        if let Err(e) = write.await {
+            warn!(error = %e, path = %path.display(), "Failed to write orders log");
+        }
+    }
+}
+
+/// File in the data dir every order action is appended to, one JSON object per line.
+pub const ORDERS_LOG: &str = "orders.log";
+
+/// Line of the orders log.
+#[derive(Debug, Serialize)]
+struct OrderLogEntry {
+    timestamp: DateTime<Utc>,
+    action: &'static str,
+    product_id: String,
+    request: serde_json::Value,
+    /// DEGIRO's answer, including the id of a created order.
+    response: Option<String>,
+    error: Option<String>,
 }
 
 #[async_trait]
@@ -44,7 +223,13 @@ impl Lifecycle for Degiro {
     type Supervision = OneToOne;
 
     async fn reset(&self, _puppeter: &Puppeter) -> Result<Self, CriticalError> {
-        Self::new(&self.username, &self.password).map_err(|e| {
+        Self::new(
+            &self.username,
+            &self.password,
+            self.requests_per_second,
+            &self.data_dir,
+        )
+        .map_err(|e| {
             error!("Failed to reset handler: {}", e);
             CriticalError::new(Pid::new::<Self>(), e.to_string())
         })
@@ -66,25 +251,162 @@ impl Handler<Authorize> for Degiro {
         puppeter: &Puppeter,
     ) -> Result<Self::Response, PuppetError> {
         info!("Authorizing...");
+        self.limiter.acquire().await;
         self.client.authorize().await.map_err(|e| {
             error!("Failed to authorize: {}", e);
             PuppetError::Critical(CriticalError::new(puppeter.pid, e.to_string()))
         })?;
 
+        self.touch_session().await;
         info!("Successfully authorized.");
         Ok(())
     }
 }
 
+/// State of the DEGIRO session.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct SessionStatus {
+    pub valid: bool,
+    /// Time since the session was last known to be alive, `None` if it never was.
+    pub age: Option<Duration>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct GetSessionStatus;
+
+#[async_trait]
+impl Handler<GetSessionStatus> for Degiro {
+    type Response = SessionStatus;
+
+    type Executor = ConcurrentExecutor;
+
+    async fn handle_message(
+        &mut self,
+        _msg: GetSessionStatus,
+        _puppeter: &Puppeter,
+    ) -> Result<Self::Response, PuppetError> {
+        let age = self.last_auth.lock().await.map(|at| at.elapsed());
+        Ok(SessionStatus {
+            valid: age.is_some_and(|age| age < SESSION_TTL),
+            age,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct SessionValid;
+
+#[async_trait]
+impl Handler<SessionValid> for Degiro {
+    type Response = bool;
+
+    type Executor = ConcurrentExecutor;
+
+    async fn handle_message(
+        &mut self,
+        _msg: SessionValid,
+        _puppeter: &Puppeter,
+    ) -> Result<Self::Response, PuppetError> {
+        Ok(self.session_valid().await)
+    }
+}
+
+/// Keeps the session alive, re-authorizes when it has lapsed and pings otherwise.
+#[derive(Clone, Copy, Debug)]
+pub struct Heartbeat;
+
+#[async_trait]
+impl Handler<Heartbeat> for Degiro {
+    type Response = ();
+
+    type Executor = ConcurrentExecutor;
+
+    async fn handle_message(
+        &mut self,
+        _msg: Heartbeat,
+        puppeter: &Puppeter,
+    ) -> Result<Self::Response, PuppetError> {
+        if !self.session_valid().await {
+            info!("Session expired, re-authorizing...");
+            return puppeter.ask::<Self, _>(Authorize).await.map_err(|e| {
+                error!(error = %e, "Failed to authorize");
+                PuppetError::critical(puppeter.pid, e)
+            });
+        }
+        self.limiter.acquire().await;
+        match self.client.account_config().await {
+            Ok(_) => {
+                self.touch_session().await;
+                Ok(())
+            }
+            Err(ClientError::Unauthorized) => {
+                warn!("Session rejected by heartbeat, re-authorizing...");
+                puppeter.ask::<Self, _>(Authorize).await.map_err(|e| {
+                    error!(error = %e, "Failed to authorize");
+                    PuppetError::critical(puppeter.pid, e)
+                })
+            }
+            Err(e) => {
+                warn!(error = %e, "Heartbeat failed");
+                Err(PuppetError::non_critical(puppeter.pid, e))
+            }
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct FetchData {
     pub id: Option<String>,
     pub name: Option<String>,
+    /// Candle intervals to fetch, `None` for the configured ones.
+    pub intervals: Option<Vec<Period>>,
+    /// Details already fetched and stored by `FetchProductsBatch`, fetched again when `None`.
+    pub product: Option<ProductDetails>,
+}
+
+/// Progress of the last fetch of all assets.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FetchStatus {
+    pub completed: usize,
+    pub total: usize,
+    /// Asset most recently started.
+    pub current_id: Option<String>,
+    /// End of the last fetch of all assets in which every asset was fetched.
+    pub last_success: Option<DateTime<Utc>>,
+}
+
+impl FetchStatus {
+    #[must_use]
+    pub const fn is_running(&self) -> bool {
+        self.completed < self.total
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct GetFetchStatus;
+
+#[async_trait]
+impl Handler<GetFetchStatus> for Degiro {
+    type Response = FetchStatus;
+
+    type Executor = ConcurrentExecutor;
+
+    async fn handle_message(
+        &mut self,
+        _msg: GetFetchStatus,
+        _puppeter: &Puppeter,
+    ) -> Result<Self::Response, PuppetError> {
+        Ok(self.fetch_status.lock().await.clone())
+    }
 }
 
+/// Assets fetched at once when fetching data for all assets.
+pub const FETCH_CONCURRENCY: usize = 8;
+
+/// Responds whether everything requested was fetched.
 #[async_trait]
 impl Handler<FetchData> for Degiro {
-    type Response = ();
+    type Response = bool;
 
     type Executor = ConcurrentExecutor;
 
@@ -97,59 +419,123 @@ impl Handler<FetchData> for Degiro {
             let mut asset_name = msg.name.clone().unwrap_or_else(|| "Unknown".to_owned());
             info!(id = %id, %asset_name, "Fetching data for asset");
             let mut isin = String::new();
+            let mut fetched = true;
+            let settings = puppeter
+                .ask::<Settings, _>(GetSettings)
+                .await
+                .map_err(|e| {
+                    error!(error = %e, "Failed to get settings");
+                    PuppetError::critical(puppeter.pid, e)
+                })?;
 
-            match self.client.product(id).await {
-                Ok(product) => {
-                    isin = product.inner.isin.clone();
-                    asset_name = product.inner.symbol.clone();
-                    puppeter
-                        .send::<Db, _>(product.inner.as_ref().clone())
-                        .await
-                        .map_err(|e| {
-                            error!(error = %e, id = %id, asset_name = %asset_name, "Failed to send 'put product'");
+            let product = match msg.product.clone() {
+                Some(product) => Ok(product),
+                None => {
+                    self.limiter.acquire().await;
+                    let mut product = self.client.product(id).await;
+                    // The session is renewed once, a second rejection fails the fetch instead
+                    // of resending it for as long as the session can't be renewed.
+                    if matches!(product, Err(ClientError::Unauthorized)) {
+                        warn!(id = %id, asset_name = %asset_name, "Handler unauthorized, attempting authorization...");
+                        puppeter.ask::<Self, _>(Authorize).await.map_err(|e| {
+                            error!(error = %e, "Failed to authorize");
                             PuppetError::critical(puppeter.pid, e)
                         })?;
+                        self.limiter.acquire().await;
+                        product = self.client.product(id).await;
+                    }
+                    match product {
+                        Ok(product) => {
+                            let product = product.inner.as_ref().clone();
+                            puppeter.send::<Db, _>(product.clone()).await.map_err(|e| {
+                                error!(error = %e, id = %id, asset_name = %product.symbol, "Failed to send 'put product'");
+                                PuppetError::critical(puppeter.pid, e)
+                            })?;
+                            Ok(product)
+                        }
+                        Err(e) => Err(e),
+                    }
+                }
+            };
+            match product {
+                Ok(product) => {
+                    isin = product.isin.clone();
+                    asset_name = product.symbol.clone();
+                    if !product.tradable || !product.active {
+                        warn!(
+                            id = %id,
+                            asset_name = %asset_name,
+                            tradable = product.tradable,
+                            active = product.active,
+                            feed_quality = ?product.feed_quality,
+                            "Asset can't be traded"
+                        );
+                        if settings.skip_untradable.unwrap_or(false) {
+                            info!(id = %id, asset_name = %asset_name, "Skipping the rest of the data");
+                            return Ok(true);
+                        }
+                    }
                 }
                 Err(e @ ClientError::Unauthorized) => {
-                    warn!(id = %id, asset_name = %asset_name, "Handler unauthorized, attempting authorization...");
-                    puppeter.ask::<Self, _>(Authorize).await.map_err(|e| {
-                        error!(error = %e, "Failed to authorize");
-                        PuppetError::critical(puppeter.pid, e)
-                    })?;
-                    puppeter.send::<Self, _>(msg.clone()).await.map_err(|e| {
-                        error!(error = %e, id = %id, asset_name = %asset_name, "Failed to resend message");
-                        PuppetError::critical(puppeter.pid, e)
-                    })?;
-
+                    error!(error = %e, id = %id, asset_name = %asset_name, "Still unauthorized after authorization");
                     return Err(PuppetError::non_critical(puppeter.pid, e));
                 }
                 Err(e) => {
-                    error!(error = %e, id = %id, asset_name = %asset_name, "Failed to fetch product data")
+                    error!(error = %e, id = %id, asset_name = %asset_name, "Failed to fetch product data");
+                    fetched = false;
                 }
             };
 
-            match self.client.quotes(id, Period::P50Y, Period::P1M).await {
-                Ok(quotes) => {
-                    info!(id = %id, asset_name = %asset_name, "Fetched {} candles", quotes.time.len());
-                    puppeter.send::<Db, _>(quotes.clone()).await.map_err(|e| {
-                        error!(error = %e, id = %id, asset_name = %asset_name, "Failed to send 'put candles'");
-                        PuppetError::critical(puppeter.pid, e)
-                    })?;
+            let intervals = msg
+                .intervals
+                .clone()
+                .unwrap_or_else(|| settings.candle_intervals());
+            for interval in intervals {
+                self.limiter.acquire().await;
+                let mut quotes = self.client.quotes(id, Period::P50Y, interval).await;
+                // The charting endpoint now and then answers with an empty or partial body, so
+                // a failed or empty fetch gets a second chance before the asset is given up on.
+                if !matches!(&quotes, Ok(quotes) if !quotes.time.is_empty()) {
+                    warn!(id = %id, asset_name = %asset_name, ?interval, "No quotes fetched, retrying once");
+                    self.limiter.acquire().await;
+                    quotes = self.client.quotes(id, Period::P50Y, interval).await;
                 }
-                Err(e) => {
-                    error!(error = %e, id = %id, asset_name = %asset_name, "Failed to fetch quotes");
-                    warn!(id = %id, asset_name = %asset_name, "Removing asset from settings and database");
-                    puppeter.ask::<Settings, _>(DeleteAsset(id.clone())).await.map_err(|e| {
-                        error!(error = %e, id = %id, asset_name = %asset_name, "Failed to remove asset from settings");
-                        PuppetError::critical(puppeter.pid, e)
-                    })?;
-                    puppeter.ask::<Db, _>(DeleteData(id.clone())).await.map_err(|e| {
-                        error!(error = %e, id = %id, asset_name = %asset_name, "Failed to delete asset from database");
-                        PuppetError::critical(puppeter.pid, e)
-                    })?;
+                match quotes {
+                    // A product without any data isn't a failed request, so it's kept.
+                    Ok(quotes) if quotes.time.is_empty() => {
+                        warn!(id = %id, asset_name = %asset_name, ?interval, "No quotes for this product");
+                        fetched = false;
+                    }
+                    Ok(quotes) => {
+                        info!(id = %id, asset_name = %asset_name, ?interval, "Fetched {} candles", quotes.time.len());
+                        let msg = StoreQuotes { quotes, interval };
+                        puppeter.send::<Db, _>(msg).await.map_err(|e| {
+                            error!(error = %e, id = %id, asset_name = %asset_name, "Failed to send 'put candles'");
+                            PuppetError::critical(puppeter.pid, e)
+                        })?;
+                    }
+                    // Only the default interval is required, the others are best effort.
+                    Err(e) if interval != DEFAULT_INTERVAL => {
+                        error!(error = %e, id = %id, asset_name = %asset_name, ?interval, "Failed to fetch quotes");
+                        fetched = false;
+                    }
+                    Err(e) => {
+                        error!(error = %e, id = %id, asset_name = %asset_name, "Failed to fetch quotes");
+                        fetched = false;
+                        warn!(id = %id, asset_name = %asset_name, "Removing asset from settings and database");
+                        let msg = RemoveAsset {
+                            id: id.clone(),
+                            purge_data: true,
+                        };
+                        puppeter.ask::<Db, _>(msg).await.map_err(|e| {
+                            error!(error = %e, id = %id, asset_name = %asset_name, "Failed to remove asset");
+                            PuppetError::critical(puppeter.pid, e)
+                        })?;
+                    }
                 }
             }
 
+            self.limiter.acquire().await;
             match self.client.financial_statements(id, &isin).await {
                 Ok(financial_reports) => {
                     puppeter
@@ -162,18 +548,20 @@ impl Handler<FetchData> for Degiro {
                 }
                 Err(e) => {
                     error!(error = %e, id = %id, asset_name = %asset_name, "Failed to fetch financial reports");
+                    fetched = false;
                     warn!(id = %id, asset_name = %asset_name, "Removing asset from settings and database");
-                    puppeter.ask::<Settings, _>(DeleteAsset(id.clone())).await.map_err(|e| {
-                        error!(error = %e, id = %id, asset_name = %asset_name, "Failed to remove asset from settings");
-                        PuppetError::critical(puppeter.pid, e)
-                    })?;
-                    puppeter.ask::<Db, _>(DeleteData(id.clone())).await.map_err(|e| {
-                        error!(error = %e, id = %id, asset_name = %asset_name, "Failed to delete asset from database");
+                    let msg = RemoveAsset {
+                        id: id.clone(),
+                        purge_data: true,
+                    };
+                    puppeter.ask::<Db, _>(msg).await.map_err(|e| {
+                        error!(error = %e, id = %id, asset_name = %asset_name, "Failed to remove asset");
                         PuppetError::critical(puppeter.pid, e)
                     })?;
                 }
             }
 
+            self.limiter.acquire().await;
             match self.client.company_ratios(id, &isin).await {
                 Ok(company_ratios) => {
                     puppeter.send::<Db, _>(company_ratios).await.map_err(|e| {
@@ -183,17 +571,19 @@ impl Handler<FetchData> for Degiro {
                 }
                 Err(e) => {
                     error!(error = %e, id = %id, asset_name = %asset_name, "Failed to fetch company ratios");
+                    fetched = false;
                     warn!(id = %id, asset_name = %asset_name, "Removing asset from settings and database");
-                    puppeter.ask::<Settings, _>(DeleteAsset(id.clone())).await.map_err(|e| {
-                        error!(error = %e, id = %id, asset_name = %asset_name, "Failed to remove asset from settings");
-                        PuppetError::critical(puppeter.pid, e)
-                    })?;
-                    puppeter.ask::<Db, _>(DeleteData(id.clone())).await.map_err(|e| {
-                        error!(error = %e, id = %id, asset_name = %asset_name, "Failed to delete asset from database");
+                    let msg = RemoveAsset {
+                        id: id.clone(),
+                        purge_data: true,
+                    };
+                    puppeter.ask::<Db, _>(msg).await.map_err(|e| {
+                        error!(error = %e, id = %id, asset_name = %asset_name, "Failed to remove asset");
                         PuppetError::critical(puppeter.pid, e)
                     })?;
                 }
             }
+            Ok(fetched)
         } else {
             info!("Fetching data for all assets");
             puppeter.ask::<Self, _>(Authorize).await.map_err(|e| {
@@ -207,19 +597,164 @@ impl Handler<FetchData> for Degiro {
                     error!(error = %e, "Failed to get settings");
                     PuppetError::critical(puppeter.pid, e)
                 })?;
-            for (id, name) in settings.assets.iter() {
-                let msg = FetchData {
-                    id: Some(id.to_string()),
-                    name: Some(name.clone()),
+            let total = settings.assets.len();
+            {
+                let mut status = self.fetch_status.lock().await;
+                *status = FetchStatus {
+                    completed: 0,
+                    total,
+                    current_id: None,
+                    last_success: status.last_success,
                 };
-                puppeter.send::<Self, _>(msg).await.map_err(|e| {
-                    error!(error = %e, id = %id, "Failed to resend message");
+            }
+            // One request for the details of every asset instead of one per asset, the
+            // per asset fetches fall back to fetching the details of anything missing.
+            let ids = settings.assets.keys().cloned().collect::<Vec<_>>();
+            let products = puppeter
+                .ask::<Self, _>(FetchProductsBatch { ids })
+                .await
+                .unwrap_or_else(|e| {
+                    warn!(error = %e, "Failed to fetch products in a batch");
+                    HashMap::new()
+                });
+            let products = &products;
+            let status = &self.fetch_status;
+            let intervals = msg.intervals.unwrap_or_else(|| settings.candle_intervals());
+            let intervals = &intervals;
+            let succeeded = stream::iter(settings.assets.iter())
+                .map(|(id, name)| async move {
+                    status.lock().await.current_id = Some(id.clone());
+                    let msg = FetchData {
+                        id: Some(id.to_string()),
+                        name: Some(name.clone()),
+                        intervals: Some(intervals.clone()),
+                        product: products.get(id).cloned(),
+                    };
+                    let fetched = puppeter.ask::<Self, _>(msg).await.unwrap_or_else(|e| {
+                        error!(error = %e, id = %id, "Failed to fetch data");
+                        false
+                    });
+                    status.lock().await.completed += 1;
+                    fetched
+                })
+                .buffer_unordered(FETCH_CONCURRENCY)
+                .filter(|fetched| future::ready(*fetched))
+                .count()
+                .await;
+            info!(
+                succeeded,
+                failed = total - succeeded,
+                "Finished fetching data for all assets"
+            );
+            if succeeded == total {
+                self.fetch_status.lock().await.last_success = Some(Utc::now());
+            }
+            Ok(succeeded == total)
+        }
+    }
+}
+
+/// Fetches the details of many products in a single request and stores them. Responds with
+/// the details by product id, ids DEGIRO didn't return are left out.
+#[derive(Clone, Debug)]
+pub struct FetchProductsBatch {
+    pub ids: Vec<String>,
+}
+
+#[async_trait]
+impl Handler<FetchProductsBatch> for Degiro {
+    type Response = HashMap<String, ProductDetails>;
+
+    type Executor = ConcurrentExecutor;
+
+    async fn handle_message(
+        &mut self,
+        msg: FetchProductsBatch,
+        puppeter: &Puppeter,
+    ) -> Result<Self::Response, PuppetError> {
+        info!(count = msg.ids.len(), "Fetching products in a batch");
+        self.limiter.acquire().await;
+        match self.client.fetch_products(&msg.ids).await {
+            Ok(products) => {
+                let mut fetched = HashMap::new();
+                for product in products {
+                    let product = product.inner.as_ref().clone();
+                    puppeter.send::<Db, _>(product.clone()).await.map_err(|e| {
+                        error!(error = %e, id = %product.id, "Failed to send 'put product'");
+                        PuppetError::critical(puppeter.pid, e)
+                    })?;
+                    fetched.insert(product.id.clone(), product);
+                }
+                info!(
+                    fetched = fetched.len(),
+                    requested = msg.ids.len(),
+                    "Fetched products in a batch"
+                );
+                Ok(fetched)
+            }
+            Err(ClientError::Unauthorized) => {
+                warn!("Handler unauthorized, attempting authorization...");
+                puppeter.ask::<Self, _>(Authorize).await.map_err(|e| {
+                    error!(error = %e, "Failed to authorize");
                     PuppetError::critical(puppeter.pid, e)
                 })?;
+                puppeter.ask::<Self, _>(msg).await.map_err(|e| {
+                    error!(error = %e, "Failed to resend message");
+                    PuppetError::critical(puppeter.pid, e)
+                })
+            }
+            Err(e) => {
+                error!(error = %e, "Failed to fetch products");
+                Err(PuppetError::non_critical(puppeter.pid, e))
             }
-            info!("Finished fetching data for all assets");
         }
-        Ok(())
+    }
+}
+
+/// Candles of a product, read from the database and fetched with `FetchData` only when
+/// nothing is stored for the interval yet.
+#[derive(Clone, Debug)]
+pub struct CachedCandles {
+    pub id: String,
+    pub interval: Period,
+}
+
+impl CachedCandles {
+    #[must_use]
+    pub fn new(id: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            interval: DEFAULT_INTERVAL,
+        }
+    }
+}
+
+#[async_trait]
+impl Handler<CachedCandles> for Degiro {
+    type Response = Option<Candles>;
+
+    type Executor = ConcurrentExecutor;
+
+    async fn handle_message(
+        &mut self,
+        msg: CachedCandles,
+        puppeter: &Puppeter,
+    ) -> Result<Self::Response, PuppetError> {
+        let query = CandlesQuery::new(ProductQuery::Id(msg.id.clone()), msg.interval);
+        if let Some(candles) = puppeter.ask::<Db, _>(query.clone()).await? {
+            return Ok(Some(candles));
+        }
+        info!(id = %msg.id, interval = ?msg.interval, "No candles stored, fetching");
+        let fetch = FetchData {
+            id: Some(msg.id.clone()),
+            name: None,
+            intervals: Some(vec![msg.interval]),
+            product: None,
+        };
+        if !puppeter.ask::<Self, _>(fetch).await? {
+            warn!(id = %msg.id, "Fetch incomplete, candles may be missing");
+        }
+        puppeter.ask::<Db, _>(query).await
     }
 }
 
@@ -238,8 +773,15 @@ impl Handler<GetPortfolio> for Degiro {
         puppeter: &Puppeter,
     ) -> Result<Self::Response, PuppetError> {
         info!("Fetching portfolio...");
+        self.limiter.acquire().await;
         match self.client.portfolio().await {
-            Ok(portfolio) => Ok(portfolio),
+            Ok(portfolio) => {
+                let snapshot = PortfolioSnapshot::new(Utc::now().date_naive(), &portfolio);
+                if let Err(e) = puppeter.send::<Db, _>(SaveSnapshot(snapshot)).await {
+                    warn!(error = %e, "Failed to send 'save snapshot'");
+                }
+                Ok(portfolio)
+            }
             Err(ClientError::Unauthorized) => {
                 warn!("Handler unauthorized, attempting authorization...");
                 puppeter.ask::<Self, _>(Authorize).await.map_err(|e| {
@@ -259,10 +801,14 @@ impl Handler<GetPortfolio> for Degiro {
     }
 }
 
+/// Transactions between the dates, newest first, optionally only those of one product or
+/// side.
 #[derive(Clone, Debug)]
 pub struct GetTransactions {
     pub from_date: NaiveDate,
     pub to_date: NaiveDate,
+    pub product_id: Option<String>,
+    pub side: Option<TransactionSide>,
 }
 
 #[async_trait]
@@ -277,8 +823,21 @@ impl Handler<GetTransactions> for Degiro {
         puppeter: &Puppeter,
     ) -> Result<Self::Response, PuppetError> {
         info!("Fetching transactions...");
+        self.limiter.acquire().await;
         match self.client.transactions(msg.from_date, msg.to_date).await {
-            Ok(transactions) => Ok(transactions),
+            Ok(mut transactions) => {
+                let side = msg.side.map(TransactionType::from);
+                transactions.0.retain(|transaction| {
+                    msg.product_id
+                        .as_ref()
+                        .map_or(true, |id| transaction.inner.product_id.to_string() == *id)
+                        && side.map_or(true, |side| transaction.inner.transaction_type == side)
+                });
+                transactions
+                    .0
+                    .sort_by(|a, b| b.inner.date.cmp(&a.inner.date));
+                Ok(transactions)
+            }
             Err(ClientError::Unauthorized) => {
                 warn!("Handler unauthorized, attempting authorization...");
                 puppeter.ask::<Self, _>(Authorize).await.map_err(|e| {
@@ -313,6 +872,7 @@ impl Handler<GetOrders> for Degiro {
         puppeter: &Puppeter,
     ) -> Result<Self::Response, PuppetError> {
         info!("Fetching GetOrders...");
+        self.limiter.acquire().await;
         match self.client.orders().await {
             Ok(orders) => Ok(orders),
             Err(ClientError::Unauthorized) => {
@@ -333,3 +893,75 @@ impl Handler<GetOrders> for Degiro {
         }
     }
 }
+
+/// Submits a new order.
+#[derive(Clone, Debug)]
+pub struct PlaceOrder(pub CreateOrderRequest);
+
+#[async_trait]
+impl Handler<PlaceOrder> for Degiro {
+    type Response = ();
+
+    type Executor = ConcurrentExecutor;
+
+    async fn handle_message(
+        &mut self,
+        msg: PlaceOrder,
+        puppeter: &Puppeter,
+    ) -> Result<Self::Response, PuppetError> {
+        info!(product_id = %msg.0.product_id, "Placing order...");
+        self.limiter.acquire().await;
+        let result = self.client.create_order(msg.0.clone()).await;
+        if !matches!(result, Err(ClientError::Unauthorized)) {
+            let entry = OrderLogEntry {
+                timestamp: Utc::now(),
+                action: "create",
+                product_id: msg.0.product_id.to_string(),
+                request: serde_json::to_value(&msg.0).unwrap_or_default(),
+                response: result.as_ref().ok().map(|res| format!("{res:?}")),
+                error: result.as_ref().err().map(ToString::to_string),
+            };
+            self.log_order(&entry).await;
+        }
+        match result {
+            Ok(_) => Ok(()),
+            Err(ClientError::Unauthorized) => {
+                warn!("Handler unauthorized, attempting authorization...");
+                puppeter.ask::<Self, _>(Authorize).await.map_err(|e| {
+                    error!(error = %e, "Failed to authorize");
+                    PuppetError::critical(puppeter.pid, e)
+                })?;
+                puppeter.ask::<Self, _>(msg).await.map_err(|e| {
+                    error!(error = %e, "Failed to resend message");
+                    PuppetError::critical(puppeter.pid, e)
+                })
+            }
+            Err(e) => {
+                error!(error = %e, "Failed to place order: {}", e);
+                Err(PuppetError::non_critical(puppeter.pid, e))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn rate_limiter_spaces_requests() {
+        let limiter = RateLimiter::new(50.0);
+        let start = Instant::now();
+        for _ in 0..5 {
+            limiter.acquire().await;
+        }
+        assert!(start.elapsed() >= Duration::from_millis(80));
+    }
+
+    #[test]
+    fn rejects_invalid_request_rate() {
+        for rps in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert!(Degiro::new("user", "pass", rps, "data").is_err());
+        }
+    }
+}
@@ -1,30 +1,62 @@
-use std::{collections::HashSet, fmt, sync::Arc};
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    fmt,
+    sync::Arc,
+};
 
 use async_trait::async_trait;
-use chrono::Datelike;
-use comfy_table::{presets::UTF8_BORDERS_ONLY, Cell, Table};
+use chrono::{Datelike, NaiveDate, Utc};
+use comfy_table::{Cell, Table};
 use dashmap::DashMap;
 use degiro_rs::{
-    api::product::{Product, ProductDetails},
-    util::{Period, ProductCategory, TransactionType},
+    api::{
+        orders::{CreateOrderRequestBuilder, OrderTimeType, OrderType},
+        product::{Product, ProductDetails},
+    },
+    util::{Currency, Period, ProductCategory, TransactionType},
 };
 use erfurt::candle::{Candles, CandlesExt};
+use futures::{stream, StreamExt};
 use itertools::Itertools;
 use master_of_puppets::prelude::*;
 use qualsdorf::{
     average_drawdown::AverageDrawdownExt, rolling_economic_drawdown::RollingEconomicDrawdownExt,
-    rsi::RsiExt, sharpe_ratio::SharpeRatioExt, Indicator,
+    rsi::RsiExt, sharpe_ratio::SharpeRatioExt, sortino_ratio::SortinoRatioExt, Indicator,
 };
+use serde::{Deserialize, Serialize};
+use strum::EnumString;
+use thiserror::Error;
 use tracing::{error, info, warn};
 
 use crate::{
-    portfolio::{AssetsSeq, RiskMode, SingleAllocation},
-    puppet::degiro::{Degiro, GetOrders, GetPortfolio},
+    indicators::{
+        alpha::AlphaExt,
+        annualized_return::{AnnualizedReturnExt, AnnualizedReturnMode},
+        annualized_risk::AnnualizedRiskExt,
+        atr::AtrExt,
+        average_drawdown_n::AverageDrawdownNExt,
+        beta::BetaExt,
+        bollinger::BollingerExt,
+        cagr::CagrExt,
+        information_ratio::InformationRatioExt,
+        rsi_cross::{CrossSignal, RsiCrossExt},
+        upside_potential_ratio::UpsidePotentialRatioExt,
+    },
+    period::PeriodExt,
+    portfolio::{candles_until, AssetsSeq, ReturnKind, RiskMode, SingleAllocation},
+    puppet::degiro::{
+        CachedCandles, Degiro, GetOrders, GetPortfolio, OrdersExt, PlaceOrder, ProductType,
+        ProductTypeExt,
+    },
+    table::{Precision, TableStyle},
 };
 
 use super::{
-    db::{CandlesQuery, CompanyRatiosQuery, Db, FinanclaReportsQuery, ProductQuery},
-    settings::Settings,
+    db::{
+        CandlesQuery, CompanyRatiosQuery, Db, FinanclaReportsQuery, ProductQuery, DEFAULT_INTERVAL,
+    },
+    settings::{AssetOverrides, Settings},
 };
 
 #[derive(Debug, Clone)]
@@ -37,6 +69,21 @@ impl Calculator {
     pub const fn new(settings: Settings) -> Self {
         Self { settings }
     }
+
+    /// Minimum acceptable return for the Sortino ratio, falling back to the configured one and
+    /// then to `0.0`.
+    fn mar(&self, mar: Option<f64>) -> f64 {
+        mar.or(self.settings.mar).unwrap_or(0.0)
+    }
+
+    /// Mean risk free rate per `interval` candle over the returns of the last `freq` candles,
+    /// the configured rates or the `annual` one.
+    fn risk_free(&self, candles: &Candles, freq: usize, interval: Period, annual: f64) -> f64 {
+        let times = &candles.time[candles.time.len().saturating_sub(freq)..];
+        self.settings
+            .risk_free(times, annual, Period::P1Y.periods(interval))
+            .mean(0..times.len().saturating_sub(1))
+    }
 }
 
 #[async_trait]
@@ -48,12 +95,18 @@ impl Lifecycle for Calculator {
     }
 }
 
+/// Single allocation of a product, `None` when there are no candles or no allocation can be
+/// calculated from them.
 #[derive(Debug, Clone)]
 pub struct GetSingleAllocation {
+    /// Candles the allocation is computed from, in the interval they're queried in.
     pub query: CandlesQuery,
     pub mode: RiskMode,
+    pub ret_kind: ReturnKind,
     pub risk: f64,
     pub risk_free: f64,
+    /// Lookback the ratios are computed over.
+    pub period: Period,
 }
 
 #[async_trait]
@@ -67,38 +120,228 @@ impl Handler<GetSingleAllocation> for Calculator {
         msg: GetSingleAllocation,
         puppeter: &Puppeter,
     ) -> Result<Self::Response, PuppetError> {
-        if let Some(candles) = puppeter.ask::<Db, _>(msg.query.clone()).await? {
-            let allocation = candles
-                .single_allocation(msg.mode, msg.risk, msg.risk_free, Period::P1Y, Period::P1M)
-                .await
-                .map_err(|e| {
-                    error!(error = %e, "Failed to calculate single allocation");
-                    CriticalError::new(puppeter.pid, e.to_string())
-                })?;
-            Ok(Some(allocation))
-        } else {
-            Ok(None)
+        let Some(candles) = puppeter.ask::<Db, _>(msg.query.clone()).await? else {
+            return Ok(None);
+        };
+        let allocation = candles
+            .single_allocation(
+                msg.mode,
+                msg.ret_kind,
+                msg.risk,
+                msg.risk_free,
+                msg.period,
+                msg.query.interval,
+            )
+            .await;
+        match allocation {
+            Ok(allocation) => Ok(Some(allocation)),
+            Err(e) => {
+                warn!(error = %e, "Failed to calculate single allocation");
+                Ok(None)
+            }
         }
     }
 }
 
 #[derive(Debug, Clone, Copy)]
+pub struct GetCorrelations {
+    pub table_style: TableStyle,
+    pub precision: Precision,
+}
+
+#[async_trait]
+impl Handler<GetCorrelations> for Calculator {
+    type Response = String;
+
+    type Executor = ConcurrentExecutor;
+
+    async fn handle_message(
+        &mut self,
+        msg: GetCorrelations,
+        puppeter: &Puppeter,
+    ) -> Result<Self::Response, PuppetError> {
+        let mut assets = Vec::new();
+        for (id, _) in self.settings.assets.iter() {
+            let product = puppeter.ask::<Db, _>(ProductQuery::Id(id.clone())).await?;
+            let candles = puppeter
+                .ask::<Db, _>(CandlesQuery::from(ProductQuery::Id(id.clone())))
+                .await?;
+            if let (Some(product), Some(candles)) = (product, candles) {
+                assets.push((product, candles));
+            }
+        }
+        let symbols = assets
+            .iter()
+            .map(|(product, _)| (product.id.clone(), product.symbol.clone()))
+            .collect::<HashMap<_, _>>();
+        let (ids, corr) = AssetsSeq::from(assets)
+            .correlation_matrix()
+            .map_err(|e| PuppetError::non_critical(puppeter.pid, e))?;
+        let mut table = Table::new();
+        let mut header = vec![Cell::new("")];
+        header.extend(ids.iter().map(|id| Cell::new(&symbols[id])));
+        table.set_header(header);
+        for (i, id) in ids.iter().enumerate() {
+            let mut row = vec![Cell::new(&symbols[id])];
+            row.extend((0..ids.len()).map(|j| {
+                Cell::new(msg.precision.ratio(corr[(i, j)]))
+                    .set_alignment(comfy_table::CellAlignment::Right)
+            }));
+            table.add_row(row);
+        }
+        Ok(msg.table_style.render(&mut table))
+    }
+}
+
+/// Ordering of DEGIRO product categories, `A` being the least risky.
+pub trait ProductCategoryExt {
+    fn rank(&self) -> u8;
+}
+
+impl ProductCategoryExt for ProductCategory {
+    fn rank(&self) -> u8 {
+        match self {
+            Self::A => 0,
+            Self::B => 1,
+            Self::C => 2,
+            Self::D => 3,
+            Self::E => 4,
+            Self::F => 5,
+            Self::G => 6,
+        }
+    }
+}
+
+/// Drawdown measure compared against `min_dd`/`max_dd` when screening assets.
+#[derive(Debug, Clone, Copy, Default, EnumString, Serialize, Deserialize)]
+#[strum(serialize_all = "snake_case")]
+pub enum DrawdownMetric {
+    /// Average drawdown over `freq` candles.
+    #[default]
+    AvgDd,
+    /// Rolling economic drawdown over `freq` candles.
+    Redp,
+}
+
+#[derive(Debug, Clone)]
 pub struct CalculatePortfolio {
     pub mode: RiskMode,
+    pub ret_kind: ReturnKind,
     pub risk: f64,
     pub risk_free: f64,
+    /// Lookback the allocations are computed over.
+    pub period: Period,
+    /// Interval of the candles everything is computed from.
+    pub interval: Period,
+    /// Lookback of the metrics, in `interval` candles.
     pub freq: usize,
     pub money: f64,
+    /// Currency of `money`, prices are converted into it. `None` compares prices as they are.
+    pub currency: Option<Currency>,
+    /// Date the analysis is done as of, today when `None`.
+    pub as_of: Option<NaiveDate>,
     pub max_stocks: usize,
     pub min_rsi: Option<f64>,
     pub max_rsi: Option<f64>,
     pub min_dd: Option<f64>,
     pub max_dd: Option<f64>,
+    pub dd_metric: DrawdownMetric,
     pub min_class: Option<ProductCategory>,
     pub max_class: Option<ProductCategory>,
     pub short_sales_constraint: bool,
     pub min_roic: Option<f64>,
     pub roic_wacc_delta: Option<f64>,
+    pub shrinkage: Option<f64>,
+    pub allowed_types: Option<HashSet<ProductType>>,
+    pub mar: Option<f64>,
+    pub min_percent_b: Option<f64>,
+    pub max_percent_b: Option<f64>,
+    /// Oversold RSI level the last cross has to be an upward one through, if any.
+    pub rsi_cross_level: Option<f64>,
+    /// Most candles since that cross, older ones are stale.
+    pub rsi_cross_within: usize,
+    /// Highest correlation allowed between two held assets.
+    pub max_correlation: Option<f64>,
+    pub return_mode: AnnualizedReturnMode,
+    /// Product id of the benchmark the beta, alpha and information ratio are measured against.
+    pub benchmark: Option<String>,
+    pub table_style: TableStyle,
+    pub precision: Precision,
+}
+
+/// Width of the Bollinger bands used for the `%B` screen, in standard deviations.
+const BOLLINGER_K: f64 = 2.0;
+
+/// Years before the last one searched for an annual report that has already been filed.
+const ANNUAL_REPORT_LOOKBACK: i32 = 3;
+
+/// Year of the last annual report that can be filed by `as_of`, today when `None`.
+fn last_report_year(as_of: Option<NaiveDate>) -> i32 {
+    as_of.unwrap_or_else(|| Utc::now().date_naive()).year() - 1
+}
+
+/// Most recent annual report from `year` or earlier, with the year it's for. Early in a year
+/// last year's report may not be filed yet, so older years are tried too. `year` when there
+/// is none.
+fn latest_annual<T>(year: i32, get: impl Fn(i32) -> Option<T>) -> Result<(i32, T), i32> {
+    (year - ANNUAL_REPORT_LOOKBACK..=year)
+        .rev()
+        .find_map(|year| get(year).map(|report| (year, report)))
+        .ok_or(year)
+}
+
+/// Smallest tradable quantity of a product unless it says otherwise.
+const DEFAULT_LOT_SIZE: f64 = 1.0;
+
+/// Largest quantity of whole `lot_size` lots at `price` that `cash` can pay for.
+fn lot_quantity(cash: f64, price: f64, lot_size: f64) -> f64 {
+    if price <= 0.0 || lot_size <= 0.0 {
+        return 0.0;
+    }
+    (cash / (price * lot_size)).floor() * lot_size
+}
+
+/// Indices of the most correlated pair of `held` assets whose correlation is above `max`.
+fn most_correlated_pair(
+    matrix: &nalgebra::DMatrix<f64>,
+    held: &[bool],
+    max: f64,
+) -> Option<(usize, usize)> {
+    (0..matrix.nrows())
+        .tuple_combinations()
+        .filter(|&(i, j)| held[i] && held[j] && matrix[(i, j)] > max)
+        .max_by(|&(a, b), &(c, d)| matrix[(a, b)].total_cmp(&matrix[(c, d)]))
+}
+
+/// Takes lots off `positions`, given as `(price, lot_size, qty)` from the lowest conviction
+/// up, until their total cost fits in `money`. Each position is sized on its own, so together
+/// they can deploy more cash than there is. Returns the cash left over.
+fn trim_to_budget(positions: &mut [(f64, f64, f64)], money: f64) -> f64 {
+    let cost = |positions: &[(f64, f64, f64)]| {
+        positions
+            .iter()
+            .map(|(price, _, qty)| price * qty)
+            .sum::<f64>()
+    };
+    let mut deployed = cost(positions);
+    while deployed > money {
+        let Some((price, lot_size, qty)) = positions.iter_mut().find(|(_, _, qty)| *qty > 0.0)
+        else {
+            break;
+        };
+        let trimmed = lot_size.min(*qty);
+        *qty -= trimmed;
+        deployed -= trimmed * *price;
+    }
+    money - cost(positions)
+}
+
+/// Orders `(sharpe, avg_dd, roic)` scores from the worst, the lowest Sharpe ratio first, then
+/// the deepest average drawdown, then the lowest ROIC.
+fn cmp_scores(a: (f64, f64, f64), b: (f64, f64, f64)) -> Ordering {
+    a.0.total_cmp(&b.0)
+        .then_with(|| b.1.total_cmp(&a.1))
+        .then_with(|| a.2.total_cmp(&b.2))
 }
 
 #[derive(Debug)]
@@ -108,24 +351,114 @@ pub struct DataEntry {
     single_allocation: f64,
     redp_allocation: f64,
     sharpe_ratio: f64,
+    /// `None` when the window has no downside below the MAR.
+    sortino_ratio: Option<f64>,
+    annualized_return: Option<f64>,
+    annualized_risk: Option<f64>,
+    /// Upside potential over downside deviation below the MAR, `None` without any downside.
+    upside_potential_ratio: Option<f64>,
+    /// Compound annual growth over the whole stored history, unlike the other metrics which
+    /// only look at the last `freq` candles.
+    cagr: Option<f64>,
     redp: f64,
     avg_dd: f64,
     rsi: f64,
+    /// Bollinger `%B` of the last close, `None` when the bands have no spread.
+    percent_b: Option<f64>,
+    rsi_cross: Option<CrossSignal>,
+    /// Smallest tradable quantity, a fraction for products traded in fractional shares.
+    lot_size: f64,
+    /// Quantity to trade, set once the allocations are final.
+    qty: f64,
+    /// Last close in the product's currency, the one of the last candle for an as-of analysis.
+    close: f64,
+    /// `close` in the currency of the budget.
+    price: f64,
     roic: f64,
     wacc: f64,
+    benchmark: Option<BenchmarkMetrics>,
+}
+
+/// Asset measured against a benchmark over the last `freq` returns both have.
+#[derive(Debug, Clone, Copy)]
+struct BenchmarkMetrics {
+    beta: Option<f64>,
+    /// Jensen's alpha per candle.
+    alpha: Option<f64>,
+    information_ratio: Option<f64>,
+}
+
+impl BenchmarkMetrics {
+    /// `risk_free` is the rate per candle alpha is measured against.
+    fn new(candles: &Candles, benchmark: &Candles, freq: usize, risk_free: f64) -> Self {
+        Self {
+            beta: candles
+                .beta(benchmark, freq)
+                .and_then(|beta| beta.last().copied()),
+            alpha: candles
+                .alpha(benchmark, freq, risk_free)
+                .and_then(|alpha| alpha.last().copied()),
+            information_ratio: candles
+                .information_ratio(benchmark, freq)
+                .and_then(|ir| ir.last().copied()),
+        }
+    }
+}
+
+/// Why `GetDataEntry` has no entry for an asset.
+#[derive(Debug, Clone, Error, Serialize, Deserialize)]
+pub enum Exclusion {
+    #[error("no candles, product, financials or ratios stored")]
+    MissingData,
+    #[error("only {candles} of the {required} candles required")]
+    InsufficientHistory { candles: usize, required: usize },
+    #[error("no beta")]
+    NoBeta,
+    #[error("no annual report for {0}")]
+    NoAnnualReport(i32),
+    #[error("no exchange rate from {from} to {to}")]
+    NoExchangeRate { from: String, to: String },
+    #[error("no {0}, the candles are degenerate")]
+    Metric(String),
+}
+
+/// `Exclusion::Metric` for a metric of asset `id` that can't be calculated.
+fn missing_metric(id: &str, metric: &str) -> Exclusion {
+    warn!(id, metric, "Metric can't be calculated");
+    Exclusion::Metric(metric.to_owned())
+}
+
+/// Asset left out before the portfolio was calculated.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExcludedAsset {
+    pub id: String,
+    pub name: String,
+    pub reason: Exclusion,
 }
 
 #[derive(Debug, Clone)]
 pub struct GetDataEntry {
     id: String,
+    pub ret_kind: ReturnKind,
     pub risk: f64,
     pub risk_free: f64,
+    pub mar: f64,
+    pub period: Period,
+    pub interval: Period,
     pub freq: usize,
+    pub rsi_cross_level: Option<f64>,
+    pub rsi_cross_within: usize,
+    pub return_mode: AnnualizedReturnMode,
+    /// Currency the price is converted into, `None` to keep the product's.
+    pub currency: Option<Currency>,
+    /// Only candles up to this date are used, all of them when `None`.
+    pub as_of: Option<NaiveDate>,
+    pub benchmark: Option<Arc<Candles>>,
 }
 
 #[async_trait]
 impl Handler<GetDataEntry> for Calculator {
-    type Response = Option<DataEntry>;
+    type Response = Result<DataEntry, Exclusion>;
 
     type Executor = ConcurrentExecutor;
 
@@ -135,7 +468,10 @@ impl Handler<GetDataEntry> for Calculator {
         puppeter: &Puppeter,
     ) -> Result<Self::Response, PuppetError> {
         let candles = puppeter
-            .ask::<Db, _>(CandlesQuery::Id(msg.id.clone()))
+            .ask::<Db, _>(CandlesQuery::new(
+                ProductQuery::Id(msg.id.clone()),
+                msg.interval,
+            ))
             .await?;
         let product = puppeter
             .ask::<Db, _>(ProductQuery::Id(msg.id.clone()))
@@ -148,38 +484,124 @@ impl Handler<GetDataEntry> for Calculator {
             .await?;
         match (candles, product, financials, ratios) {
             (Some(candles), Some(product), Some(financials), Some(ratios)) => {
+                let candles = match msg.as_of {
+                    Some(as_of) => {
+                        let end = candles.time.partition_point(|t| t.date_naive() <= as_of);
+                        candles_until(&candles, end)
+                    }
+                    None => candles,
+                };
                 if candles.time.len() >= msg.freq {
-                    let candles = candles.take_last(msg.freq).unwrap();
-                    let single_allocation = candles
+                    // The live price only belongs to an analysis done as of today.
+                    let close = msg
+                        .as_of
+                        .and_then(|_| candles.close.last().copied())
+                        .unwrap_or(product.close_price);
+                    let price = match &msg.currency {
+                        Some(currency) => {
+                            let (from, to) = (product.currency.to_string(), currency.to_string());
+                            let Some(rate) = self.settings.exchange_rate(&from, &to) else {
+                                warn!(id = %product.id, %from, %to, "No exchange rate");
+                                return Ok(Err(Exclusion::NoExchangeRate { from, to }));
+                            };
+                            close * rate
+                        }
+                        None => close,
+                    };
+                    let cagr = candles.cagr();
+                    let periods = Period::P1Y.periods(msg.interval);
+                    let risk_free = self.risk_free(&candles, msg.freq, msg.interval, msg.risk_free);
+                    // Measured before the candles are cut to `freq`, which leaves one return less.
+                    let benchmark = msg.benchmark.as_deref().map(|benchmark| {
+                        BenchmarkMetrics::new(&candles, benchmark, msg.freq, risk_free)
+                    });
+                    let available = candles.time.len();
+                    let Some(candles) = candles.take_last(msg.freq) else {
+                        return Ok(Err(Exclusion::InsufficientHistory {
+                            candles: available,
+                            required: msg.freq,
+                        }));
+                    };
+                    let risk = self
+                        .settings
+                        .asset_overrides(&msg.id)
+                        .risk
+                        .unwrap_or(msg.risk);
+                    let single_allocation = match candles
                         .single_allocation(
                             RiskMode::STD,
-                            msg.risk,
+                            msg.ret_kind,
+                            risk,
                             msg.risk_free,
-                            Period::P1Y,
-                            Period::P1M,
+                            msg.period,
+                            msg.interval,
                         )
                         .await
-                        .unwrap();
-                    let sharpe_ratio = *candles
-                        .sharpe_ratio(msg.freq, msg.risk_free)
-                        .unwrap()
-                        .last()
-                        .unwrap();
-                    let avg_dd = *candles.average_drawdown(msg.freq).unwrap().last().unwrap();
-                    let rsi = *candles.rsi(msg.freq).unwrap().last().unwrap();
-                    let redp = *candles
+                    {
+                        Ok(allocation) => allocation,
+                        Err(e) => {
+                            warn!(id = %product.id, error = %e, "No single allocation");
+                            return Ok(Err(missing_metric(&product.id, "single allocation")));
+                        }
+                    };
+                    // Like `--risk-free`, the Sharpe and Sortino ratios take the annual rate.
+                    let annual_risk_free = risk_free * periods as f64;
+                    let Some(sharpe_ratio) = candles
+                        .sharpe_ratio(msg.freq, annual_risk_free)
+                        .and_then(|sharpe| sharpe.last().copied())
+                    else {
+                        return Ok(Err(missing_metric(&product.id, "sharpe ratio")));
+                    };
+                    let sortino_ratio = candles
+                        .sortino_ratio(msg.freq, annual_risk_free, msg.mar)
+                        .and_then(|sortino| sortino.last().copied());
+                    let annualized_return =
+                        candles.annualized_return(msg.freq, periods, msg.return_mode);
+                    let annualized_risk = candles.annualized_risk(msg.freq, periods);
+                    let upside_potential_ratio = candles.upside_potential_ratio(msg.freq, msg.mar);
+                    let Some(avg_dd) = candles
+                        .average_drawdown(msg.freq)
+                        .and_then(|avg_dd| avg_dd.last().copied())
+                    else {
+                        return Ok(Err(missing_metric(&product.id, "average drawdown")));
+                    };
+                    let Some(rsi) = candles.rsi(msg.freq).and_then(|rsi| rsi.last().copied())
+                    else {
+                        return Ok(Err(missing_metric(&product.id, "rsi")));
+                    };
+                    let percent_b = candles
+                        .bollinger(msg.freq, BOLLINGER_K)
+                        .and_then(|bollinger| bollinger.last().copied());
+                    let rsi_cross = msg
+                        .rsi_cross_level
+                        .and_then(|level| candles.rsi_cross(msg.freq, level, msg.rsi_cross_within));
+                    let Some(redp) = candles
                         .rolling_economic_drawndown(msg.freq)
-                        .unwrap()
-                        .last()
-                        .unwrap();
+                        .and_then(|redp| redp.last().copied())
+                    else {
+                        return Ok(Err(missing_metric(
+                            &product.id,
+                            "rolling economic drawdown",
+                        )));
+                    };
                     let Some(beta) = ratios.current_ratios.beta.value else {
                         warn!("No beta for {}", &product.id);
-                        return Ok(None);
+                        return Ok(Err(Exclusion::NoBeta));
                     };
-                    let current_year = chrono::Utc::now().year();
-                    let Some(annual_report) = financials.get_annual(current_year - 1) else {
-                        warn!("No annual report for {} in {}", &product.id, current_year);
-                        return Ok(None);
+                    let expected = last_report_year(msg.as_of);
+                    let annual_report = match latest_annual(expected, |year| {
+                        financials.get_annual(year)
+                    }) {
+                        Ok((year, annual_report)) => {
+                            if year != expected {
+                                info!(id = %product.id, year, expected, "Using an older annual report");
+                            }
+                            annual_report
+                        }
+                        Err(year) => {
+                            warn!("No annual report for {} in {}", &product.id, year);
+                            return Ok(Err(Exclusion::NoAnnualReport(year)));
+                        }
                     };
                     let roic = annual_report.roic();
                     let capm = annual_report.capm_equity_cost(0.2, 0.05, beta);
@@ -190,25 +612,49 @@ impl Handler<GetDataEntry> for Calculator {
                         single_allocation,
                         redp_allocation: 0.0,
                         sharpe_ratio,
+                        sortino_ratio,
+                        annualized_return,
+                        annualized_risk,
+                        upside_potential_ratio,
+                        cagr,
                         avg_dd,
                         rsi,
+                        percent_b,
+                        rsi_cross,
+                        lot_size: DEFAULT_LOT_SIZE,
+                        qty: 0.0,
+                        close,
+                        price,
                         redp,
                         roic,
                         wacc,
+                        benchmark,
                     };
-                    Ok(Some(entry))
+                    Ok(Ok(entry))
                 } else {
-                    Ok(None)
+                    warn!(
+                        id = %product.id,
+                        candles = candles.time.len(),
+                        required = msg.freq,
+                        "Not enough history"
+                    );
+                    Ok(Err(Exclusion::InsufficientHistory {
+                        candles: candles.time.len(),
+                        required: msg.freq,
+                    }))
                 }
             }
-            _ => Ok(None),
+            _ => Ok(Err(Exclusion::MissingData)),
         }
     }
 }
 
+/// Data entries calculated at once unless the settings say otherwise.
+pub const DATA_ENTRY_CONCURRENCY: usize = 8;
+
 #[async_trait]
 impl Handler<CalculatePortfolio> for Calculator {
-    type Response = String;
+    type Response = CalculatedPortfolio;
 
     type Executor = ConcurrentExecutor;
 
@@ -217,51 +663,170 @@ impl Handler<CalculatePortfolio> for Calculator {
         msg: CalculatePortfolio,
         puppeter: &Puppeter,
     ) -> Result<Self::Response, PuppetError> {
-        let data = DashMap::new();
-        for (id, _) in self.settings.assets.iter() {
+        let concurrency = self
+            .settings
+            .data_entry_concurrency
+            .unwrap_or(DATA_ENTRY_CONCURRENCY)
+            .max(1);
+        // Fetched once and shared, each asset is compared over the dates it has in common.
+        let benchmark = match &msg.benchmark {
+            Some(id) => {
+                let Some(candles) = puppeter
+                    .ask::<Db, _>(CandlesQuery::new(
+                        ProductQuery::Id(id.clone()),
+                        msg.interval,
+                    ))
+                    .await?
+                else {
+                    warn!(%id, "No candles stored for the benchmark");
+                    return Ok(CalculatedPortfolio {
+                        portfolio: Err(PortfolioError::NoBenchmark(id.clone())),
+                        excluded: Vec::new(),
+                    });
+                };
+                Some(Arc::new(candles))
+            }
+            None => None,
+        };
+        let requests = self.settings.assets.iter().map(|(id, name)| {
             let get_data_entry = GetDataEntry {
                 id: id.clone(),
+                ret_kind: msg.ret_kind,
                 risk: msg.risk,
                 risk_free: msg.risk_free,
+                mar: self.mar(msg.mar),
+                period: msg.period,
+                interval: msg.interval,
                 freq: msg.freq,
+                rsi_cross_level: msg.rsi_cross_level,
+                rsi_cross_within: msg.rsi_cross_within,
+                return_mode: msg.return_mode,
+                currency: msg.currency.clone(),
+                as_of: msg.as_of,
+                benchmark: benchmark.clone(),
             };
-            if let Some(entry) = puppeter.ask::<Self, _>(get_data_entry).await? {
-                data.insert(id.clone(), entry);
+            (id, name, get_data_entry)
+        });
+        // Buffered in order so the excluded assets are listed in the order of the settings.
+        let entries = stream::iter(requests)
+            .map(|(id, name, get_data_entry)| async move {
+                (id, name, puppeter.ask::<Self, _>(get_data_entry).await)
+            })
+            .buffered(concurrency)
+            .collect::<Vec<_>>()
+            .await;
+        let data = DashMap::new();
+        let mut excluded = Vec::new();
+        for (id, name, entry) in entries {
+            match entry? {
+                Ok(entry) => {
+                    data.insert(id.clone(), entry);
+                }
+                Err(reason) => excluded.push(ExcludedAsset {
+                    id: id.clone(),
+                    name: name.clone(),
+                    reason,
+                }),
             }
         }
         let mut portfolio_calculator = PortfolioCalculator {
             mode: msg.mode,
+            ret_kind: msg.ret_kind,
             risk: msg.risk,
             risk_free: msg.risk_free,
+            period: msg.period,
+            interval: msg.interval,
             money: msg.money,
             max_stock: msg.max_stocks as i32,
             min_rsi: msg.min_rsi,
             max_rsi: msg.max_rsi,
             min_dd: msg.min_dd,
             max_dd: msg.max_dd,
+            dd_metric: msg.dd_metric,
+            min_class: msg.min_class,
+            max_class: msg.max_class,
             short_sales_constraint: msg.short_sales_constraint,
             min_roic: msg.min_roic,
             roic_wacc_delta: msg.roic_wacc_delta,
+            shrinkage: msg.shrinkage,
+            allowed_types: msg.allowed_types,
+            min_percent_b: msg.min_percent_b,
+            max_percent_b: msg.max_percent_b,
+            rsi_cross_level: msg.rsi_cross_level,
+            max_correlation: msg.max_correlation,
+            overrides: self.settings.asset_overrides.clone().unwrap_or_default(),
             data: Arc::new(data),
         };
-        portfolio_calculator.remove_invalid().calculate().await;
-        Ok(portfolio_calculator.as_table().to_string())
+        let calculated = match portfolio_calculator.remove_invalid() {
+            Ok(calculator) => calculator.calculate().await,
+            Err(err) => Err(err),
+        };
+        if let Err(err) = calculated {
+            warn!(error = %err, "Failed to calculate portfolio");
+            return Ok(CalculatedPortfolio {
+                portfolio: Err(err),
+                excluded,
+            });
+        }
+        Ok(CalculatedPortfolio {
+            portfolio: Ok(format!(
+                "{}\nLeftover cash: {}",
+                msg.table_style
+                    .render(&mut portfolio_calculator.as_table(msg.precision)),
+                msg.precision.money(portfolio_calculator.leftover_cash())
+            )),
+            excluded,
+        })
     }
 }
 
+/// Portfolio table, or why none could be calculated, along with the assets left out of it.
+#[derive(Debug)]
+pub struct CalculatedPortfolio {
+    pub portfolio: Result<String, PortfolioError>,
+    pub excluded: Vec<ExcludedAsset>,
+}
+
+#[derive(Debug, Error)]
+pub enum PortfolioError {
+    #[error("no valid assets")]
+    NoValidAssets,
+    #[error("allocation didn't settle after {0} retries")]
+    TooManyRetries(usize),
+    #[error("no candles stored for benchmark {0}")]
+    NoBenchmark(String),
+}
+
+/// Allocation attempts `calculate` makes before giving up.
+const MAX_RETRIES: usize = 5;
+
 pub struct PortfolioCalculator {
     mode: RiskMode,
+    ret_kind: ReturnKind,
     risk: f64,
     risk_free: f64,
+    period: Period,
+    interval: Period,
     money: f64,
     max_stock: i32,
     min_rsi: Option<f64>,
     max_rsi: Option<f64>,
     min_dd: Option<f64>,
     max_dd: Option<f64>,
+    dd_metric: DrawdownMetric,
+    min_class: Option<ProductCategory>,
+    max_class: Option<ProductCategory>,
     short_sales_constraint: bool,
     min_roic: Option<f64>,
     roic_wacc_delta: Option<f64>,
+    shrinkage: Option<f64>,
+    allowed_types: Option<HashSet<ProductType>>,
+    min_percent_b: Option<f64>,
+    max_percent_b: Option<f64>,
+    rsi_cross_level: Option<f64>,
+    max_correlation: Option<f64>,
+    /// Per-asset settings taking precedence over the fields above, by product id.
+    overrides: HashMap<String, AssetOverrides>,
     pub data: Arc<DashMap<String, DataEntry>>,
 }
 
@@ -270,14 +835,37 @@ impl PortfolioCalculator {
         self.data.remove(id);
     }
 
-    pub fn remove_invalid(&mut self) -> &mut Self {
+    fn risk(&self, id: &str) -> f64 {
+        self.overrides
+            .get(id)
+            .and_then(|overrides| overrides.risk)
+            .unwrap_or(self.risk)
+    }
+
+    fn short_sales_constraint(&self, id: &str) -> bool {
+        self.overrides
+            .get(id)
+            .and_then(|overrides| overrides.short_sales_constraint)
+            .unwrap_or(self.short_sales_constraint)
+    }
+
+    /// Whether `roic` clears both the absolute `min_roic` floor, 0 if unset, and the
+    /// `roic_wacc_delta` spread over `wacc`.
+    fn is_roic_valid(&self, roic: f64, wacc: f64) -> bool {
+        roic >= self.min_roic.unwrap_or(0.0)
+            && self
+                .roic_wacc_delta
+                .map_or(true, |delta| roic >= wacc + delta)
+    }
+
+    pub fn remove_invalid(&mut self) -> Result<&mut Self, PortfolioError> {
         let mut to_remove: HashSet<String> = HashSet::new();
         let max_time_month = self
             .data
             .iter()
             .filter_map(|entry| entry.value().candles.time.last().cloned())
             .max()
-            .unwrap()
+            .ok_or(PortfolioError::NoValidAssets)?
             .month();
 
         for entry in self.data.iter() {
@@ -287,12 +875,24 @@ impl PortfolioCalculator {
                 product,
                 single_allocation,
                 rsi,
+                percent_b,
+                rsi_cross,
                 roic,
                 wacc,
                 redp,
+                avg_dd,
+                price,
                 ..
             } = entry.value();
-            let last_candle_month = candles.time.last().unwrap().month();
+            let dd = match self.dd_metric {
+                DrawdownMetric::AvgDd => avg_dd,
+                DrawdownMetric::Redp => redp,
+            };
+            let Some(last_candle_month) = candles.time.last().map(Datelike::month) else {
+                println!("No candles for {} : {}", id, product.name);
+                to_remove.insert(id.clone());
+                continue;
+            };
 
             if last_candle_month != max_time_month {
                 println!(
@@ -305,6 +905,39 @@ impl PortfolioCalculator {
                 to_remove.insert(id.clone());
             }
 
+            if !product.tradable || !product.active {
+                println!("Product can't be traded for {} : {}", id, product.name);
+                to_remove.insert(id.clone());
+            }
+
+            if let Some(allowed_types) = &self.allowed_types {
+                let product_type = product.product_type();
+                if product_type == ProductType::Unknown || !allowed_types.contains(&product_type) {
+                    println!(
+                        "Product type {:?} is not allowed for {} : {}",
+                        product_type, id, product.name
+                    );
+                    to_remove.insert(id.clone());
+                }
+            }
+
+            let class = product.category.rank();
+            if self
+                .min_class
+                .as_ref()
+                .is_some_and(|min| class < min.rank())
+                || self
+                    .max_class
+                    .as_ref()
+                    .is_some_and(|max| class > max.rank())
+            {
+                println!(
+                    "Category {:?} is out of range for {} : {}",
+                    product.category, id, product.name
+                );
+                to_remove.insert(id.clone());
+            }
+
             if self.min_rsi.is_some() && self.max_rsi.is_some() {
                 let min_rsi_value = self.min_rsi.unwrap();
                 let max_rsi_value = self.max_rsi.unwrap();
@@ -316,57 +949,102 @@ impl PortfolioCalculator {
                 }
             }
 
-            if *roic < self.min_roic.unwrap_or(0.0) {
+            if let Some(level) = self.rsi_cross_level {
+                if *rsi_cross != Some(CrossSignal::OversoldUp) {
+                    println!(
+                        "No fresh RSI cross above {} for {} : {}",
+                        level, id, product.name
+                    );
+                    println!("Last cross: {:?}", rsi_cross);
+                    to_remove.insert(id.clone());
+                }
+            }
+
+            if let Some(percent_b) = percent_b {
+                if self.min_percent_b.is_some_and(|min| *percent_b < min)
+                    || self.max_percent_b.is_some_and(|max| *percent_b > max)
+                {
+                    println!("%B is out of range for {} : {}", id, product.name);
+                    println!(
+                        "%B: {}, min %B: {:?}, max %B: {:?}",
+                        percent_b, self.min_percent_b, self.max_percent_b
+                    );
+                    to_remove.insert(id.clone());
+                }
+            }
+
+            if !self.is_roic_valid(*roic, *wacc) {
                 println!("ROIC is out of range for {} : {}", id, product.name);
-                println!("Should be: {} < {}", self.min_roic.unwrap_or(0.0), roic);
+                println!(
+                    "ROIC: {}, WACC: {}, min ROIC: {:?}, ROIC-WACC delta: {:?}",
+                    roic, wacc, self.min_roic, self.roic_wacc_delta
+                );
                 to_remove.insert(id.clone());
             }
 
             if let Some(min_dd) = self.min_dd {
-                if *redp < min_dd {
+                if *dd < min_dd {
                     println!("Min DD is out of range for {} : {}", id, product.name);
-                    println!("Should be: {} < {}", redp, min_dd);
+                    println!("Should be: {} <= {}", min_dd, dd);
                     to_remove.insert(id.clone());
                 }
             }
             if let Some(max_dd) = self.max_dd {
-                if *redp > max_dd {
+                if *dd > max_dd {
                     println!("Max DD is out of range for {} : {}", id, product.name);
-                    println!("Should be: {} > {}", redp, max_dd);
+                    println!("Should be: {} <= {}", dd, max_dd);
                     to_remove.insert(id.clone());
                 }
             }
 
-            if product.close_price > self.money
-                || (*single_allocation < 1.0 && self.short_sales_constraint)
+            if *price > self.money || (*single_allocation < 1.0 && self.short_sales_constraint(id))
             {
                 to_remove.insert(id.clone());
             }
-
-            if let Some(delta) = self.roic_wacc_delta {
-                if *roic < wacc + delta {
-                    to_remove.insert(id.clone());
-                }
-            }
         }
 
         for id in to_remove {
             self.blacklist(&id);
         }
 
-        self
+        if self.data.is_empty() {
+            return Err(PortfolioError::NoValidAssets);
+        }
+        Ok(self)
     }
 
+    /// Drops every asset whose Sharpe ratio, average drawdown or ROIC isn't finite, or if all
+    /// of them are, the one that ranks worst by `cmp_scores`, ties going to the lowest id.
     pub fn remove_worst(&self) {
+        let degenerate = self
+            .data
+            .iter()
+            .filter(|entry| {
+                let DataEntry {
+                    sharpe_ratio,
+                    avg_dd,
+                    roic,
+                    ..
+                } = entry.value();
+                ![sharpe_ratio, avg_dd, roic].iter().all(|x| x.is_finite())
+            })
+            .map(|entry| entry.key().clone())
+            .collect_vec();
+        if !degenerate.is_empty() {
+            for id in &degenerate {
+                println!("Metrics are not finite for {}", id);
+                self.blacklist(id);
+            }
+            return;
+        }
+
         let min_key = {
             self.data
                 .iter()
                 .min_by(|a, b| {
-                    let a_ratio = a.value().sharpe_ratio;
-                    let b_ratio = b.value().sharpe_ratio;
-                    a_ratio
-                        .partial_cmp(&b_ratio)
-                        .unwrap_or(std::cmp::Ordering::Equal)
+                    let score = |entry: &DataEntry| (entry.sharpe_ratio, entry.avg_dd, entry.roic);
+                    cmp_scores(score(a.value()), score(b.value()))
+                        .then_with(|| a.key().cmp(b.key()))
                 })
                 .map(|min_entry| min_entry.key().clone())
         };
@@ -377,11 +1055,14 @@ impl PortfolioCalculator {
         }
     }
 
-    pub async fn calculate(&self) {
+    pub async fn calculate(&self) -> Result<(), PortfolioError> {
         let mut retry = 0;
         'outer: loop {
-            if retry > 5 {
-                panic!("Too many retries");
+            if retry > MAX_RETRIES {
+                return Err(PortfolioError::TooManyRetries(MAX_RETRIES));
+            }
+            if self.data.is_empty() {
+                return Err(PortfolioError::NoValidAssets);
             }
             let stocks = self
                 .data
@@ -399,11 +1080,13 @@ impl PortfolioCalculator {
             let Ok(mut allocations) = seq
                 .redp_multiple_allocation(
                     self.mode,
+                    self.ret_kind,
                     self.risk,
                     self.risk_free,
-                    Period::P1Y,
-                    Period::P1M,
+                    self.period,
+                    self.interval,
                     self.short_sales_constraint,
+                    self.shrinkage,
                 )
                 .await
             else {
@@ -412,7 +1095,7 @@ impl PortfolioCalculator {
                 continue 'outer;
             };
 
-            allocations.sort_by(|(_, a), (_, b)| b.partial_cmp(a).unwrap());
+            allocations.sort_by(|(_, a), (_, b)| b.total_cmp(a));
 
             if allocations.len() > self.max_stock as usize {
                 self.remove_worst();
@@ -421,14 +1104,39 @@ impl PortfolioCalculator {
 
             for (p, allocation) in allocations.iter() {
                 let cash = self.money * allocation.abs();
-                if cash < p.close_price {
+                let (price, lot_size) = self
+                    .data
+                    .get(&p.id)
+                    .map_or((p.close_price, DEFAULT_LOT_SIZE), |entry| {
+                        (entry.price, entry.lot_size)
+                    });
+                if lot_quantity(cash, price, lot_size) == 0.0 {
                     self.blacklist(&p.id);
                     continue 'outer;
                 };
             }
 
+            if let Some(max_correlation) = self.max_correlation {
+                if let Some(id) = self.correlated_to_drop(&seq, &allocations, max_correlation) {
+                    self.blacklist(&id);
+                    continue 'outer;
+                }
+            }
+
+            // Assets that may only be held long on their own, the whole set is already
+            // constrained when `short_sales_constraint` is set.
+            if let Some((p, _)) = allocations
+                .iter()
+                .find(|(p, allocation)| *allocation < 0.0 && self.short_sales_constraint(&p.id))
+            {
+                self.blacklist(&p.id);
+                continue 'outer;
+            }
+
             for (p, allocation) in allocations {
-                self.data.get_mut(&p.id).unwrap().redp_allocation = allocation;
+                if let Some(mut entry) = self.data.get_mut(&p.id) {
+                    entry.redp_allocation = allocation;
+                }
             }
             let to_remove = self
                 .data
@@ -444,46 +1152,146 @@ impl PortfolioCalculator {
                 })
                 .collect_vec();
             to_remove.iter().for_each(|id| self.blacklist(id));
+            self.enforce_budget();
 
-            break;
+            return Ok(());
         }
     }
 
+    /// Lower Sharpe member of the most correlated pair of held assets above
+    /// `max_correlation`, if there is one.
+    fn correlated_to_drop(
+        &self,
+        seq: &AssetsSeq,
+        allocations: &[(ProductDetails, f64)],
+        max_correlation: f64,
+    ) -> Option<String> {
+        let (ids, matrix) = seq
+            .correlation_matrix()
+            .map_err(|err| warn!(error = %err, "Failed to calculate correlations"))
+            .ok()?;
+        let held = ids
+            .iter()
+            .map(|id| {
+                allocations
+                    .iter()
+                    .any(|(p, allocation)| &p.id == id && *allocation != 0.0)
+            })
+            .collect_vec();
+        let (i, j) = most_correlated_pair(&matrix, &held, max_correlation)?;
+        let sharpe = |id: &str| {
+            self.data
+                .get(id)
+                .map_or(f64::NEG_INFINITY, |entry| entry.sharpe_ratio)
+        };
+        let (a, b) = (&ids[i], &ids[j]);
+        let worse = if sharpe(a) < sharpe(b) { a } else { b };
+        println!(
+            "Correlation {:.2} between {} and {} is above {}, dropping {}",
+            matrix[(i, j)],
+            a,
+            b,
+            max_correlation,
+            worse
+        );
+        Some(worse.clone())
+    }
+
+    /// Sets every position's quantity, trimming the lowest conviction ones if together they
+    /// would cost more than `money`.
+    fn enforce_budget(&self) {
+        let ids = self
+            .data
+            .iter()
+            .sorted_by(|a, b| a.redp_allocation.abs().total_cmp(&b.redp_allocation.abs()))
+            .map(|entry| entry.key().clone())
+            .collect_vec();
+        let mut positions = ids
+            .iter()
+            .filter_map(|id| {
+                let entry = self.data.get(id)?;
+                let price = entry.price;
+                let target_cash = self.money * entry.redp_allocation.abs();
+                Some((
+                    price,
+                    entry.lot_size,
+                    lot_quantity(target_cash, price, entry.lot_size),
+                ))
+            })
+            .collect_vec();
+        let leftover = trim_to_budget(&mut positions, self.money);
+        for (id, (_, _, qty)) in ids.iter().zip(positions) {
+            if let Some(mut entry) = self.data.get_mut(id) {
+                entry.qty = qty;
+            }
+        }
+        info!("Leftover cash: {:.2}", leftover);
+    }
+
+    /// Cash not deployed by the current quantities.
+    #[must_use]
+    pub fn leftover_cash(&self) -> f64 {
+        self.money
+            - self
+                .data
+                .iter()
+                .map(|entry| entry.qty * entry.price)
+                .sum::<f64>()
+    }
+
     #[must_use]
-    pub fn as_table(&self) -> Table {
+    pub fn as_table(&self, precision: Precision) -> Table {
         let mut table = Table::new();
-        let header = vec![
+        let benchmark = self.data.iter().any(|entry| entry.benchmark.is_some());
+        let mut header = vec![
             "id",
             "name",
             "symbol",
             "allocation",
+            "target cash",
             "cash",
             "qty",
             "price",
             "sl",
             "sharpe",
+            "sortino",
+            "upr",
+            "ann ret",
+            "ann vol",
+            "cagr",
             "avg dd",
             "roic",
             "wacc",
             "rsi",
             "redp",
         ];
+        if benchmark {
+            header.extend(["beta", "alpha", "ir"]);
+        }
         table.set_header(header);
-        table.load_preset(UTF8_BORDERS_ONLY);
         for entry in self
             .data
             .iter()
-            .sorted_by(|a, b| b.redp_allocation.partial_cmp(&a.redp_allocation).unwrap())
+            .sorted_by(|a, b| b.redp_allocation.total_cmp(&a.redp_allocation))
         {
             let DataEntry {
                 product,
                 redp_allocation,
                 sharpe_ratio,
+                sortino_ratio,
+                annualized_return,
+                annualized_risk,
+                upside_potential_ratio,
+                cagr,
                 redp,
                 avg_dd,
                 roic,
                 wacc,
                 rsi,
+                qty,
+                close,
+                price,
+                benchmark: metrics,
                 ..
             } = entry.value();
             let mode = if *redp_allocation > 0.0 {
@@ -492,40 +1300,167 @@ impl PortfolioCalculator {
                 TransactionType::Sell
             };
             let stop_loss = if mode == TransactionType::Buy {
-                product.close_price * (1.0 - (3.0 * avg_dd).min(self.risk))
+                close * (1.0 - (3.0 * avg_dd).min(self.risk(&product.id)))
             } else {
-                product.close_price * (1.0 + (3.0 * avg_dd).min(self.risk))
+                close * (1.0 + (3.0 * avg_dd).min(self.risk(&product.id)))
             };
-            let cash = self.money * redp_allocation.abs();
-            let qty = (cash / product.close_price).round() as i64;
-            table.add_row(vec![
+            let target_cash = self.money * redp_allocation.abs();
+            let cash = qty * price;
+            let mut row = vec![
                 Cell::new(product.id.clone()),
                 Cell::new(format!(
                     "{:<24}",
                     product.name.chars().take(24).collect::<String>()
                 )),
                 Cell::new(product.symbol.clone()),
-                Cell::new(format!("{:.2}", redp_allocation)),
-                Cell::new(format!("{:.2}", cash)),
+                Cell::new(precision.ratio(*redp_allocation)),
+                Cell::new(precision.money(target_cash)),
+                Cell::new(precision.money(cash)),
                 Cell::new(qty.to_string()),
-                Cell::new(format!("{:.2}", product.close_price)),
-                Cell::new(format!("{:.2}", stop_loss)),
-                Cell::new(format!("{:.2}", sharpe_ratio)),
-                Cell::new(format!("{:.2}", avg_dd)),
-                Cell::new(format!("{:.2}", roic)),
-                Cell::new(format!("{:.2}", wacc)),
-                Cell::new(format!("{:.2}", rsi)),
-                Cell::new(format!("{:.2}", redp)),
-            ]);
+                Cell::new(precision.money(*close)),
+                Cell::new(precision.money(stop_loss)),
+                Cell::new(precision.ratio(*sharpe_ratio)),
+                Cell::new(sortino_ratio.map_or_else(String::new, |v| precision.ratio(v))),
+                Cell::new(upside_potential_ratio.map_or_else(String::new, |v| precision.ratio(v))),
+                Cell::new(annualized_return.map_or_else(String::new, |v| precision.ratio(v))),
+                Cell::new(annualized_risk.map_or_else(String::new, |v| precision.ratio(v))),
+                Cell::new(cagr.map_or_else(String::new, |v| precision.ratio(v))),
+                Cell::new(precision.ratio(*avg_dd)),
+                Cell::new(precision.ratio(*roic)),
+                Cell::new(precision.ratio(*wacc)),
+                Cell::new(precision.ratio(*rsi)),
+                Cell::new(precision.ratio(*redp)),
+            ];
+            if benchmark {
+                let metrics = metrics.as_ref();
+                let cell = |value: Option<f64>| {
+                    Cell::new(value.map_or_else(|| "-".to_owned(), |v| precision.ratio(v)))
+                };
+                row.extend([
+                    cell(metrics.and_then(|m| m.beta)),
+                    cell(metrics.and_then(|m| m.alpha)),
+                    cell(metrics.and_then(|m| m.information_ratio)),
+                ]);
+            }
+            table.add_row(row);
         }
 
         table
     }
 }
 
+#[derive(Debug, Clone, Copy, EnumString, Serialize, Deserialize)]
+#[strum(serialize_all = "lowercase")]
+pub enum StopLossMethod {
+    /// `nstd` times the average drawdown below the last price.
+    Drawdown,
+    /// `nstd` times the average true range below the last price.
+    Atr,
+    /// `nstd` percent below the last price.
+    Percent,
+}
+
+impl StopLossMethod {
+    /// Header of the column holding one unit of the method.
+    const fn unit_header(self) -> &'static str {
+        match self {
+            Self::Drawdown => "avg dd",
+            Self::Atr => "atr",
+            Self::Percent => "percent",
+        }
+    }
+}
+
+/// Average drawdown of the last `freq` candles, of only the `largest` ones when given.
+fn stop_drawdown(candles: &Candles, freq: usize, largest: Option<usize>) -> Option<f64> {
+    match largest {
+        Some(n) => candles.average_drawdown_n(freq, n),
+        None => candles.average_drawdown(freq)?.last().copied(),
+    }
+}
+
+/// One unit of `method` over the last `freq` candles: the average drawdown as a fraction, the
+/// average true range as a price or one percent. `None` when the candles are too short.
+fn stop_unit(
+    candles: &Candles,
+    method: StopLossMethod,
+    freq: usize,
+    largest_drawdowns: Option<usize>,
+) -> Option<f64> {
+    match method {
+        StopLossMethod::Drawdown => stop_drawdown(candles, freq, largest_drawdowns),
+        StopLossMethod::Atr => candles.atr(freq)?.last().copied(),
+        StopLossMethod::Percent => Some(0.01),
+    }
+}
+
+/// Stop loss `nstd` `unit`s of `method` below `last_price`, raised to at most `max_percent`
+/// below it.
+fn stop_below(
+    last_price: f64,
+    method: StopLossMethod,
+    unit: f64,
+    nstd: f64,
+    max_percent: Option<f64>,
+) -> f64 {
+    let stop = match method {
+        StopLossMethod::Drawdown | StopLossMethod::Percent => last_price * unit.mul_add(-nstd, 1.0),
+        StopLossMethod::Atr => unit.mul_add(-nstd, last_price),
+    };
+    max_percent.map_or(stop, |max_percent| {
+        stop.max(last_price * (1.0 - max_percent / 100.0))
+    })
+}
+
+/// Stop loss `nstd` units of `method` below the last close, `None` when the candles are too
+/// short for the method.
+fn stop_loss(
+    candles: &Candles,
+    method: StopLossMethod,
+    nstd: f64,
+    freq: usize,
+    max_percent: Option<f64>,
+    largest_drawdowns: Option<usize>,
+) -> Option<f64> {
+    let last_price = *candles.close.last()?;
+    let unit = stop_unit(candles, method, freq, largest_drawdowns)?;
+    Some(stop_below(last_price, method, unit, nstd, max_percent))
+}
+
+/// Whether a sell stop at `stop` can be placed under a position trading at `price`. The stop
+/// is calculated from the last candle, which can be weeks old, so it may already sit at or
+/// above the live price, and a deep enough stop loss method ends up at or below zero.
+fn is_valid_stop(stop: f64, price: f64) -> bool {
+    stop > 0.0 && stop < price
+}
+
+/// `stop` rounded down to a multiple of `tick_size`, `None` for a tick size that isn't
+/// positive. The division is nudged up so a stop already on the tick isn't floored a tick
+/// lower by the error of the division.
+fn round_to_tick(stop: f64, tick_size: f64) -> Option<f64> {
+    (tick_size.is_finite() && tick_size > 0.0).then(|| {
+        let ticks = (stop / tick_size + 1e-9).floor();
+        (ticks * tick_size * 1e10).round() / 1e10
+    })
+}
+
 #[derive(Debug, Clone)]
 pub struct CalculateSl {
-    pub n: usize,
+    /// Distance of the stop below the last price, in units of `method`.
+    pub nstd: f64,
+    pub method: StopLossMethod,
+    /// Furthest the stop may sit below the last price, in percent.
+    pub max_percent: Option<f64>,
+    /// Averages only this many of the largest drawdowns for the `Drawdown` method.
+    pub largest_drawdowns: Option<usize>,
+    /// Minimum acceptable return for the Sortino ratio column.
+    pub mar: Option<f64>,
+    /// Lookback of the stop loss method and the Sortino ratio column, in candles.
+    pub freq: usize,
+    /// Annual risk free rate of the Sortino ratio column, unless rates are configured.
+    pub risk_free: f64,
+    pub table_style: TableStyle,
+    pub precision: Precision,
 }
 
 #[async_trait]
@@ -540,6 +1475,7 @@ impl Handler<CalculateSl> for Calculator {
         puppeter: &Puppeter,
     ) -> Result<Self::Response, PuppetError> {
         info!("Calculating stop losses...");
+        let mar = self.mar(msg.mar);
         let portfolio = puppeter.ask::<Degiro, _>(GetPortfolio).await?;
         let orders = puppeter.ask::<Degiro, _>(GetOrders).await?;
         let mut table = comfy_table::Table::new();
@@ -549,11 +1485,12 @@ impl Handler<CalculateSl> for Calculator {
             comfy_table::Cell::new("symbol"),
             comfy_table::Cell::new("date"),
             comfy_table::Cell::new("price"),
-            comfy_table::Cell::new("avg dd").set_alignment(comfy_table::CellAlignment::Right),
+            comfy_table::Cell::new(msg.method.unit_header())
+                .set_alignment(comfy_table::CellAlignment::Right),
+            comfy_table::Cell::new("sortino").set_alignment(comfy_table::CellAlignment::Right),
             comfy_table::Cell::new("stop loss").set_alignment(comfy_table::CellAlignment::Right),
         ];
         table.set_header(header);
-        table.load_preset(UTF8_BORDERS_ONLY);
         for position in portfolio.0.iter() {
             let Ok(product_id) = position.inner.id.parse::<u64>() else {
                 continue;
@@ -561,79 +1498,242 @@ impl Handler<CalculateSl> for Calculator {
             if position.inner.size <= 0.0 {
                 continue;
             }
+            // Candles first, fetching them on a miss stores the product as well.
+            let candles = puppeter
+                .ask::<Degiro, _>(CachedCandles::new(position.inner.id.clone()))
+                .await?;
             let product = puppeter
                 .ask::<Db, _>(ProductQuery::Id(position.inner.id.clone()))
                 .await?;
-            let candles = puppeter
-                .ask::<Db, _>(CandlesQuery::Id(position.inner.id.clone()))
-                .await?;
             let old_sl = orders
                 .filter_product_id(product_id)
                 .first()
                 .map(|o| o.stop_price);
             if let (Some(product), Some(candles)) = (product, candles) {
-                if let Some(avg_dd) = candles.average_drawdown(12) {
-                    if let Some(Some(avg_dd_value)) = avg_dd.values.last() {
-                        let Some(last_price) = candles.close.last() else {
-                            return Err(PuppetError::critical(
-                                puppeter.pid,
-                                "Failed to get last price",
-                            ));
-                        };
-                        let Some(last_time) = candles.time.last() else {
-                            return Err(PuppetError::critical(
-                                puppeter.pid,
-                                "Failed to get last time",
-                            ));
-                        };
-                        let new_stop = last_price * (1.0 - avg_dd_value * msg.n as f64);
-                        table.add_row(vec![
-                            comfy_table::Cell::new(product.id.clone()),
-                            comfy_table::Cell::new(format!(
-                                "{:<24}",
-                                product.name.chars().take(24).collect::<String>()
-                            )),
-                            comfy_table::Cell::new(product.symbol.clone()),
-                            comfy_table::Cell::new(last_time.to_string()),
-                            comfy_table::Cell::new(last_price)
-                                .set_alignment(comfy_table::CellAlignment::Right),
-                            comfy_table::Cell::new(format!("{:.2}", avg_dd_value))
-                                .set_alignment(comfy_table::CellAlignment::Right),
-                            match (new_stop, old_sl) {
-                                (new_sl, None) => comfy_table::Cell::new(format!("{:.2}", new_sl))
+                if let Some(unit) = stop_unit(&candles, msg.method, msg.freq, msg.largest_drawdowns)
+                {
+                    let Some(last_price) = candles.close.last() else {
+                        return Err(PuppetError::critical(
+                            puppeter.pid,
+                            "Failed to get last price",
+                        ));
+                    };
+                    let Some(last_time) = candles.time.last() else {
+                        return Err(PuppetError::critical(
+                            puppeter.pid,
+                            "Failed to get last time",
+                        ));
+                    };
+                    let nstd = self
+                        .settings
+                        .asset_overrides(&position.inner.id)
+                        .sl_nstd
+                        .unwrap_or(msg.nstd);
+                    let new_stop = stop_below(*last_price, msg.method, unit, nstd, msg.max_percent);
+                    let unit = match msg.method {
+                        StopLossMethod::Drawdown => msg.precision.ratio(unit),
+                        StopLossMethod::Atr => msg.precision.money(unit),
+                        StopLossMethod::Percent => msg.precision.ratio(nstd),
+                    };
+                    let risk_free =
+                        self.risk_free(&candles, msg.freq, DEFAULT_INTERVAL, msg.risk_free)
+                            * Period::P1Y.periods(DEFAULT_INTERVAL) as f64;
+                    let sortino_ratio = candles
+                        .sortino_ratio(msg.freq, risk_free, mar)
+                        .and_then(|sortino| sortino.last().copied());
+                    table.add_row(vec![
+                        comfy_table::Cell::new(product.id.clone()),
+                        comfy_table::Cell::new(format!(
+                            "{:<24}",
+                            product.name.chars().take(24).collect::<String>()
+                        )),
+                        comfy_table::Cell::new(product.symbol.clone()),
+                        comfy_table::Cell::new(last_time.to_string()),
+                        comfy_table::Cell::new(last_price)
+                            .set_alignment(comfy_table::CellAlignment::Right),
+                        comfy_table::Cell::new(unit)
+                            .set_alignment(comfy_table::CellAlignment::Right),
+                        comfy_table::Cell::new(
+                            sortino_ratio.map_or_else(String::new, |v| msg.precision.ratio(v)),
+                        )
+                        .set_alignment(comfy_table::CellAlignment::Right),
+                        match (new_stop, old_sl) {
+                            (new_sl, None) => comfy_table::Cell::new(msg.precision.money(new_sl))
+                                .set_alignment(comfy_table::CellAlignment::Right)
+                                .fg(comfy_table::Color::Red),
+                            (new_sl, Some(old_sl)) if old_sl >= new_sl => {
+                                comfy_table::Cell::new(msg.precision.money(new_sl))
                                     .set_alignment(comfy_table::CellAlignment::Right)
-                                    .fg(comfy_table::Color::Red),
-                                (new_sl, Some(old_sl)) if old_sl >= new_sl => {
-                                    comfy_table::Cell::new(format!("{:.2}", new_sl))
-                                        .set_alignment(comfy_table::CellAlignment::Right)
-                                        .fg(comfy_table::Color::Yellow)
-                                }
-                                (new_sl, Some(_)) => {
-                                    comfy_table::Cell::new(format!("{:.2}", new_sl))
-                                        .set_alignment(comfy_table::CellAlignment::Right)
-                                        .fg(comfy_table::Color::Green)
-                                }
-                            },
-                        ]);
-                    }
+                                    .fg(comfy_table::Color::Yellow)
+                            }
+                            (new_sl, Some(_)) => {
+                                comfy_table::Cell::new(msg.precision.money(new_sl))
+                                    .set_alignment(comfy_table::CellAlignment::Right)
+                                    .fg(comfy_table::Color::Green)
+                            }
+                        },
+                    ]);
                 }
             } else {
                 eprintln!("Failed to get data for {}", &position.inner.id);
             };
         }
-        Ok(table.to_string())
+        Ok(msg.table_style.render(&mut table))
     }
 }
 
+/// Places a stop loss at the `CalculateSl` level for every open position without a pending
+/// sell order. With `dry_run` the stops are only listed.
+#[derive(Debug, Clone)]
+pub struct PlaceStopLosses {
+    /// Distance of the stop below the last price, in units of `method`.
+    pub nstd: f64,
+    pub method: StopLossMethod,
+    /// Lookback of the stop loss method, in candles.
+    pub freq: usize,
+    /// Furthest the stop may sit below the last price, in percent.
+    pub max_percent: Option<f64>,
+    /// Averages only this many of the largest drawdowns for the `Drawdown` method.
+    pub largest_drawdowns: Option<usize>,
+    pub dry_run: bool,
+    pub table_style: TableStyle,
+    pub precision: Precision,
+}
+
 #[async_trait]
-impl Handler<GetPortfolio> for Calculator {
+impl Handler<PlaceStopLosses> for Calculator {
     type Response = String;
 
     type Executor = ConcurrentExecutor;
 
     async fn handle_message(
         &mut self,
-        _msg: GetPortfolio,
+        msg: PlaceStopLosses,
+        puppeter: &Puppeter,
+    ) -> Result<Self::Response, PuppetError> {
+        info!(dry_run = msg.dry_run, "Placing stop losses...");
+        let portfolio = puppeter.ask::<Degiro, _>(GetPortfolio).await?;
+        let orders = puppeter.ask::<Degiro, _>(GetOrders).await?;
+        let mut table = comfy_table::Table::new();
+        let header = vec![
+            comfy_table::Cell::new("id"),
+            comfy_table::Cell::new("symbol"),
+            comfy_table::Cell::new("size").set_alignment(comfy_table::CellAlignment::Right),
+            comfy_table::Cell::new("stop loss").set_alignment(comfy_table::CellAlignment::Right),
+            comfy_table::Cell::new("status"),
+        ];
+        table.set_header(header);
+        for position in portfolio.0.iter() {
+            let Ok(product_id) = position.inner.id.parse::<u64>() else {
+                continue;
+            };
+            if position.inner.size <= 0.0 {
+                continue;
+            }
+            // Candles first, fetching them on a miss stores the product as well.
+            let candles = puppeter
+                .ask::<Degiro, _>(CachedCandles::new(position.inner.id.clone()))
+                .await?;
+            let product = puppeter
+                .ask::<Db, _>(ProductQuery::Id(position.inner.id.clone()))
+                .await?;
+            let (Some(product), Some(candles)) = (product, candles) else {
+                eprintln!("Failed to get data for {}", &position.inner.id);
+                continue;
+            };
+            let nstd = self
+                .settings
+                .asset_overrides(&position.inner.id)
+                .sl_nstd
+                .unwrap_or(msg.nstd);
+            let Some(stop) = stop_loss(
+                &candles,
+                msg.method,
+                nstd,
+                msg.freq,
+                msg.max_percent,
+                msg.largest_drawdowns,
+            ) else {
+                eprintln!("Failed to calculate stop loss for {}", &position.inner.id);
+                continue;
+            };
+            // Prices off the product's tick size are rejected, rounding down keeps the stop
+            // below the price it was calculated from.
+            let rounded = product
+                .tick_size
+                .and_then(|tick_size| round_to_tick(stop, tick_size));
+            let stop = rounded.unwrap_or(stop);
+            // Any pending sell would double up with the stop once both fill.
+            let status = if rounded.is_none() {
+                warn!(id = %position.inner.id, "No tick size to round the stop loss to");
+                "unknown tick"
+            } else if orders.has_open_order(product_id, TransactionType::Sell) {
+                "open order"
+            } else if !is_valid_stop(stop, position.inner.price) {
+                warn!(
+                    id = %position.inner.id,
+                    stop,
+                    price = position.inner.price,
+                    "Stop loss isn't between zero and the current price"
+                );
+                "rejected"
+            } else if msg.dry_run {
+                "dry run"
+            } else {
+                let request = CreateOrderRequestBuilder::default()
+                    .product_id(product_id)
+                    .transaction_type(TransactionType::Sell)
+                    .order_type(OrderType::StopLoss)
+                    .time_type(OrderTimeType::Permanent)
+                    .size(position.inner.size)
+                    .stop_price(stop)
+                    .build();
+                match request {
+                    Ok(request) => match puppeter.ask::<Degiro, _>(PlaceOrder(request)).await {
+                        Ok(()) => "placed",
+                        Err(e) => {
+                            error!(error = %e, id = %position.inner.id, "Failed to place stop loss");
+                            "failed"
+                        }
+                    },
+                    Err(e) => {
+                        error!(error = %e, id = %position.inner.id, "Failed to build stop loss order");
+                        "failed"
+                    }
+                }
+            };
+            table.add_row(vec![
+                comfy_table::Cell::new(product.id.clone()),
+                comfy_table::Cell::new(product.symbol.clone()),
+                comfy_table::Cell::new(position.inner.size)
+                    .set_alignment(comfy_table::CellAlignment::Right),
+                comfy_table::Cell::new(msg.precision.money(stop))
+                    .set_alignment(comfy_table::CellAlignment::Right),
+                comfy_table::Cell::new(status),
+            ]);
+        }
+        Ok(msg.table_style.render(&mut table))
+    }
+}
+
+/// Open positions as a table.
+#[derive(Debug, Clone, Copy)]
+pub struct GetPortfolioTable {
+    pub table_style: TableStyle,
+    pub precision: Precision,
+    /// Date the annual reports are picked as of, today when `None`.
+    pub as_of: Option<NaiveDate>,
+}
+
+#[async_trait]
+impl Handler<GetPortfolioTable> for Calculator {
+    type Response = String;
+
+    type Executor = ConcurrentExecutor;
+
+    async fn handle_message(
+        &mut self,
+        msg: GetPortfolioTable,
         puppeter: &Puppeter,
     ) -> Result<Self::Response, PuppetError> {
         let portfolio = puppeter.ask::<Degiro, _>(GetPortfolio).await?;
@@ -651,7 +1751,6 @@ impl Handler<GetPortfolio> for Calculator {
             comfy_table::Cell::new("wacc").set_alignment(comfy_table::CellAlignment::Right),
         ];
         table.set_header(header);
-        table.load_preset(UTF8_BORDERS_ONLY);
         for position in portfolio.0.iter() {
             if position.inner.size <= 0.0 {
                 continue;
@@ -690,22 +1789,27 @@ impl Handler<GetPortfolio> for Calculator {
                 let profit_perc = position.inner.total_profit.amount
                     / (position.inner.size * position.inner.break_even_price);
                 row.push(
-                    Cell::new(format!("{:.2}%", profit_perc * 100.0))
+                    Cell::new(format!("{}%", msg.precision.ratio(profit_perc * 100.0)))
                         .set_alignment(comfy_table::CellAlignment::Right),
                 );
 
-                let current_year = chrono::Utc::now().year();
-                if let Some(annual_report) = financials.get_annual(current_year - 1) {
+                let expected = last_report_year(msg.as_of);
+                if let Ok((year, annual_report)) =
+                    latest_annual(expected, |year| financials.get_annual(year))
+                {
+                    if year != expected {
+                        info!(id = %product.id, year, expected, "Using an older annual report");
+                    }
                     if let Some(beta) = ratios.current_ratios.beta.value {
                         let roic = annual_report.roic();
                         let capm = annual_report.capm_equity_cost(0.2, 0.05, beta);
                         let wacc = annual_report.wacc(capm);
                         row.push(
-                            Cell::new(format!("{:.2}", roic))
+                            Cell::new(msg.precision.ratio(roic))
                                 .set_alignment(comfy_table::CellAlignment::Right),
                         );
                         row.push(
-                            Cell::new(format!("{:.2}", wacc))
+                            Cell::new(msg.precision.ratio(wacc))
                                 .set_alignment(comfy_table::CellAlignment::Right),
                         );
                     }
@@ -716,6 +1820,193 @@ impl Handler<GetPortfolio> for Calculator {
                 eprintln!("Failed to get data for {}", &position.inner.id);
             };
         }
-        Ok(table.to_string())
+        Ok(msg.table_style.render(&mut table))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn calculator(min_roic: Option<f64>, roic_wacc_delta: Option<f64>) -> PortfolioCalculator {
+        PortfolioCalculator {
+            mode: RiskMode::STD,
+            ret_kind: ReturnKind::Simple,
+            risk: 0.3,
+            risk_free: 0.0,
+            period: Period::P1Y,
+            interval: DEFAULT_INTERVAL,
+            money: 10_000.0,
+            max_stock: 10,
+            min_rsi: None,
+            max_rsi: None,
+            min_dd: None,
+            max_dd: None,
+            dd_metric: DrawdownMetric::default(),
+            min_class: None,
+            max_class: None,
+            short_sales_constraint: false,
+            min_roic,
+            roic_wacc_delta,
+            shrinkage: None,
+            allowed_types: None,
+            min_percent_b: None,
+            max_percent_b: None,
+            rsi_cross_level: None,
+            max_correlation: None,
+            overrides: HashMap::new(),
+            data: Arc::default(),
+        }
+    }
+
+    #[test]
+    fn asset_overrides() {
+        let mut calculator = calculator(None, None);
+        calculator.overrides.insert(
+            "1".to_owned(),
+            AssetOverrides {
+                risk: Some(0.1),
+                short_sales_constraint: Some(true),
+                ..AssetOverrides::default()
+            },
+        );
+        assert!((calculator.risk("1") - 0.1).abs() < f64::EPSILON);
+        assert!((calculator.risk("2") - 0.3).abs() < f64::EPSILON);
+        assert!(calculator.short_sales_constraint("1"));
+        assert!(!calculator.short_sales_constraint("2"));
+    }
+
+    #[test]
+    fn min_roic_floor() {
+        let calculator = calculator(Some(0.1), None);
+        assert!(!calculator.is_roic_valid(0.09, 0.0));
+        assert!(calculator.is_roic_valid(0.1, 0.0));
+        assert!(calculator.is_roic_valid(0.11, 0.0));
+    }
+
+    #[test]
+    fn min_roic_with_wacc_delta() {
+        let calculator = calculator(Some(0.1), Some(0.02));
+        assert!(!calculator.is_roic_valid(0.11, 0.1));
+        assert!(calculator.is_roic_valid(0.13, 0.1));
+        assert!(!calculator.is_roic_valid(0.09, 0.05));
+    }
+
+    #[test]
+    fn no_roic_bounds_keeps_zero_floor() {
+        let calculator = calculator(None, None);
+        assert!(!calculator.is_roic_valid(-0.5, 0.1));
+        assert!(calculator.is_roic_valid(0.0, 0.1));
+    }
+
+    #[test]
+    fn lot_quantity_floors_to_whole_lots() {
+        assert!((lot_quantity(1_000.0, 30.0, 1.0) - 33.0).abs() < 1e-9);
+        assert!((lot_quantity(1_000.0, 30.0, 10.0) - 30.0).abs() < 1e-9);
+        assert!(lot_quantity(20.0, 30.0, 1.0).abs() < 1e-9);
+        assert!((lot_quantity(20.0, 30.0, 0.5) - 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn lot_quantity_invalid_inputs() {
+        assert!(lot_quantity(1_000.0, 0.0, 1.0).abs() < 1e-9);
+        assert!(lot_quantity(1_000.0, 30.0, 0.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn trim_to_budget_lowest_conviction_first() {
+        let mut positions = [(30.0, 1.0, 2.0), (40.0, 1.0, 2.0)];
+        let leftover = trim_to_budget(&mut positions, 100.0);
+        assert_eq!(positions, [(30.0, 1.0, 0.0), (40.0, 1.0, 2.0)]);
+        assert!((leftover - 20.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn trim_to_budget_within_budget() {
+        let mut positions = [(30.0, 1.0, 1.0), (40.0, 1.0, 1.0)];
+        let leftover = trim_to_budget(&mut positions, 100.0);
+        assert_eq!(positions, [(30.0, 1.0, 1.0), (40.0, 1.0, 1.0)]);
+        assert!((leftover - 30.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn cmp_scores_breaks_ties() {
+        assert_eq!(cmp_scores((0.5, 0.1, 0.2), (0.6, 0.3, 0.0)), Ordering::Less);
+        assert_eq!(cmp_scores((0.5, 0.2, 0.2), (0.5, 0.1, 0.0)), Ordering::Less);
+        assert_eq!(cmp_scores((0.5, 0.1, 0.1), (0.5, 0.1, 0.2)), Ordering::Less);
+        assert_eq!(
+            cmp_scores((0.5, 0.1, 0.2), (0.5, 0.1, 0.2)),
+            Ordering::Equal
+        );
+    }
+
+    #[test]
+    fn latest_annual_falls_back_to_older_years() {
+        let year = last_report_year(NaiveDate::from_ymd_opt(2024, 2, 1));
+        assert_eq!(year, 2023);
+        assert_eq!(
+            latest_annual(year, |y| (y <= 2022).then_some(())),
+            Ok((2022, ()))
+        );
+        assert_eq!(
+            latest_annual(year, |y| (y <= 2023).then_some(())),
+            Ok((2023, ()))
+        );
+        assert_eq!(latest_annual(year, |_| None::<()>), Err(2023));
+    }
+
+    #[test]
+    fn stop_between_zero_and_price() {
+        assert!(is_valid_stop(90.0, 100.0));
+        assert!(!is_valid_stop(100.0, 100.0));
+        assert!(!is_valid_stop(105.0, 100.0));
+        assert!(!is_valid_stop(0.0, 100.0));
+        assert!(!is_valid_stop(-5.0, 100.0));
+        assert!(!is_valid_stop(f64::NAN, 100.0));
+    }
+
+    #[test]
+    fn stop_on_tick() {
+        let rounded = round_to_tick(12.37, 0.05).unwrap();
+        assert!((rounded - 12.35).abs() < 1e-9);
+        // Already on the tick despite 12.35 / 0.05 falling just short of 247.
+        let rounded = round_to_tick(12.35, 0.05).unwrap();
+        assert!((rounded - 12.35).abs() < 1e-9);
+        let rounded = round_to_tick(0.123_456, 0.0001).unwrap();
+        assert!((rounded - 0.1234).abs() < 1e-9);
+        assert!(round_to_tick(12.37, 0.0).is_none());
+    }
+
+    #[test]
+    fn stop_distance() {
+        let percent = stop_below(200.0, StopLossMethod::Percent, 0.01, 7.5, None);
+        assert!((percent - 185.0).abs() < 1e-9);
+        let drawdown = stop_below(200.0, StopLossMethod::Drawdown, 0.04, 2.0, None);
+        assert!((drawdown - 184.0).abs() < 1e-9);
+        let atr = stop_below(200.0, StopLossMethod::Atr, 3.0, 1.5, None);
+        assert!((atr - 195.5).abs() < 1e-9);
+        let capped = stop_below(200.0, StopLossMethod::Atr, 30.0, 2.0, Some(10.0));
+        assert!((capped - 180.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn most_correlated_held_pair() {
+        let matrix = nalgebra::DMatrix::from_row_slice(
+            3,
+            3,
+            &[1.0, 0.9, 0.95, 0.9, 1.0, 0.8, 0.95, 0.8, 1.0],
+        );
+        assert_eq!(
+            most_correlated_pair(&matrix, &[true, true, true], 0.85),
+            Some((0, 2))
+        );
+        assert_eq!(
+            most_correlated_pair(&matrix, &[true, true, false], 0.85),
+            Some((0, 1))
+        );
+        assert_eq!(
+            most_correlated_pair(&matrix, &[true, true, true], 0.95),
+            None
+        );
     }
 }
@@ -1,9 +1,16 @@
+use std::collections::{BTreeMap, HashMap};
+
 use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
 use config::Config;
+use degiro_rs::util::Period;
 use master_of_puppets::prelude::*;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use tracing::{error, info};
 
+use super::db::DEFAULT_INTERVAL;
+use crate::indicators::RiskFree;
+
 #[derive(Clone, Debug, Deserialize, Serialize, Default)]
 pub struct Settings {
     #[serde(skip)]
@@ -12,6 +19,53 @@ pub struct Settings {
     pub password: String,
     pub assets: Vec<(String, String)>,
     pub disabled_assets: Option<Vec<(String, String)>>,
+    /// Initial size of the database map in bytes, `DEFAULT_MAP_SIZE` if unset. The map is
+    /// doubled whenever a write fills it up.
+    pub db_map_size: Option<usize>,
+    pub requests_per_second: Option<f64>,
+    pub mar: Option<f64>,
+    /// Candle intervals fetched besides the default monthly one.
+    pub candle_intervals: Option<Vec<Period>>,
+    /// Only stores the product details of assets DEGIRO marks as untradable or inactive.
+    pub skip_untradable: Option<bool>,
+    /// Values used for a single asset instead of the ones a command was given, by product id.
+    pub asset_overrides: Option<HashMap<String, AssetOverrides>>,
+    /// Assets whose data entries are calculated at once, `DATA_ENTRY_CONCURRENCY` by default.
+    pub data_entry_concurrency: Option<usize>,
+    /// Value of one unit of each currency in a common base currency, by currency code, e.g.
+    /// `{ EUR = 1.0, USD = 0.92 }`. Used to price assets in the currency of a budget. Every rate
+    /// must be positive, the config is rejected otherwise.
+    #[serde(default, deserialize_with = "positive_rates")]
+    pub exchange_rates: Option<HashMap<String, f64>>,
+    /// Risk free rate per candle of the default interval from each date on, e.g.
+    /// `{ 2023-01-01 = 0.002, 2024-01-01 = 0.003 }`. Replaces the `--risk-free` of a portfolio.
+    pub risk_free_rates: Option<BTreeMap<NaiveDate, f64>>,
+}
+
+fn positive_rates<'de, D>(deserializer: D) -> Result<Option<HashMap<String, f64>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let rates = Option::<HashMap<String, f64>>::deserialize(deserializer)?;
+    if let Some((code, rate)) = rates
+        .iter()
+        .flatten()
+        .find(|(_, rate)| !(rate.is_finite() && **rate > 0.0))
+    {
+        return Err(serde::de::Error::custom(format!(
+            "exchange rate of {code} must be positive, got {rate}"
+        )));
+    }
+    Ok(rates)
+}
+
+/// Per-asset replacements for command options, every field is optional.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct AssetOverrides {
+    pub risk: Option<f64>,
+    /// Stop loss distance in units of the stop loss method.
+    pub sl_nstd: Option<f64>,
+    pub short_sales_constraint: Option<bool>,
 }
 
 impl Settings {
@@ -34,6 +88,53 @@ impl Settings {
         settings.file_path = Some(path.to_owned());
         settings
     }
+
+    /// Intervals to fetch candles in, the default one always comes first.
+    #[must_use]
+    pub fn candle_intervals(&self) -> Vec<Period> {
+        let mut intervals = vec![DEFAULT_INTERVAL];
+        for &interval in self.candle_intervals.iter().flatten() {
+            if !intervals.contains(&interval) {
+                intervals.push(interval);
+            }
+        }
+        intervals
+    }
+
+    /// Units of `to` one unit of `from` is worth, `None` unless both have a configured rate.
+    #[must_use]
+    pub fn exchange_rate(&self, from: &str, to: &str) -> Option<f64> {
+        if from.eq_ignore_ascii_case(to) {
+            return Some(1.0);
+        }
+        let rates = self.exchange_rates.as_ref()?;
+        let rate = |code: &str| {
+            rates
+                .iter()
+                .find_map(|(c, rate)| c.eq_ignore_ascii_case(code).then_some(*rate))
+        };
+        Some(rate(from)? / rate(to)?)
+    }
+
+    /// Risk free rates of the returns between `times`, the configured `risk_free_rates` if
+    /// any, otherwise the `annual` rate spread evenly over `periods` candles a year.
+    #[must_use]
+    pub fn risk_free(&self, times: &[DateTime<Utc>], annual: f64, periods: usize) -> RiskFree {
+        self.risk_free_rates.as_ref().map_or_else(
+            || RiskFree::Constant(annual / periods as f64),
+            |rates| RiskFree::dated(rates, times),
+        )
+    }
+
+    /// Overrides configured for the asset `id`, none set if it has no entry.
+    #[must_use]
+    pub fn asset_overrides(&self, id: &str) -> AssetOverrides {
+        self.asset_overrides
+            .as_ref()
+            .and_then(|overrides| overrides.get(id))
+            .cloned()
+            .unwrap_or_default()
+    }
 }
 
 #[async_trait]
@@ -86,12 +187,13 @@ impl Handler<GetSettings> for Settings {
     }
 }
 
+/// Moves an asset into `disabled_assets`, answering whether it was configured at all.
 #[derive(Debug, Clone)]
 pub struct DeleteAsset(pub String);
 
 #[async_trait]
 impl Handler<DeleteAsset> for Settings {
-    type Response = ();
+    type Response = bool;
     type Executor = SequentialExecutor;
     async fn handle_message(
         &mut self,
@@ -107,7 +209,39 @@ impl Handler<DeleteAsset> for Settings {
                 self.disabled_assets = Some(vec![asset]);
             }
             puppeter.send::<Self, _>(SaveSettings).await?;
+            return Ok(true);
         }
-        Ok(())
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn with_rates(rates: &str) -> Result<Settings, config::ConfigError> {
+        let toml = format!("username = \"u\"\npassword = \"p\"\nassets = []\n{rates}");
+        Config::builder()
+            .add_source(config::File::from_str(&toml, config::FileFormat::Toml))
+            .build()?
+            .try_deserialize()
+    }
+
+    #[test]
+    fn exchange_rate() {
+        let settings = with_rates("[exchange_rates]\nEUR = 1.0\nUSD = 0.8").unwrap();
+        assert_eq!(settings.exchange_rate("PLN", "pln"), Some(1.0));
+        let usd_eur = settings.exchange_rate("USD", "EUR").unwrap();
+        assert!((usd_eur - 0.8).abs() < 1e-9);
+        let eur_usd = settings.exchange_rate("EUR", "USD").unwrap();
+        assert!((eur_usd - 1.25).abs() < 1e-9);
+        assert_eq!(settings.exchange_rate("USD", "PLN"), None);
+        assert_eq!(with_rates("").unwrap().exchange_rate("USD", "EUR"), None);
+    }
+
+    #[test]
+    fn rejects_non_positive_rates() {
+        assert!(with_rates("[exchange_rates]\nEUR = 1.0\nUSD = 0.0").is_err());
+        assert!(with_rates("[exchange_rates]\nUSD = -0.9").is_err());
     }
 }
@@ -1,13 +1,18 @@
 use std::{
+    collections::{HashMap, HashSet},
+    future::Future,
     net::{SocketAddr, SocketAddrV4},
+    path::PathBuf,
     sync::Arc,
 };
 
 use async_trait::async_trait;
 use chrono::{Duration, NaiveDate};
-use comfy_table::presets::UTF8_BORDERS_ONLY;
-use degiro_rs::api::{
-    financial_statements::FinancialReports, product::ProductDetails, transactions::Transactions,
+use degiro_rs::{
+    api::{
+        financial_statements::FinancialReports, product::ProductDetails, transactions::Transactions,
+    },
+    util::{Currency, Period},
 };
 use erfurt::prelude::Candles;
 use futures::SinkExt;
@@ -22,26 +27,54 @@ use tokio::{
 };
 use tokio_stream::StreamExt;
 use tokio_util::codec::{Framed, LengthDelimitedCodec};
-use tracing::{error, info};
+use tracing::{error, info, warn};
+
+/// How often the server reassures a waiting client that a request is still being processed.
+pub const KEEPALIVE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(15);
+
+/// How long `GetStatus` waits for each puppet, one busy for longer is reported as unknown.
+pub const STATUS_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
 
 use crate::{
-    portfolio::RiskMode,
+    indicators::annualized_return::AnnualizedReturnMode,
+    portfolio::{ReturnKind, RiskMode},
     puppet::{
-        db::{CandlesQuery, CleanUp, Db, FinanclaReportsQuery, ProductQuery},
-        degiro::{Authorize, Degiro, FetchData, GetOrders, GetPortfolio, GetTransactions},
-        portfolio::{CalculatePortfolio, CalculateSl, Calculator, GetSingleAllocation},
+        db::{
+            CandlesQuery, CleanUp, CountEntries, Db, EntryCounts, ExportDb, FinanclaReportsQuery,
+            GetSnapshotHistory, ImportDb, ProductQuery, RemoveAsset, SearchProducts,
+        },
+        degiro::{
+            Authorize, Degiro, FetchData, FetchStatus, GetFetchStatus, GetOrders, GetSessionStatus,
+            GetTransactions, ProductType, SessionStatus, TransactionSide,
+        },
+        portfolio::{
+            CalculatePortfolio, CalculateSl, CalculatedPortfolio, Calculator, DrawdownMetric,
+            ExcludedAsset, GetCorrelations, GetPortfolioTable, GetSingleAllocation,
+            PlaceStopLosses, StopLossMethod,
+        },
+        settings::{GetSettings, Settings},
     },
+    table::{Precision, TableStyle},
 };
 
 #[derive(Debug)]
 pub struct ClientBuilder {
     pub(crate) addr: SocketAddr,
+    pub(crate) table_style: TableStyle,
+    pub(crate) precision: Precision,
 }
 
 #[derive(Debug)]
 pub struct Client {
     pub frame: Framed<TcpStream, LengthDelimitedCodec>,
     pub addr: SocketAddr,
+    pub table_style: TableStyle,
+    pub precision: Precision,
+    next_id: u64,
+    /// Requests sent and still waited for.
+    awaited: HashSet<u64>,
+    /// Responses to awaited requests that arrived while waiting for a different one.
+    pending: HashMap<u64, Option<Response>>,
 }
 
 #[derive(Debug, Clone)]
@@ -66,6 +99,18 @@ impl Lifecycle for Server {
     }
 }
 
+/// A request or response tagged with the id of the request it belongs to. Clients number
+/// requests from 1, keepalives carry id 0.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Envelope<T> {
+    pub id: u64,
+    /// Style of the tables in the response, a response carries the one of its request.
+    pub table_style: TableStyle,
+    /// Decimal places of the numeric cells in the response tables.
+    pub precision: Precision,
+    pub msg: T,
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub enum Request {
     Ping,
@@ -74,9 +119,15 @@ pub enum Request {
     FetchData {
         id: Option<String>,
     },
+    GetFetchStatus,
+    GetStatus,
     GetProduct {
         query: ProductQuery,
     },
+    SearchProducts {
+        query: String,
+        limit: usize,
+    },
     GetFinancials {
         query: ProductQuery,
     },
@@ -86,44 +137,109 @@ pub enum Request {
     GetSingleAllocation {
         query: ProductQuery,
         mode: RiskMode,
+        ret_kind: ReturnKind,
         risk: f64,
         risk_free: f64,
+        period: Period,
+        interval: Period,
     },
     CalculatePortfolio {
         mode: RiskMode,
+        ret_kind: ReturnKind,
         risk: f64,
         risk_free: f64,
+        period: Period,
+        interval: Period,
         freq: usize,
         money: f64,
+        currency: Option<Currency>,
+        as_of: Option<NaiveDate>,
         max_stocks: usize,
         min_rsi: Option<f64>,
         max_rsi: Option<f64>,
         min_dd: Option<f64>,
         max_dd: Option<f64>,
+        dd_metric: DrawdownMetric,
         min_class: Option<degiro_rs::util::ProductCategory>,
         max_class: Option<degiro_rs::util::ProductCategory>,
         short_sales_constraint: bool,
         min_roic: Option<f64>,
         roic_wacc_delta: Option<f64>,
+        shrinkage: Option<f64>,
+        types: Option<HashSet<ProductType>>,
+        mar: Option<f64>,
+        min_percent_b: Option<f64>,
+        max_percent_b: Option<f64>,
+        rsi_cross_level: Option<f64>,
+        rsi_cross_within: usize,
+        max_correlation: Option<f64>,
+        return_mode: AnnualizedReturnMode,
+        benchmark: Option<String>,
     },
     RecalculateSl {
-        n: usize,
+        nstd: f64,
+        method: StopLossMethod,
+        max_percent: Option<f64>,
+        largest_drawdowns: Option<usize>,
+        mar: Option<f64>,
+        freq: usize,
+        risk_free: f64,
+    },
+    PlaceStopLosses {
+        nstd: f64,
+        method: StopLossMethod,
+        freq: usize,
+        max_percent: Option<f64>,
+        largest_drawdowns: Option<usize>,
+        dry_run: bool,
+    },
+    GetCorrelations,
+    GetPortfolio {
+        as_of: Option<NaiveDate>,
     },
-    GetPortfolio,
     GetTransactions {
         from_date: NaiveDate,
         to_date: NaiveDate,
+        product_id: Option<String>,
+        side: Option<TransactionSide>,
     },
     GetOrders,
-    CleanUp,
+    GetSnapshotHistory {
+        from: NaiveDate,
+        to: NaiveDate,
+    },
+    CleanUp {
+        dry_run: bool,
+    },
+    RemoveAsset {
+        id: String,
+        purge_data: bool,
+    },
+    ExportDb {
+        path: PathBuf,
+    },
+    ImportDb {
+        path: PathBuf,
+    },
 }
 
 #[allow(clippy::large_enum_variant)]
 #[derive(Debug, Deserialize, Serialize)]
 pub enum Response {
+    /// Keepalive sent while a request is still being processed.
+    Pong,
     SendProduct {
         product: Option<ProductDetails>,
     },
+    SendFetchStatus {
+        status: Option<FetchStatus>,
+    },
+    SendStatus {
+        status: ServerStatus,
+    },
+    SendSearchProducts {
+        products: Vec<ProductDetails>,
+    },
     SendFinancials {
         financials: Option<FinancialReports>,
     },
@@ -136,19 +252,54 @@ pub enum Response {
     SendPortfolio {
         portfolio: Option<String>,
     },
+    SendCalculatePortfolio {
+        portfolio: Option<String>,
+        error: Option<String>,
+        excluded: Vec<ExcludedAsset>,
+    },
     SendRecalcucatetSl {
         table: Option<String>,
     },
     SendPortfolioSl {
         table: Option<String>,
     },
+    SendPlaceStopLosses {
+        table: Option<String>,
+    },
+    SendCorrelations {
+        table: Option<String>,
+    },
     SendTransactions {
         table: Option<String>,
     },
     SendOrders {
         table: Option<String>,
     },
-    SendCleanUp,
+    SendSnapshotHistory {
+        table: Option<String>,
+    },
+    SendCleanUp {
+        ids: Option<Vec<String>>,
+    },
+    SendRemoveAsset {
+        removed: Option<bool>,
+    },
+    SendExportDb {
+        error: Option<String>,
+    },
+    SendImportDb {
+        error: Option<String>,
+    },
+}
+
+/// Overview of the running server, a part is `None` when its puppet didn't answer in time.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ServerStatus {
+    pub session: Option<SessionStatus>,
+    /// Assets in the settings.
+    pub assets: Option<usize>,
+    pub entries: Option<EntryCounts>,
+    pub fetch: Option<FetchStatus>,
 }
 
 #[derive(Debug, Deserialize, Error, Serialize)]
@@ -207,11 +358,17 @@ impl Handler<RunServer> for Server {
                     break;
                 };
                 let mut frame = Framed::new(socket, LengthDelimitedCodec::new());
-                // TODO:
                 let (res_tx, mut res_rx) =
-                    tokio::sync::mpsc::unbounded_channel::<Option<Response>>();
+                    tokio::sync::mpsc::unbounded_channel::<Envelope<Option<Response>>>();
                 let cloned_puppeter = cloned_puppeter.clone();
                 tokio::spawn(async move {
+                    // Every request runs as its own task, so a slow one doesn't hold up the
+                    // rest of the connection. Dropping the set on disconnect aborts them.
+                    let mut tasks = tokio::task::JoinSet::new();
+                    let mut keepalive = tokio::time::interval_at(
+                        tokio::time::Instant::now() + KEEPALIVE_INTERVAL,
+                        KEEPALIVE_INTERVAL,
+                    );
                     loop {
                         tokio::select! {
                             Some(msg) = res_rx.recv() => {
@@ -222,17 +379,40 @@ impl Handler<RunServer> for Server {
                                     return Err(PuppetError::critical(cloned_puppeter.pid, "Can't send message"))
                                 };
                             }
+                            Some(joined) = tasks.join_next() => {
+                                if let Err(err) = joined {
+                                    error!(error = %err, "Request task failed");
+                                }
+                            }
+                            _ = keepalive.tick(), if !tasks.is_empty() => {
+                                let pong = Envelope { id: 0, table_style: TableStyle::default(), precision: Precision::default(), msg: Some(Response::Pong) };
+                                let Ok(bytes) = bincode::serialize(&pong) else {
+                                    return Err(PuppetError::critical(cloned_puppeter.pid, "Can't serialize message"))
+                                };
+                                if frame.send(bytes.into()).await.is_err() {
+                                    return Err(PuppetError::critical(cloned_puppeter.pid, "Can't send message"))
+                                };
+                            }
                             framed = frame.next() => {
                                 match framed {
                                     Some(Ok(buf)) => {
-                                        let Ok(req) = bincode::deserialize::<Request>(&buf) else {
-                                            return Err(PuppetError::critical(cloned_puppeter.pid, "Can't deserialize message"))
+                                        // A client speaking another protocol version only loses its connection.
+                                        let Ok(Envelope { id, table_style, precision, msg: req }) = bincode::deserialize::<Envelope<Request>>(&buf) else {
+                                            warn!("Can't deserialize message, closing the connection");
+                                            break Ok(());
                                         };
-                                        info!(req =? req, "Received message");
-                                        req.process(&res_tx, &cloned_puppeter).await;
+                                        info!(id, req =? req, "Received message");
+                                        let res_tx = res_tx.clone();
+                                        let puppeter = cloned_puppeter.clone();
+                                        tasks.spawn(async move {
+                                            let msg = req.process(&puppeter, table_style, precision).await;
+                                            // The receiver is only gone once the connection is closed.
+                                            res_tx.send(Envelope { id, table_style, precision, msg }).ok();
+                                        });
                                     }
                                     Some(Err(err)) => {
-                                        dbg!(err);
+                                        warn!(error = %err, "Can't read frame, closing the connection");
+                                        break Ok(());
                                     }
                                     None => break Ok(()),
                                 }
@@ -249,7 +429,21 @@ impl Handler<RunServer> for Server {
 impl ClientBuilder {
     pub fn new(socket: impl Into<SocketAddrV4>) -> Self {
         let addr = socket.into();
-        Self { addr: addr.into() }
+        Self {
+            addr: addr.into(),
+            table_style: TableStyle::default(),
+            precision: Precision::default(),
+        }
+    }
+    #[must_use]
+    pub const fn table_style(mut self, table_style: TableStyle) -> Self {
+        self.table_style = table_style;
+        self
+    }
+    #[must_use]
+    pub const fn precision(mut self, precision: Precision) -> Self {
+        self.precision = precision;
+        self
     }
     pub async fn build(&self) -> Result<Client, tokio::io::Error> {
         let socket = TcpStream::connect(&self.addr).await?;
@@ -257,21 +451,82 @@ impl ClientBuilder {
         Ok(Client {
             frame,
             addr: self.addr,
+            table_style: self.table_style,
+            precision: self.precision,
+            next_id: 0,
+            awaited: HashSet::new(),
+            pending: HashMap::new(),
         })
     }
 }
 impl Client {
-    pub async fn read(&mut self) -> Option<Response> {
-        match tokio::time::timeout(Duration::seconds(60).to_std().unwrap(), self.frame.next()).await
-        {
-            Err(_) | Ok(None) | Ok(Some(Err(_))) => None,
-            Ok(Some(Ok(buf))) => bincode::deserialize::<Option<Response>>(&buf).unwrap(),
+    /// Reads the next response frame, skipping keepalives. Each frame restarts the timeout, so
+    /// a request only times out once the server stops sending anything.
+    pub async fn read(&mut self) -> Option<Envelope<Option<Response>>> {
+        loop {
+            match tokio::time::timeout(Duration::seconds(60).to_std().unwrap(), self.frame.next())
+                .await
+            {
+                Err(_) | Ok(None) | Ok(Some(Err(_))) => return None,
+                Ok(Some(Ok(buf))) => {
+                    match bincode::deserialize::<Envelope<Option<Response>>>(&buf).unwrap() {
+                        Envelope {
+                            id: 0,
+                            msg: Some(Response::Pong),
+                            ..
+                        } => continue,
+                        envelope => return Some(envelope),
+                    }
+                }
+            }
         }
     }
-    pub async fn write(&mut self, req: Request) -> Option<Response> {
-        let bytes = bincode::serialize(&req).unwrap();
+    /// Sends a request without waiting for its response and returns its id.
+    pub async fn send(&mut self, req: Request) -> u64 {
+        self.next_id += 1;
+        let id = self.next_id;
+        let bytes = bincode::serialize(&Envelope {
+            id,
+            table_style: self.table_style,
+            precision: self.precision,
+            msg: req,
+        })
+        .unwrap();
         self.frame.send(bytes.into()).await.unwrap();
-        self.read().await
+        self.awaited.insert(id);
+        id
+    }
+    /// Waits for the response to request `id`, keeping the responses to other awaited requests
+    /// for later. Once it returns `id` is no longer awaited, even when it timed out, so a late
+    /// response to it is dropped instead of kept for good.
+    pub async fn recv(&mut self, id: u64) -> Option<Response> {
+        let res = self.wait(id).await;
+        self.awaited.remove(&id);
+        self.pending.remove(&id);
+        res
+    }
+    async fn wait(&mut self, id: u64) -> Option<Response> {
+        loop {
+            if let Some(res) = self.pending.remove(&id) {
+                return res;
+            }
+            let envelope = self.read().await?;
+            if envelope.id == id {
+                return envelope.msg;
+            }
+            if self.awaited.contains(&envelope.id) {
+                self.pending.insert(envelope.id, envelope.msg);
+            } else {
+                warn!(
+                    id = envelope.id,
+                    "Dropping the response to a request no longer awaited"
+                );
+            }
+        }
+    }
+    pub async fn write(&mut self, req: Request) -> Option<Response> {
+        let id = self.send(req).await;
+        self.recv(id).await
     }
 }
 
@@ -295,15 +550,22 @@ impl Client {
 //     }
 // }
 
+/// Answer of a `GetStatus` ask, `None` when it fails or takes longer than `STATUS_TIMEOUT`.
+async fn status_part<T, E>(ask: impl Future<Output = Result<T, E>>) -> Option<T> {
+    tokio::time::timeout(STATUS_TIMEOUT, ask).await.ok()?.ok()
+}
+
 impl Request {
     pub async fn process(
         self,
-        res_tx: &tokio::sync::mpsc::UnboundedSender<Option<Response>>,
         puppeter: &Puppeter,
-    ) {
+        table_style: TableStyle,
+        precision: Precision,
+    ) -> Option<Response> {
         match self {
-            Self::Ping => todo!(),
-            Self::Pong => todo!(),
+            Self::Ping => Some(Response::Pong),
+            // Only answers a ping, there's nothing to answer.
+            Self::Pong => None,
             Self::Authorize => {
                 puppeter
                     .ask::<Degiro, _>(Authorize)
@@ -311,23 +573,55 @@ impl Request {
                     .unwrap_or_else(|err| {
                         tracing::error!(error = %err, "Failed to authorize");
                     });
-                res_tx.send(None).unwrap();
+                None
             }
             Self::FetchData { id } => {
-                let msg = FetchData { id, name: None };
+                let msg = FetchData {
+                    id,
+                    name: None,
+                    intervals: None,
+                    product: None,
+                };
                 puppeter.send::<Degiro, _>(msg).await.unwrap_or_else(|err| {
                     tracing::error!(error = %err, "Failed to fetch data");
                 });
-                res_tx.send(None).unwrap();
+                None
+            }
+            Self::GetFetchStatus => {
+                let status = puppeter.ask::<Degiro, _>(GetFetchStatus).await.ok();
+                Some(Response::SendFetchStatus { status })
+            }
+            Self::GetStatus => {
+                let (session, settings, entries, fetch) = tokio::join!(
+                    status_part(puppeter.ask::<Degiro, _>(GetSessionStatus)),
+                    status_part(puppeter.ask::<Settings, _>(GetSettings)),
+                    status_part(puppeter.ask::<Db, _>(CountEntries)),
+                    status_part(puppeter.ask::<Degiro, _>(GetFetchStatus)),
+                );
+                let status = ServerStatus {
+                    session,
+                    assets: settings.map(|settings| settings.assets.len()),
+                    entries,
+                    fetch,
+                };
+                Some(Response::SendStatus { status })
             }
             Self::GetProduct { query } => {
                 let product = puppeter.ask::<Db, _>(query).await.unwrap_or_else(|err| {
                     tracing::error!(error = %err, "Failed to get product");
                     None
                 });
-                res_tx
-                    .send(Some(Response::SendProduct { product }))
-                    .unwrap();
+                Some(Response::SendProduct { product })
+            }
+            Self::SearchProducts { query, limit } => {
+                let products = puppeter
+                    .ask::<Db, _>(SearchProducts { query, limit })
+                    .await
+                    .unwrap_or_else(|err| {
+                        tracing::error!(error = %err, "Failed to search products");
+                        Vec::new()
+                    });
+                Some(Response::SendSearchProducts { products })
             }
             Self::GetFinancials { query } => {
                 let financials = puppeter
@@ -337,9 +631,7 @@ impl Request {
                         tracing::error!(error = %err, "Failed to get product");
                         None
                     });
-                res_tx
-                    .send(Some(Response::SendFinancials { financials }))
-                    .unwrap();
+                Some(Response::SendFinancials { financials })
             }
             Self::GetCandles { query } => {
                 let candles = puppeter
@@ -349,21 +641,24 @@ impl Request {
                         tracing::error!(error = %err, "Failed to get product");
                         None
                     });
-                res_tx
-                    .send(Some(Response::SendCandles { candles }))
-                    .unwrap();
+                Some(Response::SendCandles { candles })
             }
             Self::GetSingleAllocation {
                 query,
                 mode,
+                ret_kind,
                 risk,
                 risk_free,
+                period,
+                interval,
             } => {
                 let msg = GetSingleAllocation {
-                    query: query.into(),
+                    query: CandlesQuery::new(query, interval),
                     mode,
+                    ret_kind,
                     risk,
                     risk_free,
+                    period,
                 };
                 let allocation = puppeter
                     .ask::<Calculator, _>(msg)
@@ -372,86 +667,225 @@ impl Request {
                         tracing::error!(error = %err, "Failed to get single allocation");
                         None
                     });
-                res_tx
-                    .send(Some(Response::SendSingleAllocation {
-                        single_allocation: allocation,
-                    }))
-                    .unwrap();
+                Some(Response::SendSingleAllocation {
+                    single_allocation: allocation,
+                })
             }
             Self::CalculatePortfolio {
                 mode,
+                ret_kind,
                 risk,
                 risk_free,
+                period,
+                interval,
                 freq,
                 money,
+                currency,
+                as_of,
                 max_stocks,
                 min_rsi,
                 max_rsi,
                 min_dd,
                 max_dd,
+                dd_metric,
                 min_class,
                 max_class,
                 short_sales_constraint,
                 min_roic,
                 roic_wacc_delta,
+                shrinkage,
+                types,
+                mar,
+                min_percent_b,
+                max_percent_b,
+                rsi_cross_level,
+                rsi_cross_within,
+                max_correlation,
+                return_mode,
+                benchmark,
             } => {
                 let msg = CalculatePortfolio {
                     mode,
+                    ret_kind,
                     risk,
                     risk_free,
+                    period,
+                    interval,
                     freq,
                     money,
+                    currency,
+                    as_of,
                     max_stocks,
                     min_rsi,
                     max_rsi,
                     min_dd,
                     max_dd,
+                    dd_metric,
                     min_class,
                     max_class,
                     short_sales_constraint,
                     min_roic,
                     roic_wacc_delta,
+                    shrinkage,
+                    allowed_types: types,
+                    mar,
+                    min_percent_b,
+                    max_percent_b,
+                    rsi_cross_level,
+                    rsi_cross_within,
+                    max_correlation,
+                    return_mode,
+                    benchmark,
+                    table_style,
+                    precision,
                 };
-                let portfolio = puppeter.ask::<Calculator, _>(msg).await.ok();
-                res_tx
-                    .send(Some(Response::SendPortfolio { portfolio }))
-                    .unwrap();
+                let response = match puppeter.ask::<Calculator, _>(msg).await {
+                    Ok(CalculatedPortfolio {
+                        portfolio: Ok(portfolio),
+                        excluded,
+                    }) => Response::SendCalculatePortfolio {
+                        portfolio: Some(portfolio),
+                        error: None,
+                        excluded,
+                    },
+                    Ok(CalculatedPortfolio {
+                        portfolio: Err(err),
+                        excluded,
+                    }) => Response::SendCalculatePortfolio {
+                        portfolio: None,
+                        error: Some(err.to_string()),
+                        excluded,
+                    },
+                    Err(err) => {
+                        tracing::error!(error = %err, "Failed to calculate portfolio");
+                        Response::SendCalculatePortfolio {
+                            portfolio: None,
+                            error: Some(err.to_string()),
+                            excluded: Vec::new(),
+                        }
+                    }
+                };
+                Some(response)
             }
-            Self::RecalculateSl { n } => {
-                let msg = CalculateSl { n };
+            Self::RecalculateSl {
+                nstd,
+                method,
+                max_percent,
+                largest_drawdowns,
+                mar,
+                freq,
+                risk_free,
+            } => {
+                let msg = CalculateSl {
+                    nstd,
+                    method,
+                    max_percent,
+                    largest_drawdowns,
+                    mar,
+                    freq,
+                    risk_free,
+                    table_style,
+                    precision,
+                };
                 let table = puppeter.ask::<Calculator, _>(msg).await.ok();
-                res_tx
-                    .send(Some(Response::SendRecalcucatetSl { table }))
-                    .unwrap();
+                Some(Response::SendRecalcucatetSl { table })
             }
-            Self::GetPortfolio => {
-                let msg = GetPortfolio;
+            Self::PlaceStopLosses {
+                nstd,
+                method,
+                freq,
+                max_percent,
+                largest_drawdowns,
+                dry_run,
+            } => {
+                let msg = PlaceStopLosses {
+                    nstd,
+                    method,
+                    freq,
+                    max_percent,
+                    largest_drawdowns,
+                    dry_run,
+                    table_style,
+                    precision,
+                };
+                let table = puppeter
+                    .ask::<Calculator, _>(msg)
+                    .await
+                    .map_err(|err| {
+                        tracing::error!(error = %err, "Failed to place stop losses");
+                    })
+                    .ok();
+                Some(Response::SendPlaceStopLosses { table })
+            }
+            Self::GetCorrelations => {
+                let table = puppeter
+                    .ask::<Calculator, _>(GetCorrelations {
+                        table_style,
+                        precision,
+                    })
+                    .await
+                    .map_err(|err| {
+                        tracing::error!(error = %err, "Failed to calculate correlations");
+                    })
+                    .ok();
+                Some(Response::SendCorrelations { table })
+            }
+            Self::GetPortfolio { as_of } => {
+                let msg = GetPortfolioTable {
+                    table_style,
+                    precision,
+                    as_of,
+                };
                 let portfolio = puppeter.ask::<Calculator, _>(msg).await.ok();
-                res_tx
-                    .send(Some(Response::SendPortfolio { portfolio }))
-                    .unwrap();
+                Some(Response::SendPortfolio { portfolio })
             }
-            Self::GetTransactions { from_date, to_date } => {
-                let msg = GetTransactions { from_date, to_date };
+            Self::GetTransactions {
+                from_date,
+                to_date,
+                product_id,
+                side,
+            } => {
+                let msg = GetTransactions {
+                    from_date,
+                    to_date,
+                    product_id,
+                    side,
+                };
                 let transactions = puppeter.ask::<Degiro, _>(msg).await.ok();
                 let mut table = comfy_table::Table::new();
                 let header = vec![
                     comfy_table::Cell::new("id"),
+                    comfy_table::Cell::new("date"),
                     comfy_table::Cell::new("product id"),
                     comfy_table::Cell::new("transaction type"),
                     comfy_table::Cell::new("transaction type id"),
                     comfy_table::Cell::new("order type id"),
                     comfy_table::Cell::new("price")
                         .set_alignment(comfy_table::CellAlignment::Right),
+                    comfy_table::Cell::new("currency"),
                     comfy_table::Cell::new("total")
                         .set_alignment(comfy_table::CellAlignment::Right),
+                    comfy_table::Cell::new("fees").set_alignment(comfy_table::CellAlignment::Right),
+                    comfy_table::Cell::new("fees %")
+                        .set_alignment(comfy_table::CellAlignment::Right),
+                    comfy_table::Cell::new("net total")
+                        .set_alignment(comfy_table::CellAlignment::Right),
                 ];
                 table.set_header(header);
-                table.load_preset(UTF8_BORDERS_ONLY);
                 if let Some(transactions) = transactions {
                     for transaction in transactions.0 {
+                        let fees = transaction.inner.total_fees_in_base_currency;
+                        let net_total = transaction.inner.total_plus_all_fees_in_base_currency;
+                        // Fees as a share of the traded value, both in the base currency.
+                        let traded = (net_total - fees).abs();
+                        let fees_percent = if traded > 0.0 {
+                            precision.ratio(fees.abs() / traded * 100.0)
+                        } else {
+                            String::new()
+                        };
                         table.add_row(vec![
                             comfy_table::Cell::new(transaction.inner.id.to_string()),
+                            comfy_table::Cell::new(transaction.inner.date.to_string()),
                             comfy_table::Cell::new(transaction.inner.product_id.to_string()),
                             comfy_table::Cell::new(transaction.inner.transaction_type.to_string()),
                             comfy_table::Cell::new(
@@ -465,16 +899,55 @@ impl Request {
                             ),
                             comfy_table::Cell::new(transaction.inner.price.to_string())
                                 .set_alignment(comfy_table::CellAlignment::Right),
+                            comfy_table::Cell::new(transaction.inner.currency.to_string()),
                             comfy_table::Cell::new(transaction.inner.total.to_string())
                                 .set_alignment(comfy_table::CellAlignment::Right),
+                            comfy_table::Cell::new(precision.money(fees))
+                                .set_alignment(comfy_table::CellAlignment::Right),
+                            comfy_table::Cell::new(fees_percent)
+                                .set_alignment(comfy_table::CellAlignment::Right),
+                            comfy_table::Cell::new(precision.money(net_total))
+                                .set_alignment(comfy_table::CellAlignment::Right),
                         ]);
                     }
                 }
-                res_tx
-                    .send(Some(Response::SendTransactions {
-                        table: Some(table.to_string()),
-                    }))
-                    .unwrap();
+                Some(Response::SendTransactions {
+                    table: Some(table_style.render(&mut table)),
+                })
+            }
+            Self::GetSnapshotHistory { from, to } => {
+                let msg = GetSnapshotHistory { from, to };
+                let table = match puppeter.ask::<Db, _>(msg).await {
+                    Ok(snapshots) => {
+                        let mut table = comfy_table::Table::new();
+                        table.set_header(vec![
+                            comfy_table::Cell::new("date"),
+                            comfy_table::Cell::new("value")
+                                .set_alignment(comfy_table::CellAlignment::Right),
+                            comfy_table::Cell::new("profit")
+                                .set_alignment(comfy_table::CellAlignment::Right),
+                            comfy_table::Cell::new("positions")
+                                .set_alignment(comfy_table::CellAlignment::Right),
+                        ]);
+                        for snapshot in snapshots {
+                            table.add_row(vec![
+                                comfy_table::Cell::new(snapshot.date.to_string()),
+                                comfy_table::Cell::new(precision.money(snapshot.value))
+                                    .set_alignment(comfy_table::CellAlignment::Right),
+                                comfy_table::Cell::new(precision.money(snapshot.profit))
+                                    .set_alignment(comfy_table::CellAlignment::Right),
+                                comfy_table::Cell::new(snapshot.positions.len())
+                                    .set_alignment(comfy_table::CellAlignment::Right),
+                            ]);
+                        }
+                        Some(table_style.render(&mut table))
+                    }
+                    Err(err) => {
+                        tracing::error!(error = %err, "Failed to get snapshot history");
+                        None
+                    }
+                };
+                Some(Response::SendSnapshotHistory { table })
             }
             Self::GetOrders => {
                 let msg = GetOrders;
@@ -491,7 +964,6 @@ impl Request {
                         .set_alignment(comfy_table::CellAlignment::Right),
                 ];
                 table.set_header(header);
-                table.load_preset(UTF8_BORDERS_ONLY);
                 if let Some(orders) = orders {
                     for order in orders.iter() {
                         table.add_row(vec![
@@ -507,16 +979,49 @@ impl Request {
                         ]);
                     }
                 }
-                res_tx
-                    .send(Some(Response::SendOrders {
-                        table: Some(table.to_string()),
-                    }))
-                    .unwrap();
+                Some(Response::SendOrders {
+                    table: Some(table_style.render(&mut table)),
+                })
             }
-            Self::CleanUp => {
-                let msg = CleanUp;
-                puppeter.send::<Db, _>(msg).await.ok();
-                res_tx.send(Some(Response::SendCleanUp)).unwrap();
+            Self::CleanUp { dry_run } => {
+                let msg = CleanUp { dry_run };
+                let ids = puppeter
+                    .ask::<Db, _>(msg)
+                    .await
+                    .map_err(|err| {
+                        tracing::error!(error = %err, "Failed to clean up");
+                    })
+                    .ok();
+                Some(Response::SendCleanUp { ids })
+            }
+            Self::RemoveAsset { id, purge_data } => {
+                let msg = RemoveAsset { id, purge_data };
+                let removed = puppeter
+                    .ask::<Db, _>(msg)
+                    .await
+                    .map_err(|err| {
+                        tracing::error!(error = %err, "Failed to remove asset");
+                    })
+                    .ok();
+                Some(Response::SendRemoveAsset { removed })
+            }
+            Self::ExportDb { path } => {
+                let msg = ExportDb { path };
+                let error = puppeter
+                    .ask::<Db, _>(msg)
+                    .await
+                    .err()
+                    .map(|err| err.to_string());
+                Some(Response::SendExportDb { error })
+            }
+            Self::ImportDb { path } => {
+                let msg = ImportDb { path };
+                let error = puppeter
+                    .ask::<Db, _>(msg)
+                    .await
+                    .err()
+                    .map(|err| err.to_string());
+                Some(Response::SendImportDb { error })
             }
         }
     }
@@ -0,0 +1,99 @@
+use comfy_table::{
+    presets::{ASCII_BORDERS_ONLY, ASCII_MARKDOWN, UTF8_BORDERS_ONLY},
+    Row, Table,
+};
+use serde::{Deserialize, Serialize};
+use strum::EnumString;
+
+/// How the tables sent back to the CLI are rendered.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, EnumString, Serialize, Deserialize)]
+#[strum(serialize_all = "lowercase", ascii_case_insensitive)]
+pub enum TableStyle {
+    #[default]
+    Utf8,
+    Ascii,
+    Markdown,
+    /// Tab separated values without any borders, for piping into other tools.
+    None,
+}
+
+impl TableStyle {
+    #[must_use]
+    pub fn render(self, table: &mut Table) -> String {
+        let preset = match self {
+            Self::Utf8 => UTF8_BORDERS_ONLY,
+            Self::Ascii => ASCII_BORDERS_ONLY,
+            Self::Markdown => ASCII_MARKDOWN,
+            Self::None => return tab_separated(table),
+        };
+        table.load_preset(preset);
+        table.to_string()
+    }
+}
+
+/// Decimal places of the numeric cells. Money and prices use `money`, allocations, ratios
+/// and other unitless metrics use `ratio`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Precision {
+    pub money: usize,
+    pub ratio: usize,
+}
+
+impl Default for Precision {
+    fn default() -> Self {
+        Self { money: 2, ratio: 2 }
+    }
+}
+
+impl Precision {
+    #[must_use]
+    pub fn money(self, value: f64) -> String {
+        format!("{:.*}", self.money, value)
+    }
+    #[must_use]
+    pub fn ratio(self, value: f64) -> String {
+        format!("{:.*}", self.ratio, value)
+    }
+}
+
+fn tab_separated(table: &Table) -> String {
+    let line = |row: &Row| {
+        row.cell_iter()
+            .map(|cell| cell.content().replace(['\t', '\n'], " "))
+            .collect::<Vec<_>>()
+            .join("\t")
+    };
+    table
+        .header()
+        .into_iter()
+        .chain(table.row_iter())
+        .map(line)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn render_tab_separated() {
+        let mut table = Table::new();
+        table.set_header(vec!["id", "name"]);
+        table.add_row(vec!["1", "foo\tbar"]);
+        assert_eq!(TableStyle::None.render(&mut table), "id\tname\n1\tfoo bar");
+    }
+
+    #[test]
+    fn precision() {
+        let precision = Precision { money: 2, ratio: 4 };
+        assert_eq!(precision.money(1234.5678), "1234.57");
+        assert_eq!(precision.ratio(0.123456), "0.1235");
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!("markdown".parse::<TableStyle>(), Ok(TableStyle::Markdown));
+        assert_eq!("UTF8".parse::<TableStyle>(), Ok(TableStyle::Utf8));
+    }
+}